@@ -11,6 +11,26 @@ if [ $? -ne 0 ]; then
 fi
 
 echo "✅ Build successful!"
+
+# Build the user programs and stage them for the initrd
+USER_BINS="init echo fault strace ipcbench untrace"
+(cd userland && cargo build --release)
+if [ $? -ne 0 ]; then
+    echo "❌ Userland build failed!"
+    exit 1
+fi
+mkdir -p initrd
+for bin in $USER_BINS; do
+    cp userland/target/riscv64gc-unknown-none-elf/release/$bin initrd/
+done
+
+# Pack user programs under initrd/ into a cpio (newc) archive
+QEMU_INITRD=()
+if [ -d initrd ] && [ -n "$(find initrd -type f)" ]; then
+    (cd initrd && find . -type f | cpio --quiet -o -H newc) > target/initrd.cpio
+    QEMU_INITRD=(-initrd target/initrd.cpio -append "init=${INIT:-init}")
+    echo "📦 initrd: $(find initrd -type f | wc -l) file(s)"
+fi
 echo ""
 echo "🚀 Launching woflOS in QEMU..."
 echo "   (Press Ctrl+A then X to quit QEMU)"
@@ -20,8 +40,9 @@ echo ""
 qemu-system-riscv64 \
     -machine virt \
     -cpu rv64 \
-    -smp 1 \
+    -smp ${SMP:-4} \
     -m 128M \
     -nographic \
     -serial mon:stdio \
-    -kernel target/riscv64gc-unknown-none-elf/release/woflos
\ No newline at end of file
+    -kernel target/riscv64gc-unknown-none-elf/release/woflos \
+    "${QEMU_INITRD[@]}"
\ No newline at end of file
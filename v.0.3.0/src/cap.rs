@@ -0,0 +1,88 @@
+//! Per-process capability tables
+//!
+//! A capability is the right to use one kernel service or object. Each
+//! process has a small table of them, addressed by handle (the slot
+//! index). A new process starts with whatever its creator chose to
+//! grant; the handles it received are listed in its auxiliary vector.
+//!
+//! This is the unsigned, in-kernel groundwork for Layer 5: tokens never
+//! leave the kernel, so there is nothing to forge yet.
+
+use crate::ipc::EndpointId;
+
+/// Capability slots per process
+pub const MAX_CAPS: usize = 16;
+
+/// Index into a process's capability table
+pub type Handle = usize;
+
+/// What a capability lets its holder do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Write to the serial console
+    Console,
+    /// Start programs from the initrd
+    Spawn,
+    /// Send and receive on an IPC endpoint
+    Endpoint(EndpointId),
+}
+
+impl Capability {
+    /// Kind code used in the auxiliary vector ABI
+    pub fn kind(&self) -> u32 {
+        match self {
+            Capability::Console => 1,
+            Capability::Spawn => 2,
+            Capability::Endpoint(_) => 3,
+        }
+    }
+
+    /// Object the capability refers to (0 for kernel-wide services)
+    pub fn object(&self) -> u64 {
+        match self {
+            Capability::Endpoint(id) => id.raw(),
+            _ => 0,
+        }
+    }
+}
+
+/// A process's capabilities
+#[derive(Debug, Clone)]
+pub struct CapTable {
+    slots: [Option<Capability>; MAX_CAPS],
+    /// Slots this table may use (see `process::limits`)
+    limit: usize,
+}
+
+impl CapTable {
+    pub const fn new() -> Self {
+        CapTable { slots: [None; MAX_CAPS], limit: MAX_CAPS }
+    }
+
+    /// An empty table holding at most `limit` capabilities
+    pub fn with_limit(limit: usize) -> Self {
+        CapTable { slots: [None; MAX_CAPS], limit: limit.min(MAX_CAPS) }
+    }
+
+    /// Store a capability in the first free slot within the limit
+    pub fn insert(&mut self, cap: Capability) -> Option<Handle> {
+        let handle = self.slots[..self.limit].iter().position(|s| s.is_none())?;
+        self.slots[handle] = Some(cap);
+        Some(handle)
+    }
+
+    /// The capability behind `handle`
+    pub fn get(&self, handle: Handle) -> Option<Capability> {
+        self.slots.get(handle).copied().flatten()
+    }
+
+    /// Does the table hold `cap`?
+    pub fn has(&self, cap: Capability) -> bool {
+        self.slots.contains(&Some(cap))
+    }
+
+    /// Every held capability with its handle
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, Capability)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(h, s)| s.map(|cap| (h, cap)))
+    }
+}
@@ -0,0 +1,211 @@
+//! Console input and its line discipline
+//!
+//! The UART interrupts on every received byte. The line discipline turns
+//! those bytes into input for SYS_READ:
+//!
+//! - cooked mode (the default) collects a line, handling backspace, and
+//!   hands it over whole when Enter is pressed
+//! - raw mode hands over every byte as it arrives
+//!
+//! With echo on (the default) typed characters are written back to the
+//! console. Readers block on a wait queue until there is input.
+
+use crate::process::wait::WaitQueue;
+use crate::sync::{KernelCell, OnceCell};
+
+/// Mode bits (SYS_CONSOLE_MODE)
+pub const MODE_COOKED: usize = 1 << 0;
+pub const MODE_ECHO: usize = 1 << 1;
+pub const MODE_ALL: usize = MODE_COOKED | MODE_ECHO;
+
+const COMPATIBLE: &str = "ns16550a";
+
+/// Bytes of input waiting for a reader
+const INPUT_SIZE: usize = 512;
+
+/// Longest cooked line, newline included
+const LINE_MAX: usize = 256;
+
+struct Tty {
+    mode: usize,
+    /// Input ready for SYS_READ (FIFO)
+    input: [u8; INPUT_SIZE],
+    head: usize,
+    len: usize,
+    /// The cooked line being edited
+    line: [u8; LINE_MAX],
+    line_len: usize,
+    /// Processes blocked in SYS_READ
+    readers: WaitQueue,
+}
+
+impl Tty {
+    const fn new() -> Self {
+        Tty {
+            mode: MODE_COOKED | MODE_ECHO,
+            input: [0; INPUT_SIZE],
+            head: 0,
+            len: 0,
+            line: [0; LINE_MAX],
+            line_len: 0,
+            readers: WaitQueue::new(),
+        }
+    }
+
+    /// Queue bytes for readers, dropping what doesn't fit
+    fn push_input(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().take(INPUT_SIZE - self.len) {
+            self.input[(self.head + self.len) % INPUT_SIZE] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// Hand the line being edited to readers
+    fn commit_line(&mut self) {
+        let line = self.line;
+        self.push_input(&line[..self.line_len]);
+        self.line_len = 0;
+    }
+
+    fn receive(&mut self, byte: u8) {
+        let echo = self.mode & MODE_ECHO != 0;
+
+        if self.mode & MODE_COOKED == 0 {
+            self.push_input(&[byte]);
+            if echo {
+                crate::uart::putc(byte);
+            }
+            return;
+        }
+
+        match byte {
+            b'\r' | b'\n' => {
+                self.line[self.line_len] = b'\n';
+                self.line_len += 1;
+                self.commit_line();
+                if echo {
+                    crate::uart::putc(b'\n');
+                }
+            }
+            // Backspace / delete
+            0x7F | 0x08 => {
+                if self.line_len > 0 {
+                    self.line_len -= 1;
+                    if echo {
+                        crate::uart::write_bytes(b"\x08 \x08");
+                    }
+                }
+            }
+            // Keep room for the newline
+            byte if self.line_len < LINE_MAX - 1 => {
+                self.line[self.line_len] = byte;
+                self.line_len += 1;
+                if echo {
+                    crate::uart::putc(byte);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+static TTY: KernelCell<Tty> = KernelCell::new(Tty::new());
+
+/// The UART's PLIC source
+static UART_IRQ: OnceCell<u32> = OnceCell::new();
+
+/// Start taking console input by interrupt
+///
+/// Call after `plic::init`.
+pub fn init() {
+    let irq = crate::fdt::get()
+        .and_then(|fdt| fdt.find_compatible(COMPATIBLE))
+        .and_then(|node| node.property_u64("interrupts"))
+        .map(|irq| irq as u32);
+
+    let Some(irq) = irq else {
+        crate::kprintln!("[CONSOLE] no UART interrupt - input disabled");
+        return;
+    };
+    let _ = UART_IRQ.set(irq);
+    crate::plic::enable(irq);
+    crate::uart::enable_rx_interrupt();
+    crate::kprintln!("[CONSOLE] input on IRQ {}", irq);
+}
+
+/// PLIC source of console input
+pub fn irq() -> Option<u32> {
+    UART_IRQ.get().copied()
+}
+
+/// Drain the UART through the line discipline and wake readers
+pub fn handle_interrupt() {
+    // SAFETY: interrupts are handled under the kernel lock, and the line
+    // discipline doesn't call back into this module
+    let tty = unsafe { TTY.get() };
+    while let Some(byte) = crate::uart::getc() {
+        tty.receive(byte);
+    }
+    if tty.len > 0 {
+        tty.readers.wake_all();
+    }
+}
+
+/// Copy waiting input into `buf` without consuming it, returning how
+/// many bytes
+///
+/// Cooked mode stops after a newline, so each read returns at most one
+/// line. `None` means there is no input yet.
+pub fn peek(buf: &mut [u8]) -> Option<usize> {
+    // SAFETY: called from syscalls, under the kernel lock; the borrow
+    // ends when we return
+    let tty = unsafe { TTY.get() };
+    if tty.len == 0 {
+        return None;
+    }
+
+    let mut n = 0;
+    while n < buf.len() && n < tty.len {
+        let byte = tty.input[(tty.head + n) % INPUT_SIZE];
+        buf[n] = byte;
+        n += 1;
+        if byte == b'\n' && tty.mode & MODE_COOKED != 0 {
+            break;
+        }
+    }
+    Some(n)
+}
+
+/// Drop the first `n` bytes of input, once a reader has them
+pub fn consume(n: usize) {
+    // SAFETY: as for `peek`
+    let tty = unsafe { TTY.get() };
+    let n = n.min(tty.len);
+    tty.head = (tty.head + n) % INPUT_SIZE;
+    tty.len -= n;
+}
+
+/// Park the current process until input arrives
+pub fn wait_for_input() {
+    // SAFETY: as for `peek`
+    unsafe { TTY.get() }.readers.park_current();
+}
+
+/// Change the mode bits in `mask` to those in `value`, returning the old
+/// mode
+///
+/// Leaving cooked mode hands over the line being edited.
+pub fn set_mode(mask: usize, value: usize) -> usize {
+    // SAFETY: as for `peek`
+    let tty = unsafe { TTY.get() };
+    let old = tty.mode;
+    tty.mode = (old & !mask) | (value & mask & MODE_ALL);
+
+    if old & MODE_COOKED != 0 && tty.mode & MODE_COOKED == 0 {
+        tty.commit_line();
+        if tty.len > 0 {
+            tty.readers.wake_all();
+        }
+    }
+    old
+}
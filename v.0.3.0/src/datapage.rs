@@ -0,0 +1,117 @@
+//! Kernel data page
+//!
+//! Every process has a read-only page at `loader::DATA_PAGE_ADDR`, found
+//! through the `AT_DATA_PAGE` auxv entry, holding things it would
+//! otherwise need a syscall for: its PID, what it takes to turn the
+//! `time` CSR into nanoseconds, which harts are online and the kernel
+//! version. User code reads `time` itself (`scounteren.TM` is set), so
+//! with this page a clock read never traps.
+//!
+//! ```text
+//! 0    version      layout version (DATA_PAGE_VERSION)
+//! 8    seq          odd while the kernel is updating the page
+//! 16   pid, ppid    ppid 0 = no parent
+//! 32   timebase_hz  `time` ticks per second
+//! 40   boot_ticks   `time` at boot
+//! 48   boot_time_ns wall-clock time at boot, since the epoch
+//! 56   flags        DATA_REALTIME: boot_time_ns is valid
+//! 64   harts        number of harts online
+//! 72   hart_mask    bit per online hart
+//! 80   kernel       NUL-padded version string
+//! ```
+//!
+//! Fields that can change after the process starts (only the harts, so
+//! far) are written under `seq`: a reader reads it, the fields, then
+//! `seq` again, and retries if it was odd or moved.
+//!
+//! The page belongs to the address space, so threads share their
+//! leader's and it goes away with the process.
+
+use core::sync::atomic::{AtomicU64, Ordering, fence};
+
+use crate::memory::frame;
+use crate::memory::paging::{AddressSpace, MapError, PTE_R};
+use crate::process::Pid;
+
+/// Layout version; bumped when fields move
+pub const DATA_PAGE_VERSION: u64 = 1;
+
+/// `flags` bits
+pub const DATA_REALTIME: u64 = 1 << 0;
+
+/// Bytes of the kernel version string, NUL-padded
+const KERNEL_VERSION_LEN: usize = 32;
+
+const KERNEL_VERSION: &str = concat!("woflOS ", env!("CARGO_PKG_VERSION"));
+
+const _: () = assert!(KERNEL_VERSION.len() < KERNEL_VERSION_LEN);
+
+#[repr(C)]
+struct DataPage {
+    version: u64,
+    seq: AtomicU64,
+    pid: u64,
+    ppid: u64,
+    timebase_hz: u64,
+    boot_ticks: u64,
+    boot_time_ns: u64,
+    flags: u64,
+    harts: AtomicU64,
+    hart_mask: AtomicU64,
+    kernel: [u8; KERNEL_VERSION_LEN],
+}
+
+const _: () = assert!(core::mem::size_of::<DataPage>() <= crate::memory::PAGE_SIZE);
+
+/// Map a fresh, read-only data page into `space`
+///
+/// It stays zeroed (version 0, which user code treats as absent) until
+/// `init` fills it in.
+pub fn map(space: &mut AddressSpace) -> Result<(), MapError> {
+    let page = frame::alloc_zeroed_frame().ok_or(MapError::OutOfMemory)?;
+    space
+        .map(crate::loader::DATA_PAGE_ADDR, page, PTE_R)
+        .inspect_err(|_| frame::free_frame(page))
+}
+
+/// The data page in `space`, through the kernel's identity map
+fn page(space: &AddressSpace) -> Option<&'static mut DataPage> {
+    let (pa, _) = space.translate(crate::loader::DATA_PAGE_ADDR)?;
+    Some(unsafe { &mut *(pa as *mut DataPage) })
+}
+
+/// Fill in the data page of process `pid`
+pub fn init(space: &AddressSpace, pid: Pid, parent: Option<Pid>) {
+    let Some(data) = page(space) else { return };
+    let boot_time = crate::time::boot_time_ns();
+
+    data.pid = pid as u64;
+    data.ppid = parent.unwrap_or(0) as u64;
+    data.timebase_hz = crate::time::timebase_hz();
+    data.boot_ticks = crate::time::boot_ticks();
+    data.boot_time_ns = boot_time.unwrap_or(0);
+    data.flags = if boot_time.is_some() { DATA_REALTIME } else { 0 };
+    data.kernel = [0; KERNEL_VERSION_LEN];
+    data.kernel[..KERNEL_VERSION.len()].copy_from_slice(KERNEL_VERSION.as_bytes());
+    update_harts(data);
+    fence(Ordering::Release);
+    data.version = DATA_PAGE_VERSION;
+}
+
+fn update_harts(data: &DataPage) {
+    let mask = crate::smp::online_mask();
+    data.seq.fetch_add(1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    data.harts.store(mask.count_ones() as u64, Ordering::Relaxed);
+    data.hart_mask.store(mask as u64, Ordering::Relaxed);
+    data.seq.fetch_add(1, Ordering::Release);
+}
+
+/// A hart came online: update every process' page
+pub fn harts_changed() {
+    for process in crate::process::iter() {
+        if let Some(data) = process.address_space.as_ref().and_then(page) {
+            update_harts(data);
+        }
+    }
+}
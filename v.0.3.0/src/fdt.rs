@@ -0,0 +1,223 @@
+//! Flattened device tree (DTB) parsing
+//!
+//! OpenSBI hands us a pointer to the DTB in a1 at boot. This is a tiny,
+//! read-only walker over the structure block: enough to find a node by
+//! path or `compatible` string and read its properties. All values in
+//! the blob are big-endian.
+
+use crate::sync::OnceCell;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// A validated device tree blob
+#[derive(Clone, Copy)]
+pub struct Fdt {
+    blob: &'static [u8],
+    structs: usize,
+    strings: usize,
+}
+
+/// A node inside the tree
+#[derive(Clone, Copy)]
+pub struct Node {
+    fdt: Fdt,
+    pub name: &'static str,
+    /// Offset of the first token after the node name
+    props: usize,
+}
+
+impl Fdt {
+    /// Validate the header of the DTB at `addr`
+    ///
+    /// # Safety
+    /// `addr` must point at readable memory that stays valid forever.
+    pub unsafe fn from_ptr(addr: usize) -> Option<Self> {
+        if addr == 0 || addr % 8 != 0 {
+            return None;
+        }
+        let header = core::slice::from_raw_parts(addr as *const u8, 40);
+        if be32(header, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let total = be32(header, 4)? as usize;
+        let blob = core::slice::from_raw_parts(addr as *const u8, total);
+
+        Some(Fdt {
+            blob,
+            structs: be32(blob, 8)? as usize,
+            strings: be32(blob, 12)? as usize,
+        })
+    }
+
+    /// Physical address range occupied by the blob
+    pub fn range(&self) -> (usize, usize) {
+        let start = self.blob.as_ptr() as usize;
+        (start, start + self.blob.len())
+    }
+
+    /// Find a node by absolute path, e.g. `/chosen` or `/soc/rtc`
+    ///
+    /// Path components match with or without the `@unit-address` suffix.
+    pub fn find_node(&self, path: &str) -> Option<Node> {
+        let mut want = path.split('/').filter(|c| !c.is_empty());
+        let mut next = want.next();
+        if next.is_none() {
+            return self.nodes().next(); // the root node
+        }
+
+        // How many path components are matched by the current branch
+        let mut matched = 0;
+        for (depth, node) in self.nodes_with_depth() {
+            if depth != 0 && depth <= matched {
+                // Left the matched branch: the path can't continue below it
+                return None;
+            }
+            if depth == matched + 1 && next.is_some_and(|c| name_matches(node.name, c)) {
+                matched += 1;
+                next = want.next();
+                if next.is_none() {
+                    return Some(node);
+                }
+            }
+        }
+        None
+    }
+
+    /// Find the first node whose `compatible` list contains `compat`
+    pub fn find_compatible(&self, compat: &str) -> Option<Node> {
+        self.nodes().find(|node| node.is_compatible(compat))
+    }
+
+    /// Every node in document order
+    pub fn nodes(&self) -> impl Iterator<Item = Node> + '_ {
+        self.nodes_with_depth().map(|(_, node)| node)
+    }
+
+    /// Every node with its depth (root = 0)
+    fn nodes_with_depth(&self) -> impl Iterator<Item = (usize, Node)> + '_ {
+        let mut off = self.structs;
+        let mut depth: isize = -1;
+
+        core::iter::from_fn(move || loop {
+            let token = be32(self.blob, off)?;
+            off += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(self.blob, off)?;
+                    off = align4(off + name.len() + 1);
+                    depth += 1;
+                    return Some((depth as usize, Node { fdt: *self, name, props: off }));
+                }
+                FDT_END_NODE => depth -= 1,
+                FDT_PROP => {
+                    let len = be32(self.blob, off)? as usize;
+                    off = align4(off + 8 + len);
+                }
+                FDT_NOP => {}
+                // FDT_END or garbage
+                _ => return None,
+            }
+        })
+    }
+
+    fn string_at(&self, off: usize) -> Option<&'static str> {
+        cstr(self.blob, self.strings + off)
+    }
+}
+
+impl Node {
+    /// Raw value of property `name`
+    pub fn property(&self, name: &str) -> Option<&'static [u8]> {
+        let blob = self.fdt.blob;
+        let mut off = self.props;
+
+        loop {
+            match be32(blob, off)? {
+                FDT_PROP => {
+                    let len = be32(blob, off + 4)? as usize;
+                    let name_off = be32(blob, off + 8)? as usize;
+                    let value = blob.get(off + 12..off + 12 + len)?;
+                    if self.fdt.string_at(name_off)? == name {
+                        return Some(value);
+                    }
+                    off = align4(off + 12 + len);
+                }
+                FDT_NOP => off += 4,
+                // Properties always come before child nodes
+                _ => return None,
+            }
+        }
+    }
+
+    /// Property as a u32 or u64 cell value
+    pub fn property_u64(&self, name: &str) -> Option<u64> {
+        let value = self.property(name)?;
+        match value.len() {
+            4 => be32(value, 0).map(u64::from),
+            8 => Some((u64::from(be32(value, 0)?) << 32) | u64::from(be32(value, 4)?)),
+            _ => None,
+        }
+    }
+
+    /// Property as a string (without the trailing NUL)
+    pub fn property_str(&self, name: &str) -> Option<&'static str> {
+        let value = self.property(name)?;
+        let value = value.strip_suffix(&[0]).unwrap_or(value);
+        core::str::from_utf8(value).ok()
+    }
+
+    /// First `reg` entry as (address, size)
+    ///
+    /// Assumes #address-cells = #size-cells = 2, as on QEMU virt.
+    pub fn reg(&self) -> Option<(usize, usize)> {
+        let reg = self.property("reg")?;
+        let cell = |i: usize| be32(reg, i * 4).map(|c| c as usize);
+        Some(((cell(0)? << 32) | cell(1)?, (cell(2)? << 32) | cell(3)?))
+    }
+
+    /// Does the `compatible` list contain `compat`?
+    pub fn is_compatible(&self, compat: &str) -> bool {
+        self.property("compatible")
+            .is_some_and(|list| list.split(|&b| b == 0).any(|c| c == compat.as_bytes()))
+    }
+}
+
+/// The boot DTB, once `init` has found it
+static BOOT_FDT: OnceCell<Fdt> = OnceCell::new();
+
+/// Remember the DTB passed in by the firmware
+///
+/// # Safety
+/// Must be called once at boot, with the pointer OpenSBI passed in a1.
+pub unsafe fn init(addr: usize) -> Option<Fdt> {
+    BOOT_FDT.set(Fdt::from_ptr(addr)?).ok().copied()
+}
+
+/// The boot DTB, if the firmware gave us a valid one
+pub fn get() -> Option<Fdt> {
+    BOOT_FDT.get().copied()
+}
+
+fn name_matches(node: &str, component: &str) -> bool {
+    node == component || node.split('@').next() == Some(component)
+}
+
+fn be32(data: &[u8], off: usize) -> Option<u32> {
+    let bytes = data.get(off..off + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn cstr(data: &'static [u8], off: usize) -> Option<&'static str> {
+    let rest = data.get(off..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&rest[..len]).ok()
+}
+
+fn align4(off: usize) -> usize {
+    (off + 3) & !3
+}
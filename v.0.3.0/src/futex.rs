@@ -0,0 +1,92 @@
+//! Futexes: user-space locks that sleep in the kernel
+//!
+//! A futex is any aligned 32-bit word in user memory. Waiters are keyed
+//! by the word's *physical* address, so threads sharing an address space
+//! and processes sharing a frame meet on the same key. Keys hash into a
+//! fixed set of wait queues; waking only takes the waiters whose key
+//! matches.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::memory::paging::PTE_R;
+use crate::process::wait::WaitQueue;
+use crate::process;
+use crate::sync::KernelCell;
+
+/// Wait queues for all futexes, shared by hash
+const BUCKETS: usize = 64;
+
+static QUEUES: KernelCell<[WaitQueue; BUCKETS]> = KernelCell::new([const { WaitQueue::new() }; BUCKETS]);
+
+/// SYS_FUTEX_WAIT results (in a0)
+pub const WOKEN: usize = 0;
+pub const VALUE_CHANGED: usize = 1;
+pub const TIMED_OUT: usize = 2;
+
+/// Sleep on `uaddr` if it still holds `expected`
+///
+/// `deadline` is a `time` CSR value, `None` to wait forever. Returns the
+/// result for the caller's a0: `VALUE_CHANGED` if it didn't sleep,
+/// otherwise the provisional result a waker will overwrite with `WOKEN`.
+/// `None` means an unaligned or unmapped address.
+pub fn wait(uaddr: usize, expected: u32, deadline: Option<u64>) -> Option<usize> {
+    let key = key(uaddr)?;
+
+    // Other harts may change the word meanwhile, but every `wake` runs
+    // under the kernel lock we hold from this load until we're parked, so
+    // checking and parking is atomic with respect to wakers
+    let word = unsafe { &*(key as *const AtomicU32) };
+    if word.load(Ordering::SeqCst) != expected {
+        return Some(VALUE_CHANGED);
+    }
+
+    let current = process::current_process()?;
+    current.futex_key = key;
+
+    let queue = bucket(key);
+    match deadline {
+        Some(deadline) => {
+            crate::timer::park_current_until(queue, deadline);
+            Some(TIMED_OUT)
+        }
+        None => {
+            queue.park_current();
+            Some(WOKEN)
+        }
+    }
+}
+
+/// Wake up to `count` processes sleeping on `uaddr`
+///
+/// Returns how many were woken, `None` for a bad address.
+pub fn wake(uaddr: usize, count: usize) -> Option<usize> {
+    let key = key(uaddr)?;
+
+    Some(bucket(key).wake_matching(count, |p| {
+        if p.futex_key != key {
+            return false;
+        }
+        p.futex_key = 0;
+        p.context.a0 = WOKEN;
+        true
+    }))
+}
+
+/// Physical address of the futex word at `uaddr` in the current process
+fn key(uaddr: usize) -> Option<usize> {
+    if uaddr == 0 || uaddr % 4 != 0 {
+        return None;
+    }
+    let leader = process::current_leader()?;
+    match &leader.address_space {
+        Some(space) => space.translate(uaddr).filter(|&(_, flags)| flags & PTE_R != 0).map(|(pa, _)| pa),
+        // Untranslated processes use physical addresses directly
+        None => Some(uaddr),
+    }
+}
+
+fn bucket(key: usize) -> &'static mut WaitQueue {
+    // SAFETY: futex calls run under the kernel lock and use one bucket
+    // at a time
+    unsafe { &mut QUEUES.get()[(key >> 2) % BUCKETS] }
+}
@@ -0,0 +1,126 @@
+//! Initial ramdisk: a cpio archive of user programs
+//!
+//! QEMU loads the archive given with `-initrd` into RAM and records where
+//! in the DTB `/chosen` node (`linux,initrd-start` / `linux,initrd-end`).
+//! The archive uses the "newc" format (`cpio -o -H newc`); `build.sh`
+//! packs everything under `initrd/` into one.
+//!
+//! Each entry is a 110-byte ASCII header, the NUL-terminated file name
+//! and the file data, with name and data each padded to 4 bytes. The
+//! archive ends with an entry named `TRAILER!!!`.
+
+use crate::sync::OnceCell;
+
+const NEWC_MAGIC: &[u8] = b"070701";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// Regular file bit pattern in `c_mode`
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+/// A file inside the initrd
+#[derive(Clone, Copy)]
+pub struct File {
+    pub name: &'static str,
+    pub data: &'static [u8],
+}
+
+/// A cpio newc archive in memory
+#[derive(Clone, Copy)]
+pub struct Initrd {
+    archive: &'static [u8],
+}
+
+impl Initrd {
+    /// Wrap an archive, checking that it starts like a newc cpio
+    pub fn new(archive: &'static [u8]) -> Option<Self> {
+        archive.starts_with(NEWC_MAGIC).then_some(Initrd { archive })
+    }
+
+    /// Physical address range occupied by the archive
+    pub fn range(&self) -> (usize, usize) {
+        let start = self.archive.as_ptr() as usize;
+        (start, start + self.archive.len())
+    }
+
+    /// All regular files in archive order
+    ///
+    /// Iteration stops at the trailer or at the first malformed header.
+    pub fn files(&self) -> impl Iterator<Item = File> {
+        let archive = self.archive;
+        let mut off = 0;
+
+        core::iter::from_fn(move || loop {
+            let header = archive.get(off..off + HEADER_SIZE)?;
+            if !header.starts_with(NEWC_MAGIC) {
+                return None;
+            }
+
+            let mode = hex_field(header, 1)?;
+            let file_size = hex_field(header, 6)? as usize;
+            let name_size = hex_field(header, 11)? as usize;
+
+            let name_start = off + HEADER_SIZE;
+            let name = archive.get(name_start..name_start + name_size)?;
+            let name = core::str::from_utf8(name.strip_suffix(&[0])?).ok()?;
+
+            let data_start = align4(name_start + name_size);
+            let data = archive.get(data_start..data_start + file_size)?;
+            off = align4(data_start + file_size);
+
+            if name == TRAILER {
+                return None;
+            }
+            if mode & S_IFMT == S_IFREG {
+                return Some(File { name: normalize(name), data });
+            }
+        })
+    }
+
+    /// Look up a file by name (leading `./` or `/` ignored)
+    pub fn find(&self, name: &str) -> Option<File> {
+        let name = normalize(name);
+        self.files().find(|f| f.name == name)
+    }
+}
+
+/// The ramdisk QEMU loaded for us, if any
+static BOOT_INITRD: OnceCell<Initrd> = OnceCell::new();
+
+/// Locate the initrd through the DTB `/chosen` node
+///
+/// # Safety
+/// Must be called once at boot, after `fdt::init`.
+pub unsafe fn init() -> Option<Initrd> {
+    let chosen = crate::fdt::get()?.find_node("/chosen")?;
+    let start = chosen.property_u64("linux,initrd-start")? as usize;
+    let end = chosen.property_u64("linux,initrd-end")? as usize;
+    if end <= start {
+        return None;
+    }
+
+    let archive = core::slice::from_raw_parts(start as *const u8, end - start);
+    BOOT_INITRD.set(Initrd::new(archive)?).ok().copied()
+}
+
+/// The boot initrd, if one was found
+pub fn get() -> Option<Initrd> {
+    BOOT_INITRD.get().copied()
+}
+
+/// Parse the `index`-th 8-digit hex field after the magic
+fn hex_field(header: &[u8], index: usize) -> Option<u32> {
+    let start = NEWC_MAGIC.len() + index * 8;
+    let digits = core::str::from_utf8(header.get(start..start + 8)?).ok()?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+fn normalize(name: &str) -> &str {
+    let name = name.strip_prefix("./").unwrap_or(name);
+    name.trim_start_matches('/')
+}
+
+fn align4(off: usize) -> usize {
+    (off + 3) & !3
+}
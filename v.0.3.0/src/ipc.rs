@@ -0,0 +1,550 @@
+//! Synchronous IPC endpoints
+//!
+//! An endpoint is a rendezvous point with no buffering: a sender blocks
+//! until a receiver takes its message, a receiver until a message
+//! arrives, and either side that finds the other already waiting
+//! completes the exchange on the spot. The message buffer is copied
+//! twice, from the sender's address space into the kernel's `BOUNCE`
+//! buffer and from there into the receiver's.
+//!
+//! A message is `MSG_WORDS` registers plus an optional buffer of up to
+//! `MAX_MSG_LEN` bytes. The receiver also learns who sent it, the
+//! sender's PID and thread ID, from the kernel rather than the message.
+//! A parked party gets its results in its saved registers, the way a
+//! futex waiter does.
+//!
+//! Request/response goes faster with SYS_CALL, a send that goes on to
+//! wait for the answer in the same trap. Receiving a call leaves the
+//! receiving thread holding a one-shot reply object, and
+//! SYS_REPLY_RECV answers through it and waits for the next message,
+//! again in one trap, so a server loop costs one trap per request. A
+//! reply object that is replaced, or whose holder exits, fails its
+//! caller with `Pipe`.
+//!
+//! A send, receive or call submitted through a ring (see `ring`) doesn't
+//! block its thread. If nobody is there to meet it, the request waits on
+//! the endpoint in a slot of its own, in line with any blocked threads,
+//! and completes through the ring when the other side turns up; a call
+//! holds on to its slot until the answer comes.
+//!
+//! Endpoints live in a fixed table and are reached through capabilities.
+//! Each belongs to the process that created it and closes when that
+//! process exits: everyone blocked on it fails with `Pipe`. A slot's
+//! generation changes whenever it is freed, so a capability to a closed
+//! endpoint never reaches the next one in its slot. The owner's
+//! `ipc_depth` limit caps how many senders may queue on its endpoints.
+
+use crate::memory::user::{copy_from_user, copy_to_user};
+use crate::process::limits::LimitError;
+use crate::process::wait::WaitQueue;
+use crate::process::{self, scheduler, Pid, ProcessState};
+use crate::sync::KernelCell;
+use crate::syscall::Errno;
+
+/// Endpoints that can exist at once
+pub const MAX_ENDPOINTS: usize = 32;
+
+/// Registers a message carries
+pub const MSG_WORDS: usize = 3;
+
+/// Longest message buffer
+pub const MAX_MSG_LEN: usize = 1024;
+
+/// Ring requests that can wait on endpoints at once
+const MAX_PENDING: usize = 64;
+
+/// Names one endpoint for as long as it lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndpointId {
+    slot: u32,
+    generation: u32,
+}
+
+impl EndpointId {
+    /// Object code used in the auxiliary vector ABI
+    pub fn raw(&self) -> u64 {
+        (self.generation as u64) << 32 | self.slot as u64
+    }
+}
+
+struct Endpoint {
+    /// Process the endpoint belongs to; `None` for a free slot
+    owner: Option<Pid>,
+    generation: u32,
+    /// Threads blocked in SYS_SEND (or SYS_CALL), and in SYS_RECV
+    senders: WaitQueue,
+    receivers: WaitQueue,
+    /// Callers whose message was received, waiting for the answer
+    callers: WaitQueue,
+}
+
+static ENDPOINTS: KernelCell<[Endpoint; MAX_ENDPOINTS]> = KernelCell::new(
+    [const {
+        Endpoint {
+            owner: None,
+            generation: 0,
+            senders: WaitQueue::new(),
+            receivers: WaitQueue::new(),
+            callers: WaitQueue::new(),
+        }
+    }; MAX_ENDPOINTS],
+);
+
+/// Where messages pass through on their way between address spaces
+///
+/// One message at a time: it is copied in and out again within a single
+/// call, under the kernel lock.
+static BOUNCE: KernelCell<[u8; MAX_MSG_LEN]> = KernelCell::new([0; MAX_MSG_LEN]);
+
+/// A message as its sender described it: registers plus a buffer in the
+/// sender's address space
+#[derive(Debug, Clone, Copy)]
+pub struct Message {
+    pub words: [usize; MSG_WORDS],
+    pub buf: usize,
+    pub len: usize,
+}
+
+/// What a thread blocked on an endpoint is waiting to do
+///
+/// `ticket` is its place in line, among threads and ring requests alike.
+#[derive(Debug, Clone, Copy)]
+pub enum IpcWait {
+    /// SYS_SEND, or the first half of SYS_CALL (`reply` = room for the
+    /// answer in `msg.buf`)
+    Send { msg: Message, reply: Option<usize>, ticket: u64 },
+    Recv { buf: usize, capacity: usize, ticket: u64 },
+    /// The second half of SYS_CALL: waiting for answer `token`
+    Reply { buf: usize, capacity: usize, token: u64 },
+}
+
+impl IpcWait {
+    fn ticket(&self) -> u64 {
+        match *self {
+            IpcWait::Send { ticket, .. } | IpcWait::Recv { ticket, .. } => ticket,
+            IpcWait::Reply { token, .. } => token,
+        }
+    }
+}
+
+/// How the current thread made its request
+#[derive(Debug, Clone, Copy)]
+pub enum Via {
+    /// As a syscall: the thread blocks until it's done
+    Trap,
+    /// Through its ring, with this `user_data`: the request completes
+    /// there instead
+    Ring(u64),
+}
+
+/// A ring request waiting on an endpoint, the way a blocked thread would
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    endpoint: EndpointId,
+    /// Process whose ring gets the completion, and the thread that
+    /// submitted the request
+    tgid: Pid,
+    thread: Pid,
+    user_data: u64,
+    wait: IpcWait,
+}
+
+static PENDING: KernelCell<[Option<Pending>; MAX_PENDING]> = KernelCell::new([None; MAX_PENDING]);
+
+fn pending() -> &'static mut [Option<Pending>; MAX_PENDING] {
+    // SAFETY: IPC runs under the kernel lock, and each use is over
+    // before the next `pending()`
+    unsafe { PENDING.get() }
+}
+
+/// Somebody waiting on an endpoint
+#[derive(Debug, Clone, Copy)]
+enum Waiter {
+    /// A thread, blocked on one of the endpoint's queues
+    Thread(Pid),
+    /// A ring request, in this slot of `PENDING`
+    Ring(usize),
+}
+
+impl Waiter {
+    fn wait(self) -> Option<IpcWait> {
+        match self {
+            Waiter::Thread(pid) => process::get(pid)?.ipc,
+            Waiter::Ring(slot) => pending()[slot].map(|request| request.wait),
+        }
+    }
+
+    /// The thread that made the request, and its process
+    fn ids(self) -> Option<(Pid, Pid)> {
+        match self {
+            Waiter::Thread(pid) => process::get(pid).map(|thread| (pid, thread.tgid)),
+            Waiter::Ring(slot) => pending()[slot].map(|request| (request.thread, request.tgid)),
+        }
+    }
+
+    /// Take a thread off `queue`; a ring request stays in its slot
+    fn unqueue(self, queue: &mut WaitQueue) {
+        if let Waiter::Thread(pid) = self {
+            queue.remove(pid);
+        }
+    }
+
+    /// Finish the request, returning `registers` from a0 on: into a
+    /// thread's saved registers, or posted to the ring
+    fn finish(self, registers: &[usize]) {
+        match self {
+            Waiter::Thread(pid) => {
+                let Some(thread) = process::get(pid) else { return };
+                thread.ipc = None;
+                let context = &mut thread.context;
+                let saved = [
+                    &mut context.a0,
+                    &mut context.a1,
+                    &mut context.a2,
+                    &mut context.a3,
+                    &mut context.a4,
+                    &mut context.a5,
+                ];
+                for (reg, &value) in saved.into_iter().zip(registers) {
+                    *reg = value;
+                }
+                scheduler::make_ready(pid);
+            }
+            Waiter::Ring(slot) => {
+                let Some(request) = pending()[slot].take() else { return };
+                let mut completion = [0; 6];
+                completion[..registers.len()].copy_from_slice(registers);
+                if let Some(ring) = process::get(request.tgid).and_then(|leader| leader.ring.as_mut()) {
+                    ring.complete(request.user_data, completion);
+                }
+            }
+        }
+    }
+}
+
+/// The right to answer one call, held by the thread that received it
+#[derive(Debug, Clone, Copy)]
+pub struct Reply {
+    /// Calling thread and its process
+    caller: Pid,
+    tgid: Pid,
+    endpoint: EndpointId,
+    /// Tells this call from any later one by the same caller
+    token: u64,
+}
+
+/// Source of reply tokens and tickets
+static NEXT_TOKEN: KernelCell<u64> = KernelCell::new(0);
+
+fn next_token() -> u64 {
+    // SAFETY: IPC runs under the kernel lock
+    let token = unsafe { NEXT_TOKEN.get() };
+    *token += 1;
+    *token
+}
+
+/// A message as handed to its receiver
+#[derive(Debug, Clone, Copy)]
+pub struct Received {
+    /// Length of the sender's buffer; more than the receiver's capacity
+    /// means it was cut short
+    pub len: usize,
+    /// Sending process and thread
+    pub sender: Pid,
+    pub thread: Pid,
+    pub words: [usize; MSG_WORDS],
+}
+
+impl Received {
+    /// The receiver's a0-a5: length, sender, thread, then the words
+    pub fn registers(&self) -> [usize; 3 + MSG_WORDS] {
+        let [w0, w1, w2] = self.words;
+        [self.len, self.sender, self.thread, w0, w1, w2]
+    }
+}
+
+/// Create an endpoint belonging to process `owner`
+pub fn create(owner: Pid) -> Option<EndpointId> {
+    // SAFETY: IPC runs under the kernel lock; see `lookup`
+    let endpoints = unsafe { ENDPOINTS.get() };
+    let (slot, endpoint) = endpoints.iter_mut().enumerate().find(|(_, e)| e.owner.is_none())?;
+    endpoint.owner = Some(owner);
+    Some(EndpointId { slot: slot as u32, generation: endpoint.generation })
+}
+
+/// Close endpoint `id`, failing everybody waiting on it with `Pipe`
+pub fn close(id: EndpointId) {
+    let Some(endpoint) = lookup(id) else { return };
+    endpoint.owner = None;
+    endpoint.generation = endpoint.generation.wrapping_add(1);
+    for queue in [&mut endpoint.senders, &mut endpoint.receivers, &mut endpoint.callers] {
+        queue.wake_matching(usize::MAX, |p| {
+            p.ipc = None;
+            p.context.a0 = Errno::Pipe.to_user();
+            true
+        });
+    }
+    for slot in 0..MAX_PENDING {
+        if pending()[slot].is_some_and(|request| request.endpoint == id) {
+            Waiter::Ring(slot).finish(&[Errno::Pipe.to_user()]);
+        }
+    }
+}
+
+/// Process `tgid` is exiting: close its endpoints, fail the calls its
+/// threads were to answer and forget its ring requests
+pub fn release(tgid: Pid) {
+    for slot in 0..MAX_ENDPOINTS {
+        // SAFETY: see `lookup`; the borrow ends before `close` looks the
+        // endpoint up again
+        let endpoint = unsafe { &ENDPOINTS.get()[slot] };
+        let (owner, generation) = (endpoint.owner, endpoint.generation);
+        if owner == Some(tgid) {
+            close(EndpointId { slot: slot as u32, generation });
+        }
+    }
+    for thread in process::iter().filter(|p| p.tgid == tgid) {
+        // Its own threads are going away with it
+        let outside = |reply: &Reply| reply.tgid != tgid;
+        if let Some(reply) = thread.reply.take().filter(outside) {
+            drop_reply(reply);
+        }
+    }
+    for request in pending().iter_mut().filter(|request| request.is_some_and(|r| r.tgid == tgid)) {
+        *request = None;
+    }
+}
+
+fn lookup(id: EndpointId) -> Option<&'static mut Endpoint> {
+    // SAFETY: IPC runs under the kernel lock, and each operation works
+    // on the one endpoint it looked up
+    let endpoint = unsafe { ENDPOINTS.get() }.get_mut(id.slot as usize)?;
+    (endpoint.owner.is_some() && endpoint.generation == id.generation).then_some(endpoint)
+}
+
+/// Copy `data` to `buf` in process `tgid`; false if it isn't mapped
+/// writable there
+fn write_to(tgid: Pid, buf: usize, data: &[u8]) -> bool {
+    process::get(tgid)
+        .and_then(|leader| leader.address_space.as_mut())
+        .is_some_and(|space| space.write(buf, data).is_ok())
+}
+
+/// Fill `data` from `buf` in process `tgid`; false if it isn't mapped
+/// readable there
+fn read_from(tgid: Pid, buf: usize, data: &mut [u8]) -> bool {
+    process::get(tgid)
+        .and_then(|leader| leader.address_space.as_ref())
+        .is_some_and(|space| space.read(buf, data).is_ok())
+}
+
+/// Whoever has waited longest on endpoint `id` to do what `select`
+/// accepts: the first thread on `queue`, or a ring request
+fn oldest(id: EndpointId, queue: &WaitQueue, select: fn(&IpcWait) -> bool) -> Option<Waiter> {
+    // A thread no longer waiting goes first, to be cleared out
+    let thread = queue.front().map(|pid| {
+        let ticket = process::get(pid).and_then(|p| p.ipc).map_or(0, |wait| wait.ticket());
+        (ticket, Waiter::Thread(pid))
+    });
+    let requests = pending().iter().enumerate().filter_map(|(slot, request)| {
+        request.filter(|r| r.endpoint == id && select(&r.wait)).map(|r| (r.wait.ticket(), Waiter::Ring(slot)))
+    });
+    thread.into_iter().chain(requests).min_by_key(|&(ticket, _)| ticket).map(|(_, waiter)| waiter)
+}
+
+/// Leave the current thread's request waiting on endpoint `id`
+///
+/// A syscall parks the thread on `queue` and returns `Ok` for now; a
+/// ring request takes a slot in `PENDING` and returns `Pending`, or
+/// `Again` if there is none.
+fn park(id: EndpointId, queue: &mut WaitQueue, via: Via, wait: IpcWait) -> Result<(), Errno> {
+    match via {
+        Via::Trap => {
+            process::current_process().ok_or(Errno::Srch)?.ipc = Some(wait);
+            queue.park_current();
+            Ok(())
+        }
+        Via::Ring(user_data) => claim(id, user_data, wait).and(Err(Errno::Pending)),
+    }
+}
+
+/// Take a slot in `PENDING` for the current thread's ring request
+fn claim(id: EndpointId, user_data: u64, wait: IpcWait) -> Result<usize, Errno> {
+    let current = process::current_process().ok_or(Errno::Srch)?;
+    let slot = pending().iter().position(Option::is_none).ok_or(Errno::Again)?;
+    pending()[slot] = Some(Pending { endpoint: id, tgid: current.tgid, thread: current.pid, user_data, wait });
+    Ok(slot)
+}
+
+/// Send `msg` on endpoint `id` from the current thread
+///
+/// Hands it to the longest waiting receiver, or leaves the request
+/// waiting (see `park`) until one comes. With `reply` (SYS_CALL) the
+/// caller then waits for the answer, at most `reply` bytes into
+/// `msg.buf`, and the receiver gets the reply object. A plain send ends
+/// up returning 0 once the message is delivered.
+pub fn send(id: EndpointId, msg: Message, reply: Option<usize>, via: Via) -> Result<(), Errno> {
+    // SAFETY: see `BOUNCE`
+    let data = unsafe { BOUNCE.get() }.get_mut(..msg.len).ok_or(Errno::TooBig)?;
+    copy_from_user(data, msg.buf)?;
+    let current = process::current_process().ok_or(Errno::Srch)?;
+    let (thread, sender) = (current.pid, current.tgid);
+    // A ring call needs a slot to wait for the answer in before the
+    // message goes
+    if reply.is_some() && matches!(via, Via::Ring(_)) && pending().iter().all(Option::is_some) {
+        return Err(Errno::Again);
+    }
+
+    let endpoint = lookup(id).ok_or(Errno::Pipe)?;
+    while let Some(receiver) = oldest(id, &endpoint.receivers, |wait| matches!(wait, IpcWait::Recv { .. })) {
+        receiver.unqueue(&mut endpoint.receivers);
+        let (Some(IpcWait::Recv { buf, capacity, .. }), Some((receiver_thread, receiver_tgid))) =
+            (receiver.wait(), receiver.ids())
+        else {
+            continue;
+        };
+
+        // A receiver whose buffer went bad fails; the next one may do
+        if !write_to(receiver_tgid, buf, &data[..data.len().min(capacity)]) {
+            receiver.finish(&[Errno::Fault.to_user()]);
+            continue;
+        }
+        receiver.finish(&Received { len: msg.len, sender, thread, words: msg.words }.registers());
+        let Some(capacity) = reply else { return Ok(()) };
+        // `await_reply` has a ring call's slot wait for the answer
+        let caller = match via {
+            Via::Trap => Waiter::Thread(thread),
+            Via::Ring(user_data) => Waiter::Ring(claim(id, user_data, IpcWait::Send { msg, reply, ticket: 0 })?),
+        };
+        await_reply(endpoint, id, caller, msg.buf, capacity, receiver_thread);
+        return match caller {
+            Waiter::Thread(_) => Ok(()),
+            Waiter::Ring(_) => Err(Errno::Pending),
+        };
+    }
+
+    let depth = endpoint.owner.and_then(process::get).map_or(0, |owner| owner.limits.ipc_depth);
+    let queued = pending().iter().flatten().filter(|r| r.endpoint == id && matches!(r.wait, IpcWait::Send { .. }));
+    if endpoint.senders.len() + queued.count() >= depth {
+        return Err(LimitError::IpcDepth.into());
+    }
+    park(id, &mut endpoint.senders, via, IpcWait::Send { msg, reply, ticket: next_token() })
+}
+
+/// Receive a message on endpoint `id` into `buf` in the current thread
+///
+/// Takes one from the longest waiting sender, or leaves the request
+/// waiting (see `park`; `None` for a syscall) until one comes. Bytes
+/// past `capacity` are dropped. A message sent by SYS_CALL leaves the
+/// caller holding its reply object.
+pub fn recv(id: EndpointId, buf: usize, capacity: usize, via: Via) -> Result<Option<Received>, Errno> {
+    let endpoint = lookup(id).ok_or(Errno::Pipe)?;
+    let current = process::current_process().ok_or(Errno::Srch)?.pid;
+    while let Some(sender) = oldest(id, &endpoint.senders, |wait| matches!(wait, IpcWait::Send { .. })) {
+        let (Some(IpcWait::Send { msg, reply, .. }), Some((thread, sender_tgid))) = (sender.wait(), sender.ids())
+        else {
+            sender.unqueue(&mut endpoint.senders);
+            continue;
+        };
+
+        // A sender whose buffer went bad fails; the next one may do
+        // SAFETY: see `BOUNCE`
+        let data = &mut unsafe { BOUNCE.get() }[..msg.len];
+        let read = read_from(sender_tgid, msg.buf, data);
+        if read {
+            // Our own buffer being bad leaves the sender waiting
+            copy_to_user(buf, &data[..msg.len.min(capacity)])?;
+        }
+
+        sender.unqueue(&mut endpoint.senders);
+        match reply {
+            Some(reply_capacity) if read => await_reply(endpoint, id, sender, msg.buf, reply_capacity, current),
+            _ => sender.finish(&[if read { 0 } else { Errno::Fault.to_user() }]),
+        }
+        if read {
+            return Ok(Some(Received { len: msg.len, sender: sender_tgid, thread, words: msg.words }));
+        }
+    }
+
+    park(id, &mut endpoint.receivers, via, IpcWait::Recv { buf, capacity, ticket: next_token() }).map(|()| None)
+}
+
+/// `caller`'s message just reached thread `receiver`: leave the caller
+/// waiting for the answer (a thread parked, a ring request in its slot)
+/// and give `receiver` the reply object
+///
+/// A receiver still holding an unanswered reply object drops it. One
+/// that has exited since its ring request was made drops the new one.
+fn await_reply(endpoint: &mut Endpoint, id: EndpointId, caller: Waiter, buf: usize, capacity: usize, receiver: Pid) {
+    let Some((thread, tgid)) = caller.ids() else { return };
+    let token = next_token();
+    let wait = IpcWait::Reply { buf, capacity, token };
+    match caller {
+        Waiter::Thread(pid) => {
+            if let Some(process) = process::get(pid) {
+                process.ipc = Some(wait);
+            }
+            endpoint.callers.park(pid);
+        }
+        Waiter::Ring(slot) => {
+            if let Some(request) = &mut pending()[slot] {
+                request.wait = wait;
+            }
+        }
+    }
+
+    let reply = Reply { caller: thread, tgid, endpoint: id, token };
+    match process::get(receiver).filter(|p| p.state != ProcessState::Dead) {
+        Some(receiver) => {
+            if let Some(old) = receiver.reply.replace(reply) {
+                drop_reply(old);
+            }
+        }
+        None => drop_reply(reply),
+    }
+}
+
+/// Who `reply` answers, if they are still waiting for that answer: the
+/// calling thread, or its ring request
+fn waiting_caller(reply: &Reply) -> Option<Waiter> {
+    let answers = |wait: IpcWait| matches!(wait, IpcWait::Reply { token, .. } if token == reply.token);
+    if process::get(reply.caller).is_some_and(|p| p.state == ProcessState::Blocked && p.ipc.is_some_and(answers)) {
+        return Some(Waiter::Thread(reply.caller));
+    }
+    pending().iter().position(|request| request.is_some_and(|r| answers(r.wait))).map(Waiter::Ring)
+}
+
+/// Answer a call with `msg` from the current thread
+///
+/// A caller that has gone away, or was failed in the meantime, is
+/// silently skipped. Fails, leaving the call unanswered, only if `msg`
+/// can't be read.
+pub fn reply(reply: Reply, msg: Message) -> Result<(), Errno> {
+    // SAFETY: see `BOUNCE`
+    let data = unsafe { BOUNCE.get() }.get_mut(..msg.len).ok_or(Errno::TooBig)?;
+    copy_from_user(data, msg.buf)?;
+    let current = process::current_process().ok_or(Errno::Srch)?;
+    let (thread, sender) = (current.pid, current.tgid);
+
+    let Some(caller) = waiting_caller(&reply) else { return Ok(()) };
+    let Some(IpcWait::Reply { buf, capacity, .. }) = caller.wait() else { return Ok(()) };
+    let written = write_to(reply.tgid, buf, &data[..data.len().min(capacity)]);
+
+    if let Some(endpoint) = lookup(reply.endpoint) {
+        caller.unqueue(&mut endpoint.callers);
+    }
+    match written {
+        true => caller.finish(&Received { len: msg.len, sender, thread, words: msg.words }.registers()),
+        false => caller.finish(&[Errno::Fault.to_user()]),
+    }
+    Ok(())
+}
+
+/// Fail the call `reply` would have answered with `Pipe`
+pub fn drop_reply(reply: Reply) {
+    let Some(caller) = waiting_caller(&reply) else { return };
+    if let Some(endpoint) = lookup(reply.endpoint) {
+        caller.unqueue(&mut endpoint.callers);
+    }
+    caller.finish(&[Errno::Pipe.to_user()]);
+}
@@ -0,0 +1,220 @@
+//! Initial process stack: argc, argv, environment and auxiliary vector
+//!
+//! A new program starts with `sp` pointing at this block, laid out at the
+//! top of its user stack (addresses increase downwards in the picture):
+//!
+//! ```text
+//! sp ──► argc
+//!        argv[0] .. argv[argc-1], 0
+//!        envp[0] .. envp[envc-1], 0
+//!        auxv: (type, value) pairs, ended by (AT_NULL, 0)
+//!        capability info: AT_CAPS_COUNT × { handle: u32, kind: u32, object: u64 }
+//!        argument and environment strings (NUL-terminated)
+//!        ... padding
+//!        USER_STACK_TOP
+//! ```
+//!
+//! Every word is 8 bytes, `sp` is 16-byte aligned and all pointers are
+//! user virtual addresses. Environment strings are `KEY=value`.
+
+use crate::cap::{CapTable, MAX_CAPS};
+use crate::memory::paging::AddressSpace;
+use crate::memory::{PAGE_SIZE, frame};
+use crate::process::Pid;
+
+/// Bytes available for the whole block
+pub const ARG_MAX: usize = PAGE_SIZE;
+
+/// Most arguments / environment strings a program can be given
+pub const MAX_ARGS: usize = 32;
+pub const MAX_ENV: usize = 32;
+
+/// Auxiliary vector types (Linux numbering where one exists)
+pub const AT_NULL: usize = 0;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_ENTRY: usize = 9;
+/// woflOS-specific entries
+pub const AT_PID: usize = 0x100;
+pub const AT_PPID: usize = 0x101; // 0 = no parent
+pub const AT_BOOT_TICKS: usize = 0x102; // `time` CSR value at boot
+pub const AT_TIMEBASE_FREQ: usize = 0x103; // `time` CSR ticks per second
+pub const AT_CAPS: usize = 0x104; // pointer to the capability info array
+pub const AT_CAPS_COUNT: usize = 0x105;
+pub const AT_DATA_PAGE: usize = 0x106; // address of the kernel data page
+
+/// Auxiliary vector entries we emit, including AT_NULL
+const AUX_ENTRIES: usize = 10;
+
+/// Size of one capability info record
+const CAP_INFO_SIZE: usize = 16;
+
+/// Why an argument couldn't be added
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgsError {
+    TooMany,
+    TooLarge,
+    BadString,
+}
+
+/// Arguments and environment for a program about to be spawned
+///
+/// Strings are collected at the top of a scratch frame, which mirrors the
+/// top page of the new stack, so laying out the block is a single copy.
+#[derive(Debug)]
+pub struct Args {
+    page: usize,
+    /// String bytes used at the top of the page
+    used: usize,
+    /// String offsets, measured down from the top of the page
+    argv: [usize; MAX_ARGS],
+    argc: usize,
+    envp: [usize; MAX_ENV],
+    envc: usize,
+}
+
+impl Args {
+    /// An empty argument list (`None` if no frame is free)
+    pub fn new() -> Option<Self> {
+        Some(Args {
+            page: frame::alloc_zeroed_frame()?,
+            used: 0,
+            argv: [0; MAX_ARGS],
+            argc: 0,
+            envp: [0; MAX_ENV],
+            envc: 0,
+        })
+    }
+
+    /// Append an argument
+    pub fn push_arg(&mut self, arg: &str) -> Result<(), ArgsError> {
+        self.push_arg_with(arg.len(), |buf| {
+            buf.copy_from_slice(arg.as_bytes());
+            Ok(())
+        })
+    }
+
+    /// Append a `KEY=value` environment string
+    pub fn push_env(&mut self, var: &str) -> Result<(), ArgsError> {
+        self.push_env_with(var.len(), |buf| {
+            buf.copy_from_slice(var.as_bytes());
+            Ok(())
+        })
+    }
+
+    /// Append a `len`-byte argument produced by `fill`
+    pub fn push_arg_with(&mut self, len: usize, fill: impl FnOnce(&mut [u8]) -> Result<(), ArgsError>) -> Result<(), ArgsError> {
+        if self.argc == MAX_ARGS {
+            return Err(ArgsError::TooMany);
+        }
+        let off = self.push_string(len, self.argc + 1, self.envc, fill)?;
+        self.argv[self.argc] = off;
+        self.argc += 1;
+        Ok(())
+    }
+
+    /// Append a `len`-byte environment string produced by `fill`
+    pub fn push_env_with(&mut self, len: usize, fill: impl FnOnce(&mut [u8]) -> Result<(), ArgsError>) -> Result<(), ArgsError> {
+        if self.envc == MAX_ENV {
+            return Err(ArgsError::TooMany);
+        }
+        let off = self.push_string(len, self.argc, self.envc + 1, fill)?;
+        self.envp[self.envc] = off;
+        self.envc += 1;
+        Ok(())
+    }
+
+    /// Reserve room for a string, let `fill` write it and NUL-terminate it
+    fn push_string(
+        &mut self,
+        len: usize,
+        argc: usize,
+        envc: usize,
+        fill: impl FnOnce(&mut [u8]) -> Result<(), ArgsError>,
+    ) -> Result<usize, ArgsError> {
+        let used = len
+            .checked_add(1 + self.used)
+            .filter(|&used| used + block_overhead(argc, envc) <= ARG_MAX)
+            .ok_or(ArgsError::TooLarge)?;
+
+        let off = used;
+        let buf = unsafe { core::slice::from_raw_parts_mut((self.page + PAGE_SIZE - off) as *mut u8, len + 1) };
+        fill(&mut buf[..len])?;
+        if buf[..len].contains(&0) {
+            return Err(ArgsError::BadString);
+        }
+        buf[len] = 0;
+
+        self.used = used;
+        Ok(off)
+    }
+
+    /// Lay the block out at the top of the stack in `space`
+    ///
+    /// Returns the initial stack pointer. `stack_top` must be page-aligned
+    /// with its top page mapped writable.
+    pub fn write_to(&self, space: &mut AddressSpace, stack_top: usize, pid: Pid, parent: Option<Pid>, entry: usize, caps: &CapTable) -> usize {
+        let page = unsafe { core::slice::from_raw_parts_mut(self.page as *mut u8, PAGE_SIZE) };
+        let base = stack_top - PAGE_SIZE;
+        let user = |off: usize| base + PAGE_SIZE - off; // string offset → address
+
+        // Capability info records sit just below the strings
+        let ncaps = caps.iter().count();
+        let caps_off = (PAGE_SIZE - self.used - ncaps * CAP_INFO_SIZE) & !(CAP_INFO_SIZE - 1);
+        for (i, (handle, cap)) in caps.iter().enumerate() {
+            let rec = caps_off + i * CAP_INFO_SIZE;
+            page[rec..rec + 4].copy_from_slice(&(handle as u32).to_le_bytes());
+            page[rec + 4..rec + 8].copy_from_slice(&cap.kind().to_le_bytes());
+            page[rec + 8..rec + 16].copy_from_slice(&cap.object().to_le_bytes());
+        }
+
+        let aux = [
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_ENTRY, entry),
+            (AT_PID, pid),
+            (AT_PPID, parent.unwrap_or(0)),
+            (AT_BOOT_TICKS, crate::time::boot_ticks() as usize),
+            (AT_TIMEBASE_FREQ, crate::time::timebase_hz() as usize),
+            (AT_CAPS, base + caps_off),
+            (AT_CAPS_COUNT, ncaps),
+            (AT_DATA_PAGE, super::DATA_PAGE_ADDR),
+            (AT_NULL, 0),
+        ];
+
+        // argc, argv, envp and auxv, 16-byte aligned at sp
+        let words = 1 + (self.argc + 1) + (self.envc + 1) + 2 * aux.len();
+        let sp_off = (caps_off - words * 8) & !15;
+        let mut off = sp_off;
+        let mut put = |value: usize| {
+            page[off..off + 8].copy_from_slice(&value.to_le_bytes());
+            off += 8;
+        };
+
+        put(self.argc);
+        self.argv[..self.argc].iter().for_each(|&s| put(user(s)));
+        put(0);
+        self.envp[..self.envc].iter().for_each(|&s| put(user(s)));
+        put(0);
+        for (kind, value) in aux {
+            put(kind);
+            put(value);
+        }
+
+        space
+            .write(base + sp_off, &page[sp_off..])
+            .expect("top of user stack is mapped writable");
+        base + sp_off
+    }
+}
+
+impl Drop for Args {
+    fn drop(&mut self) {
+        frame::free_frame(self.page);
+    }
+}
+
+/// Worst-case bytes needed besides the strings themselves
+fn block_overhead(argc: usize, envc: usize) -> usize {
+    let vectors = 8 * (1 + (argc + 1) + (envc + 1)) + 16 * AUX_ENTRIES;
+    // Plus alignment padding for the capability records and sp
+    vectors + CAP_INFO_SIZE * MAX_CAPS + CAP_INFO_SIZE + 16
+}
@@ -0,0 +1,189 @@
+//! ELF64 header parsing and validation
+//!
+//! Only what the loader needs: statically linked, little-endian RISC-V
+//! executables. Everything is read with bounds-checked little-endian
+//! accessors so a truncated or hostile image can't make us read past the
+//! end of the buffer.
+
+use core::fmt;
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 243;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+/// Program header types
+pub const PT_LOAD: u32 = 1;
+
+/// Program header flags
+pub const PF_X: u32 = 1 << 0;
+pub const PF_W: u32 = 1 << 1;
+pub const PF_R: u32 = 1 << 2;
+
+/// Why an ELF image was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    Truncated,
+    BadMagic,
+    Not64Bit,
+    NotLittleEndian,
+    BadVersion,
+    NotExecutable,
+    WrongMachine(u16),
+    BadProgramHeaders,
+    SegmentOutOfFile { index: usize },
+    SegmentTooSmall { index: usize },
+    SegmentNotInUserSpace { index: usize, vaddr: u64 },
+    SegmentMisaligned { index: usize },
+    SegmentOverlap { index: usize },
+    NoPermissions { index: usize },
+    WritableAndExecutable { index: usize },
+    NoLoadableSegments,
+    BadEntryPoint(u64),
+    OutOfMemory,
+    /// The program needs more frames than its limit allows
+    FrameLimit,
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ElfError::Truncated => write!(f, "image is truncated"),
+            ElfError::BadMagic => write!(f, "not an ELF file (bad magic)"),
+            ElfError::Not64Bit => write!(f, "not a 64-bit ELF"),
+            ElfError::NotLittleEndian => write!(f, "not little-endian"),
+            ElfError::BadVersion => write!(f, "unsupported ELF version"),
+            ElfError::NotExecutable => write!(f, "not a static executable (ET_EXEC)"),
+            ElfError::WrongMachine(m) => write!(f, "wrong machine {} (expected RISC-V)", m),
+            ElfError::BadProgramHeaders => write!(f, "malformed program header table"),
+            ElfError::SegmentOutOfFile { index } => write!(f, "segment {} extends past end of file", index),
+            ElfError::SegmentTooSmall { index } => write!(f, "segment {} has filesz > memsz", index),
+            ElfError::SegmentNotInUserSpace { index, vaddr } => {
+                write!(f, "segment {} at {:#x} is outside user space", index, vaddr)
+            }
+            ElfError::SegmentMisaligned { index } => write!(f, "segment {} offset/vaddr not congruent mod page size", index),
+            ElfError::SegmentOverlap { index } => write!(f, "segment {} overlaps an earlier segment", index),
+            ElfError::NoPermissions { index } => write!(f, "segment {} is neither readable, writable nor executable", index),
+            ElfError::WritableAndExecutable { index } => write!(f, "segment {} is both writable and executable", index),
+            ElfError::NoLoadableSegments => write!(f, "no PT_LOAD segments"),
+            ElfError::BadEntryPoint(e) => write!(f, "entry point {:#x} is not in an executable segment", e),
+            ElfError::OutOfMemory => write!(f, "out of memory"),
+            ElfError::FrameLimit => write!(f, "memory limit exceeded"),
+        }
+    }
+}
+
+/// The parts of the ELF header the loader cares about
+#[derive(Debug, Clone, Copy)]
+pub struct ElfHeader {
+    pub entry: u64,
+    phoff: usize,
+    phnum: usize,
+}
+
+/// One program header
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+}
+
+/// A validated ELF image borrowed from memory
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    pub header: ElfHeader,
+}
+
+impl<'a> ElfFile<'a> {
+    /// Validate the ELF header and program header table
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.len() < EHDR_SIZE {
+            return Err(ElfError::Truncated);
+        }
+        if data[0..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if data[4] != ELFCLASS64 {
+            return Err(ElfError::Not64Bit);
+        }
+        if data[5] != ELFDATA2LSB {
+            return Err(ElfError::NotLittleEndian);
+        }
+        if data[6] != EV_CURRENT || read_u32(data, 20)? != EV_CURRENT as u32 {
+            return Err(ElfError::BadVersion);
+        }
+        if read_u16(data, 16)? != ET_EXEC {
+            return Err(ElfError::NotExecutable);
+        }
+        let machine = read_u16(data, 18)?;
+        if machine != EM_RISCV {
+            return Err(ElfError::WrongMachine(machine));
+        }
+
+        let entry = read_u64(data, 24)?;
+        let phoff = read_u64(data, 32)? as usize;
+        let phentsize = read_u16(data, 54)? as usize;
+        let phnum = read_u16(data, 56)? as usize;
+
+        if phentsize != PHDR_SIZE {
+            return Err(ElfError::BadProgramHeaders);
+        }
+        let table_end = phnum
+            .checked_mul(PHDR_SIZE)
+            .and_then(|len| phoff.checked_add(len))
+            .ok_or(ElfError::BadProgramHeaders)?;
+        if table_end > data.len() {
+            return Err(ElfError::BadProgramHeaders);
+        }
+
+        Ok(ElfFile { data, header: ElfHeader { entry, phoff, phnum } })
+    }
+
+    /// Iterate over the program headers
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + '_ {
+        (0..self.header.phnum).filter_map(move |i| {
+            let base = self.header.phoff + i * PHDR_SIZE;
+            Some(ProgramHeader {
+                kind: read_u32(self.data, base).ok()?,
+                flags: read_u32(self.data, base + 4).ok()?,
+                offset: read_u64(self.data, base + 8).ok()?,
+                vaddr: read_u64(self.data, base + 16).ok()?,
+                filesz: read_u64(self.data, base + 32).ok()?,
+                memsz: read_u64(self.data, base + 40).ok()?,
+            })
+        })
+    }
+
+    /// File contents of a segment (bounds-checked)
+    pub fn segment_data(&self, index: usize, ph: &ProgramHeader) -> Result<&'a [u8], ElfError> {
+        let start = ph.offset as usize;
+        let end = start
+            .checked_add(ph.filesz as usize)
+            .ok_or(ElfError::SegmentOutOfFile { index })?;
+        self.data.get(start..end).ok_or(ElfError::SegmentOutOfFile { index })
+    }
+}
+
+fn read_u16(data: &[u8], off: usize) -> Result<u16, ElfError> {
+    let bytes = data.get(off..off + 2).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], off: usize) -> Result<u32, ElfError> {
+    let bytes = data.get(off..off + 4).ok_or(ElfError::Truncated)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], off: usize) -> Result<u64, ElfError> {
+    let bytes = data.get(off..off + 8).ok_or(ElfError::Truncated)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
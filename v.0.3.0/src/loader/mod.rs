@@ -0,0 +1,268 @@
+//! User program loader
+//!
+//! Turns an ELF64 RISC-V executable into a ready-to-run process: every
+//! PT_LOAD segment gets fresh zeroed frames mapped with the segment's
+//! permissions (so .bss is zero without extra work), the file contents
+//! are copied in, and a user stack is mapped just below `USER_TOP` with
+//! an unmapped guard page above it. The top of the stack holds the
+//! program's arguments, environment and auxiliary vector (see `args`).
+
+pub mod args;
+pub mod elf;
+
+use args::Args;
+use elf::{ElfError, ElfFile, PF_R, PF_W, PF_X, PT_LOAD, ProgramHeader};
+
+use crate::cap::CapTable;
+use crate::memory::paging::{AddressSpace, MapError, PTE_R, PTE_W, PTE_X, USER_BASE, USER_TOP};
+use crate::memory::{PAGE_SIZE, align_down, align_up, frame};
+use crate::process::limits::Limits;
+use crate::process::supervisor::Respawn;
+use crate::process::{self, Pid, Process};
+
+/// Pages of user stack
+pub const USER_STACK_PAGES: usize = 16;
+
+/// Top of the user stack (one guard page below the end of user space)
+pub const USER_STACK_TOP: usize = USER_TOP - PAGE_SIZE;
+
+/// Lowest address of the user stack
+pub const USER_STACK_BOTTOM: usize = USER_STACK_TOP - USER_STACK_PAGES * PAGE_SIZE;
+
+/// Pages of stack for each additional thread
+pub const THREAD_STACK_PAGES: usize = 16;
+
+/// Thread stack slots below the main stack, each under its own guard page
+pub const THREAD_STACK_SLOTS: usize = 15;
+
+/// Lowest address of the thread stack area
+pub const THREAD_STACKS_BOTTOM: usize = USER_STACK_BOTTOM - THREAD_STACK_SLOTS * (THREAD_STACK_PAGES + 1) * PAGE_SIZE;
+
+/// Where a process' submission/completion ring is mapped (see `ring`),
+/// with a guard page on either side
+pub const RING_ADDR: usize = THREAD_STACKS_BOTTOM - 2 * PAGE_SIZE;
+
+/// Where the read-only kernel data page is mapped (see `datapage`),
+/// under the ring's guard page with one of its own below
+pub const DATA_PAGE_ADDR: usize = RING_ADDR - 2 * PAGE_SIZE;
+
+/// Program segments end below this
+pub const USER_IMAGE_TOP: usize = DATA_PAGE_ADDR - PAGE_SIZE;
+
+/// A program loaded into a fresh address space
+pub struct LoadedImage {
+    pub space: AddressSpace,
+    pub entry: usize,
+    pub stack_top: usize,
+}
+
+/// Load an ELF image into a new address space owning at most
+/// `frame_limit` frames
+pub fn load(image: &[u8], frame_limit: usize) -> Result<LoadedImage, ElfError> {
+    let elf = ElfFile::parse(image)?;
+    let mut space = AddressSpace::new().ok_or(ElfError::OutOfMemory)?;
+    space.set_frame_limit(frame_limit);
+
+    let mut loaded_any = false;
+    let mut entry_ok = false;
+    let entry = elf.header.entry;
+
+    for (index, ph) in elf.program_headers().enumerate() {
+        if ph.kind != PT_LOAD {
+            continue;
+        }
+        check_segment(&elf, index, &ph)?;
+        load_segment(&mut space, elf.segment_data(index, &ph)?, index, &ph)?;
+
+        loaded_any = true;
+        if ph.flags & PF_X != 0 && (ph.vaddr..ph.vaddr + ph.memsz).contains(&entry) {
+            entry_ok = true;
+        }
+    }
+
+    if !loaded_any {
+        return Err(ElfError::NoLoadableSegments);
+    }
+    if !entry_ok || entry % 2 != 0 {
+        return Err(ElfError::BadEntryPoint(entry));
+    }
+
+    map_stack(&mut space)?;
+    crate::datapage::map(&mut space).map_err(map_error)?;
+
+    Ok(LoadedImage { space, entry: entry as usize, stack_top: USER_STACK_TOP })
+}
+
+/// Load an ELF image and start it as a new process
+///
+/// The process gets `args` on its stack, starts out holding `caps` and
+/// is bound by `limits`. It keeps all of them so a supervisor can
+/// restart it.
+pub fn spawn(
+    name: &'static str,
+    image: &'static [u8],
+    parent: Option<Pid>,
+    args: Args,
+    caps: CapTable,
+    limits: Limits,
+) -> Result<Pid, ElfError> {
+    let mut loaded = load(image, limits.frames)?;
+
+    // If the table is full the image is dropped here, freeing its frames
+    process::insert(move |pid| {
+        crate::datapage::init(&loaded.space, pid, parent);
+        let sp = args.write_to(&mut loaded.space, loaded.stack_top, pid, parent, loaded.entry, &caps);
+        let mut process = Process::new(pid, name, loaded.entry, sp);
+        process.parent = parent;
+        process.address_space = Some(loaded.space);
+        process.caps = caps.clone();
+        process.limits = limits;
+        process.respawn = Some(Respawn { name, image, args, caps, limits });
+        process
+    })
+    .ok_or(ElfError::OutOfMemory)
+}
+
+/// Reject segments we can't (or won't) map
+fn check_segment(elf: &ElfFile, index: usize, ph: &ProgramHeader) -> Result<(), ElfError> {
+    if ph.filesz > ph.memsz {
+        return Err(ElfError::SegmentTooSmall { index });
+    }
+    if ph.memsz == 0 {
+        return Ok(());
+    }
+
+    let end = ph.vaddr.checked_add(ph.memsz);
+    let in_user = ph.vaddr >= USER_BASE as u64 && end.is_some_and(|end| end <= USER_IMAGE_TOP as u64);
+    if !in_user {
+        return Err(ElfError::SegmentNotInUserSpace { index, vaddr: ph.vaddr });
+    }
+    if ph.vaddr % PAGE_SIZE as u64 != ph.offset % PAGE_SIZE as u64 {
+        return Err(ElfError::SegmentMisaligned { index });
+    }
+    if ph.flags & (PF_R | PF_W | PF_X) == 0 {
+        return Err(ElfError::NoPermissions { index });
+    }
+    if ph.flags & PF_W != 0 && ph.flags & PF_X != 0 {
+        return Err(ElfError::WritableAndExecutable { index });
+    }
+
+    // Segments may share a page, but never bytes
+    for earlier in elf.program_headers().take(index).filter(|p| p.kind == PT_LOAD && p.memsz > 0) {
+        if ph.vaddr < earlier.vaddr + earlier.memsz && earlier.vaddr < ph.vaddr + ph.memsz {
+            return Err(ElfError::SegmentOverlap { index });
+        }
+    }
+
+    Ok(())
+}
+
+/// Map a segment's pages and copy its file contents in
+fn load_segment(space: &mut AddressSpace, data: &[u8], index: usize, ph: &ProgramHeader) -> Result<(), ElfError> {
+    let flags = pte_flags(ph.flags);
+    let start = ph.vaddr as usize;
+    let end = start + ph.memsz as usize;
+
+    let mut page = align_down(start);
+    while page < align_up(end) {
+        let frame = match space.translate(page) {
+            // Page shared with the previous segment: widen its permissions
+            Some((frame, existing)) => {
+                if (existing | flags) & (PTE_W | PTE_X) == (PTE_W | PTE_X) {
+                    return Err(ElfError::WritableAndExecutable { index });
+                }
+                space.add_flags(page, flags).map_err(map_error)?;
+                frame
+            }
+            None => {
+                let frame = frame::alloc_zeroed_frame().ok_or(ElfError::OutOfMemory)?;
+                if let Err(e) = space.map(page, frame, flags) {
+                    frame::free_frame(frame);
+                    return Err(map_error(e));
+                }
+                frame
+            }
+        };
+
+        // Copy the part of the file image that lands on this page; the
+        // rest (.bss) stays zero
+        let copy_start = page.max(start);
+        let copy_end = (page + PAGE_SIZE).min(start + data.len());
+        if copy_start < copy_end {
+            let src = &data[copy_start - start..copy_end - start];
+            let dst = (frame + (copy_start - page)) as *mut u8;
+            unsafe { core::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) };
+        }
+
+        page += PAGE_SIZE;
+    }
+
+    Ok(())
+}
+
+/// Map the user stack below the guard page
+fn map_stack(space: &mut AddressSpace) -> Result<(), ElfError> {
+    for page in (USER_STACK_BOTTOM..USER_STACK_TOP).step_by(PAGE_SIZE) {
+        let frame = frame::alloc_zeroed_frame().ok_or(ElfError::OutOfMemory)?;
+        if let Err(e) = space.map(page, frame, PTE_R | PTE_W) {
+            frame::free_frame(frame);
+            return Err(map_error(e));
+        }
+    }
+    Ok(())
+}
+
+/// Top of the stack in thread stack slot `slot`
+pub fn thread_stack_top(slot: usize) -> usize {
+    USER_STACK_BOTTOM - PAGE_SIZE - slot * (THREAD_STACK_PAGES + 1) * PAGE_SIZE
+}
+
+/// Map a fresh stack in thread stack slot `slot`, returning its top
+pub fn map_thread_stack(space: &mut AddressSpace, slot: usize) -> Result<usize, ElfError> {
+    let top = thread_stack_top(slot);
+    for page in (top - THREAD_STACK_PAGES * PAGE_SIZE..top).step_by(PAGE_SIZE) {
+        let mapped = frame::alloc_zeroed_frame()
+            .ok_or(MapError::OutOfMemory)
+            .and_then(|frame| space.map(page, frame, PTE_R | PTE_W).inspect_err(|_| frame::free_frame(frame)));
+        if let Err(e) = mapped {
+            unmap_thread_stack(space, slot);
+            return Err(map_error(e));
+        }
+    }
+    Ok(top)
+}
+
+/// Unmap and free the stack in thread stack slot `slot`
+pub fn unmap_thread_stack(space: &mut AddressSpace, slot: usize) {
+    let top = thread_stack_top(slot);
+    for page in (top - THREAD_STACK_PAGES * PAGE_SIZE..top).step_by(PAGE_SIZE) {
+        if let Some(frame) = space.unmap(page) {
+            frame::free_frame(frame);
+        }
+    }
+}
+
+fn pte_flags(flags: u32) -> usize {
+    let mut pte = 0;
+    if flags & PF_R != 0 {
+        pte |= PTE_R;
+    }
+    if flags & PF_W != 0 {
+        // Sv39 has no write-only pages
+        pte |= PTE_R | PTE_W;
+    }
+    if flags & PF_X != 0 {
+        pte |= PTE_X;
+    }
+    pte
+}
+
+fn map_error(e: MapError) -> ElfError {
+    match e {
+        MapError::OutOfMemory => ElfError::OutOfMemory,
+        MapError::LimitExceeded => ElfError::FrameLimit,
+        MapError::NotUserAddress | MapError::AlreadyMapped | MapError::NotMapped | MapError::PermissionDenied => {
+            ElfError::BadProgramHeaders
+        }
+    }
+}
@@ -8,8 +8,25 @@ use core::arch::asm;
 use core::panic::PanicInfo;
 
 mod uart;
+mod cap;
+mod console;
+mod datapage;
+mod fdt;
+mod futex;
+mod initrd;
+mod ipc;
+mod loader;
 mod memory;
+mod plic;
+mod process;
+mod ring;
+mod rtc;
+mod sbi;
+mod smp;
+mod sync;
 mod syscall;
+mod time;
+mod timer;
 mod trap;
 mod user_test;
 
@@ -21,10 +38,15 @@ use uart::Uart;
 /// - UART online
 /// - .bss cleared
 /// - memory subsystem initialized (frame + heap)
+/// - DTB and initrd located and fenced off from the frame allocator
 /// - jump to `kernel_main()`
+///
+/// OpenSBI passes the hart ID in a0 and the DTB address in a1. Only this
+/// hart runs until `kernel_main()` starts the others.
 #[link_section = ".text.boot"]
 #[no_mangle]
-pub extern "C" fn _start() -> ! {
+pub extern "C" fn _start(hart_id: usize, dtb: usize) -> ! {
+    unsafe { smp::init_hart(hart_id) };
     let uart = Uart::new(0x1000_0000);
     uart.puts("[BOOT] kernel_main entered\n");
 
@@ -49,6 +71,19 @@ pub extern "C" fn _start() -> ! {
     unsafe { memory::init(kernel_end, memory_end) };
     uart.puts("[BOOT] memory initialized\n");
 
+    // Firmware-provided data lives in RAM we now manage: fence it off
+    if let Some(fdt) = unsafe { fdt::init(dtb) } {
+        let (start, end) = fdt.range();
+        memory::frame::reserve(start, end);
+        uart.puts("[BOOT] device tree found\n");
+    }
+    if let Some(initrd) = unsafe { initrd::init() } {
+        let (start, end) = initrd.range();
+        memory::frame::reserve(start, end);
+        uart.puts("[BOOT] initrd found\n");
+    }
+    time::init();
+
     // Continue with the real kernel
     kernel_main_inner()
 }
@@ -63,20 +98,105 @@ fn kernel_main_inner() -> ! {
     crate::kprintln!("============================================");
     crate::kprintln!("[OK] woflOS v0.4.0 (Layer 1 bring-up)");
 
+    // Held whenever this hart runs kernel code; the other harts queue up
+    // for it once they are started
+    smp::lock_kernel();
+
     // Layer 1: install trap vector + enable minimal trap handling
     trap::init();
+    timer::init();
+    plic::init();
+    console::init();
+
+    // Layer 3: process table + scheduler
+    process::init();
+
+    // Background kernel work
+    if process::kthread::spawn("kzerod", memory::frame::zero_pool_daemon).is_none() {
+        panic!("failed to spawn kzerod");
+    }
+
+    // Layer 1: launch user programs from the initrd, falling back to the
+    // built-in test program when there is none
+    if !launch_initrd_programs() {
+        let user_entry = user_test::user_main as usize;
+        let user_stack_top = user_test::get_user_stack_top();
+
+        crate::kprintln!("[L1] spawning user process: entry={:#x} stack_top={:#x}", user_entry, user_stack_top);
+        if process::spawn("user_test", user_entry, user_stack_top, None).is_none() {
+            panic!("failed to spawn user_test");
+        }
+    }
 
-    // Layer 1: enter user mode and prove round-trip syscall works.
-    let user_entry = user_test::user_main as usize;
-    let user_stack_top = user_test::get_user_stack_top();
+    crate::kprintln!("[PROC] Process table:");
+    process::dump();
 
-    crate::kprintln!("[L1] entering user mode: entry={:#x} stack_top={:#x}", user_entry, user_stack_top);
-    let frame = trap::create_test_user_context(user_entry, user_stack_top);
-    trap::enter_user_mode(&frame)
+    smp::start_secondary_harts();
+    process::scheduler::start()
+}
+
+/// List the initrd and start the programs named on the kernel command line
+///
+/// `-append "init=name1,name2"` picks the programs; the default is `init`.
+/// Like Linux, other `KEY=value` words on the command line become their
+/// environment. Each program gets the console and spawn capabilities.
+/// Returns `false` if nothing could be launched.
+fn launch_initrd_programs() -> bool {
+    let Some(rd) = initrd::get() else {
+        crate::kprintln!("[INITRD] none (boot QEMU with -initrd to load user programs)");
+        return false;
+    };
+
+    crate::kprintln!("[INITRD] contents:");
+    for file in rd.files() {
+        crate::kprintln!("  {:<24} {} bytes", file.name, file.data.len());
+    }
+
+    let bootargs = fdt::get()
+        .and_then(|fdt| fdt.find_node("/chosen"))
+        .and_then(|chosen| chosen.property_str("bootargs"))
+        .unwrap_or("");
+    let programs = bootargs
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("init="))
+        .unwrap_or("init");
+
+    let mut launched = false;
+    for name in programs.split(',').filter(|n| !n.is_empty()) {
+        let Some(file) = rd.find(name) else {
+            crate::kprintln!("[INITRD] {}: not found", name);
+            continue;
+        };
+
+        let Some(mut args) = loader::args::Args::new() else {
+            crate::kprintln!("[INITRD] {}: out of memory", file.name);
+            continue;
+        };
+        let mut env = bootargs.split_whitespace().filter(|w| w.contains('=') && !w.starts_with("init="));
+        let built = args.push_arg(file.name).and_then(|()| env.try_for_each(|var| args.push_env(var)));
+        if let Err(e) = built {
+            crate::kprintln!("[INITRD] {}: bad command line ({:?})", file.name, e);
+            continue;
+        }
+
+        let mut caps = cap::CapTable::new();
+        caps.insert(cap::Capability::Console);
+        caps.insert(cap::Capability::Spawn);
+
+        match loader::spawn(file.name, file.data, None, args, caps, process::limits::Limits::UNLIMITED) {
+            Ok(pid) => {
+                crate::kprintln!("[INITRD] {}: started as PID {}", file.name, pid);
+                launched = true;
+            }
+            Err(e) => crate::kprintln!("[INITRD] {}: {}", file.name, e),
+        }
+    }
+    launched
 }
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    unsafe { uart::force_unlock() };
     crate::kprintln!("\n[PANIC] kernel panic");
     if let Some(loc) = info.location() {
         crate::kprintln!("[PANIC] at {}:{}", loc.file(), loc.line());
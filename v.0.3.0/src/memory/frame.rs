@@ -1,4 +1,6 @@
 use super::{PAGE_SIZE, align_up};
+use crate::process::{kthread, wait::WaitQueue};
+use crate::sync::{KernelCell, OnceCell, SpinLock};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 /// Maximum number of frames we can track (32K frames = 128MB with 4KB pages)
@@ -17,18 +19,22 @@ pub struct FrameAllocator {
     next_free: AtomicUsize,
 }
 
-/// Global frame allocator instance
-static mut FRAME_ALLOCATOR: FrameAllocator = FrameAllocator {
-    bitmap: [const { AtomicUsize::new(0) }; MAX_FRAMES / (core::mem::size_of::<usize>() * 8)],
-    start_addr: 0,
-    total_frames: 0,
-    next_free: AtomicUsize::new(0),
-};
+/// Global frame allocator instance, set up at boot
+static FRAME_ALLOCATOR: OnceCell<FrameAllocator> = OnceCell::new();
 
 impl FrameAllocator {
+    /// An allocator for `total_frames` frames from `start_addr`, all free
+    fn new(start_addr: usize, total_frames: usize) -> Self {
+        FrameAllocator {
+            bitmap: [const { AtomicUsize::new(0) }; MAX_FRAMES / (core::mem::size_of::<usize>() * 8)],
+            start_addr,
+            total_frames: total_frames.min(MAX_FRAMES),
+            next_free: AtomicUsize::new(0),
+        }
+    }
+    
     /// Allocate a single physical frame (4KB page)
     /// Returns physical address of the frame, or None if out of memory
-    #[allow(dead_code)]
     pub fn alloc(&self) -> Option<usize> {
         let start = self.next_free.load(Ordering::Relaxed);
         
@@ -62,6 +68,33 @@ impl FrameAllocator {
         None // Out of memory!
     }
     
+    /// Mark every frame overlapping [start, end) as used
+    pub fn reserve(&self, start: usize, end: usize) {
+        let first = start.saturating_sub(self.start_addr) / PAGE_SIZE;
+        let last = align_up(end.saturating_sub(self.start_addr)) / PAGE_SIZE;
+        
+        for frame in first..last.min(self.total_frames) {
+            let word_idx = frame / (core::mem::size_of::<usize>() * 8);
+            let bit_idx = frame % (core::mem::size_of::<usize>() * 8);
+            self.bitmap[word_idx].fetch_or(1 << bit_idx, Ordering::AcqRel);
+        }
+    }
+    
+    /// Free a frame previously returned by `alloc`
+    pub fn dealloc(&self, phys_addr: usize) {
+        if phys_addr < self.start_addr || !phys_addr.is_multiple_of(PAGE_SIZE) {
+            return;
+        }
+        let frame = (phys_addr - self.start_addr) / PAGE_SIZE;
+        if frame >= self.total_frames {
+            return;
+        }
+        
+        let word_idx = frame / (core::mem::size_of::<usize>() * 8);
+        let bit_idx = frame % (core::mem::size_of::<usize>() * 8);
+        self.bitmap[word_idx].fetch_and(!(1 << bit_idx), Ordering::AcqRel);
+    }
+    
     /// Get statistics about memory usage
     #[allow(dead_code)]
     pub fn stats(&self) -> (usize, usize) {
@@ -76,37 +109,106 @@ impl FrameAllocator {
 }
 
 /// Initialize the frame allocator
-pub unsafe fn init(kernel_end: usize, memory_end: usize) {
-    let allocator = &raw mut FRAME_ALLOCATOR;
+pub fn init(kernel_end: usize, memory_end: usize) {
     let start = align_up(kernel_end);
     let total_bytes = memory_end - start;
     let total_frames = total_bytes / PAGE_SIZE;
     
-    (*allocator).start_addr = start;
-    (*allocator).total_frames = total_frames.min(MAX_FRAMES);
-    (*allocator).next_free.store(0, Ordering::Release);
-    
-    // Clear bitmap (all frames free initially)
-    let bitmap_ptr = (*allocator).bitmap.as_ptr();
-    for i in 0..(*allocator).bitmap.len() {
-        (*bitmap_ptr.add(i)).store(0, Ordering::Release);
-    }
+    // All frames free initially
+    let _ = FRAME_ALLOCATOR.set(FrameAllocator::new(start, total_frames));
 }
 
 /// Allocate a physical frame
-#[allow(dead_code)]
 pub fn alloc_frame() -> Option<usize> {
-    unsafe {
-        let allocator = &raw const FRAME_ALLOCATOR;
-        (*allocator).alloc()
+    FRAME_ALLOCATOR.get()?.alloc()
+}
+
+/// Keep the allocator away from a physical range (DTB, initrd, ...)
+pub fn reserve(start: usize, end: usize) {
+    if let Some(allocator) = FRAME_ALLOCATOR.get() {
+        allocator.reserve(start, end);
+    }
+}
+
+/// Free a physical frame
+pub fn free_frame(phys_addr: usize) {
+    if let Some(allocator) = FRAME_ALLOCATOR.get() {
+        allocator.dealloc(phys_addr);
     }
 }
 
 /// Get memory statistics
 #[allow(dead_code)]
 pub fn get_stats() -> (usize, usize) {
-    unsafe {
-        let allocator = &raw const FRAME_ALLOCATOR;
-        (*allocator).stats()
+    FRAME_ALLOCATOR.get().map_or((0, 0), FrameAllocator::stats)
+}
+/// Number of pre-zeroed frames kept ready by the `kzerod` kernel thread
+const ZERO_POOL_SIZE: usize = 16;
+
+/// Refill the pool once it drops to this many frames
+const ZERO_POOL_LOW: usize = ZERO_POOL_SIZE / 4;
+
+/// Frames that have already been zeroed in the background
+struct ZeroPool {
+    frames: [usize; ZERO_POOL_SIZE],
+    len: usize,
+}
+
+static ZERO_POOL: SpinLock<ZeroPool> = SpinLock::new(ZeroPool { frames: [0; ZERO_POOL_SIZE], len: 0 });
+
+/// Where `kzerod` sleeps while the pool is full enough
+static ZERO_POOL_WAITERS: KernelCell<WaitQueue> = KernelCell::new(WaitQueue::new());
+
+/// Allocate a physical frame filled with zeroes
+///
+/// Takes a frame from the pre-zeroed pool when possible, and zeroes one
+/// inline otherwise.
+pub fn alloc_zeroed_frame() -> Option<usize> {
+    let (pooled, len) = {
+        let mut pool = ZERO_POOL.lock();
+        let pooled = pool.len.checked_sub(1).map(|last| {
+            pool.len = last;
+            pool.frames[last]
+        });
+        (pooled, pool.len)
+    };
+    let frame = match pooled {
+        Some(frame) => frame,
+        None => {
+            let frame = alloc_frame()?;
+            unsafe { core::ptr::write_bytes(frame as *mut u8, 0, PAGE_SIZE) };
+            frame
+        }
+    };
+
+    if len < ZERO_POOL_LOW {
+        // SAFETY: allocations run under the kernel lock; the daemon only
+        // borrows the queue to park on it
+        unsafe { ZERO_POOL_WAITERS.get() }.wake_one();
+    }
+    Some(frame)
+}
+
+/// Body of the `kzerod` kernel thread
+///
+/// Keeps the zero pool topped up, then sleeps until an allocation drains
+/// it below the low watermark.
+pub fn zero_pool_daemon() {
+    loop {
+        while ZERO_POOL.lock().len < ZERO_POOL_SIZE {
+            let Some(frame) = alloc_frame() else { break };
+            unsafe { core::ptr::write_bytes(frame as *mut u8, 0, PAGE_SIZE) };
+
+            let mut pool = ZERO_POOL.lock();
+            if pool.len < ZERO_POOL_SIZE {
+                let len = pool.len;
+                pool.frames[len] = frame;
+                pool.len += 1;
+            } else {
+                free_frame(frame);
+            }
+        }
+
+        kthread::block_on(&ZERO_POOL_WAITERS);
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,16 @@
 pub mod frame;
 pub mod heap;
+pub mod paging;
+pub mod user;
 
 /// Page size for RISC-V (4KB)
 pub const PAGE_SIZE: usize = 4096;
 
+/// Align address down to page boundary
+pub const fn align_down(addr: usize) -> usize {
+    addr & !(PAGE_SIZE - 1)
+}
+
 /// Align address up to page boundary
 pub const fn align_up(addr: usize) -> usize {
     (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
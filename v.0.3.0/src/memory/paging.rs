@@ -0,0 +1,306 @@
+//! Sv39 virtual memory for user address spaces
+//!
+//! Every address space shares the same kernel view: two supervisor-only
+//! 1GB gigapages identity-mapping MMIO (0x0000_0000) and RAM
+//! (0x8000_0000). The gigabyte in between is user space, built from 4KB
+//! pages. Because the kernel is identity-mapped everywhere, the trap
+//! handler keeps running no matter whose `satp` is active.
+//!
+//! ```text
+//! 0xC000_0000 ┌──────────────────────┐
+//!             │ RAM (kernel, S-only) │ root[2] gigapage
+//! 0x8000_0000 ├──────────────────────┤ USER_TOP
+//!             │ guard page           │
+//!             │ user stack           │
+//!             │        ...           │
+//!             │ ELF segments         │ root[1] → 4KB pages
+//! 0x4000_0000 ├──────────────────────┤ USER_BASE
+//!             │ MMIO (S-only)        │ root[0] gigapage
+//! 0x0000_0000 └──────────────────────┘
+//! ```
+
+use core::arch::asm;
+
+use super::{PAGE_SIZE, frame};
+
+/// Page table entry flags
+pub const PTE_V: usize = 1 << 0;
+pub const PTE_R: usize = 1 << 1;
+pub const PTE_W: usize = 1 << 2;
+pub const PTE_X: usize = 1 << 3;
+pub const PTE_U: usize = 1 << 4;
+pub const PTE_G: usize = 1 << 5;
+pub const PTE_A: usize = 1 << 6;
+pub const PTE_D: usize = 1 << 7;
+
+/// Lowest user virtual address
+pub const USER_BASE: usize = 0x4000_0000;
+
+/// One past the highest user virtual address
+pub const USER_TOP: usize = 0x8000_0000;
+
+/// satp.MODE value for Sv39
+const SATP_SV39: usize = 8 << 60;
+
+/// Entries per page table
+const ENTRIES: usize = 512;
+
+type PageTable = [usize; ENTRIES];
+
+/// Why a mapping request failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    OutOfMemory,
+    NotUserAddress,
+    AlreadyMapped,
+    NotMapped,
+    PermissionDenied,
+    /// The space already owns as many frames as its limit allows
+    LimitExceeded,
+}
+
+/// A user address space (one Sv39 root table)
+///
+/// It owns every frame mapped into it and its page tables, and counts
+/// them against an optional limit.
+#[derive(Debug)]
+pub struct AddressSpace {
+    root: usize,
+    /// Frames owned: tables (root included) and mapped pages
+    frames: usize,
+    frame_limit: usize,
+}
+
+impl AddressSpace {
+    /// Create an address space with only the kernel mappings
+    pub fn new() -> Option<Self> {
+        let root = frame::alloc_zeroed_frame()?;
+        let table = unsafe { &mut *(root as *mut PageTable) };
+
+        // MMIO and RAM gigapages: supervisor-only, global, pre-set A/D
+        let kernel = PTE_V | PTE_R | PTE_W | PTE_G | PTE_A | PTE_D;
+        table[0] = leaf(0x0000_0000, kernel);
+        table[2] = leaf(0x8000_0000, kernel | PTE_X);
+
+        Some(AddressSpace { root, frames: 1, frame_limit: usize::MAX })
+    }
+
+    /// Refuse to map more than `limit` frames, page tables included
+    pub fn set_frame_limit(&mut self, limit: usize) {
+        self.frame_limit = limit;
+    }
+
+    /// Value to load into `satp` to switch to this address space
+    pub fn satp(&self) -> usize {
+        SATP_SV39 | (self.root / PAGE_SIZE)
+    }
+
+    /// Map one 4KB user page `va` → `pa` with `flags` (R/W/X; U is implied)
+    pub fn map(&mut self, va: usize, pa: usize, flags: usize) -> Result<(), MapError> {
+        if !is_user(va) {
+            return Err(MapError::NotUserAddress);
+        }
+
+        if self.frames >= self.frame_limit {
+            return Err(MapError::LimitExceeded);
+        }
+        let pte = self.walk(va, true)?;
+        if *pte & PTE_V != 0 {
+            return Err(MapError::AlreadyMapped);
+        }
+        *pte = leaf(pa, flags | PTE_U | PTE_A | PTE_D);
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Remove the mapping for `va`, returning the frame it pointed at
+    ///
+    /// The frame is not freed; that's up to the caller. Page tables stay
+    /// allocated until the address space is dropped.
+    pub fn unmap(&mut self, va: usize) -> Option<usize> {
+        let pte = self.walk(va, false).ok()?;
+        if *pte & PTE_V == 0 {
+            return None;
+        }
+        let pa = pte_addr(*pte);
+        *pte = 0;
+        self.frames -= 1;
+        unsafe { asm!("sfence.vma {}, zero", in(reg) va) };
+        Some(pa)
+    }
+
+    /// Add permissions to an existing mapping
+    pub fn add_flags(&mut self, va: usize, flags: usize) -> Result<(), MapError> {
+        let pte = self.walk(va, false)?;
+        if *pte & PTE_V == 0 {
+            return Err(MapError::NotUserAddress);
+        }
+        *pte |= flags;
+        Ok(())
+    }
+
+    /// Look up the physical address and flags backing `va`
+    pub fn translate(&self, va: usize) -> Option<(usize, usize)> {
+        if !is_user(va) {
+            return None;
+        }
+
+        let mut table = self.root;
+        for level in [2, 1] {
+            let pte = unsafe { (*(table as *const PageTable))[vpn(va, level)] };
+            if pte & PTE_V == 0 {
+                return None;
+            }
+            table = pte_addr(pte);
+        }
+
+        let pte = unsafe { (*(table as *const PageTable))[vpn(va, 0)] };
+        if pte & PTE_V == 0 {
+            return None;
+        }
+        Some((pte_addr(pte) + va % PAGE_SIZE, pte & 0x3FF))
+    }
+
+    /// Copy bytes out of this address space (pages must be user-readable)
+    ///
+    /// Goes through the page tables and the kernel's identity map, so it
+    /// works whether or not this space is the active one.
+    pub fn read(&self, va: usize, buf: &mut [u8]) -> Result<(), MapError> {
+        let mut done = 0;
+        while done < buf.len() {
+            let pa = self.user_page(va + done, PTE_R)?;
+            let chunk = (PAGE_SIZE - (va + done) % PAGE_SIZE).min(buf.len() - done);
+            unsafe { core::ptr::copy_nonoverlapping(pa as *const u8, buf[done..].as_mut_ptr(), chunk) };
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    /// Copy bytes into this address space (pages must be user-writable)
+    pub fn write(&mut self, va: usize, data: &[u8]) -> Result<(), MapError> {
+        let mut done = 0;
+        while done < data.len() {
+            let pa = self.user_page(va + done, PTE_W)?;
+            let chunk = (PAGE_SIZE - (va + done) % PAGE_SIZE).min(data.len() - done);
+            unsafe { core::ptr::copy_nonoverlapping(data[done..].as_ptr(), pa as *mut u8, chunk) };
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    /// Physical address of `va` if it's mapped with `need` permissions
+    fn user_page(&self, va: usize, need: usize) -> Result<usize, MapError> {
+        if !is_user(va) {
+            return Err(MapError::NotUserAddress);
+        }
+        let (pa, flags) = self.translate(va).ok_or(MapError::NotMapped)?;
+        if flags & need != need {
+            return Err(MapError::PermissionDenied);
+        }
+        Ok(pa)
+    }
+
+    /// Make this address space the active one
+    pub fn activate(&self) {
+        set_satp(self.satp());
+    }
+
+    /// Find the leaf PTE for `va`, optionally creating missing tables
+    fn walk(&mut self, va: usize, create: bool) -> Result<&mut usize, MapError> {
+        let mut table = self.root;
+
+        for level in [2, 1] {
+            let entry = unsafe { &mut (*(table as *mut PageTable))[vpn(va, level)] };
+            if *entry & PTE_V == 0 {
+                if !create {
+                    return Err(MapError::NotUserAddress);
+                }
+                // The table and the page it leads to must both fit
+                if self.frames + 1 >= self.frame_limit {
+                    return Err(MapError::LimitExceeded);
+                }
+                let next = frame::alloc_zeroed_frame().ok_or(MapError::OutOfMemory)?;
+                *entry = (next / PAGE_SIZE) << 10 | PTE_V;
+                self.frames += 1;
+            }
+            table = pte_addr(*entry);
+        }
+
+        Ok(unsafe { &mut (*(table as *mut PageTable))[vpn(va, 0)] })
+    }
+}
+
+impl Drop for AddressSpace {
+    /// Free every user page and page table (kernel gigapages are shared)
+    fn drop(&mut self) {
+        // Never leave satp pointing at a freed root table
+        if current_satp() == self.satp() {
+            activate_bare();
+        }
+        
+        let root = unsafe { &*(self.root as *const PageTable) };
+
+        for &l1 in root.iter().filter(|&&pte| is_table(pte)) {
+            let l1 = pte_addr(l1);
+            for &l0 in unsafe { &*(l1 as *const PageTable) }.iter().filter(|&&pte| is_table(pte)) {
+                let l0 = pte_addr(l0);
+                for &page in unsafe { &*(l0 as *const PageTable) }.iter() {
+                    if page & PTE_V != 0 {
+                        frame::free_frame(pte_addr(page));
+                    }
+                }
+                frame::free_frame(l0);
+            }
+            frame::free_frame(l1);
+        }
+        frame::free_frame(self.root);
+    }
+}
+
+/// Switch to the bare (untranslated) address space
+pub fn activate_bare() {
+    set_satp(0);
+}
+
+/// The `satp` value currently in effect
+pub fn current_satp() -> usize {
+    let satp: usize;
+    unsafe { asm!("csrr {}, satp", out(reg) satp) };
+    satp
+}
+
+fn set_satp(satp: usize) {
+    if satp == current_satp() {
+        return;
+    }
+    unsafe {
+        asm!(
+            "csrw satp, {}",
+            "sfence.vma",
+            in(reg) satp,
+        );
+    }
+}
+
+/// Is `va` inside the user part of the address space?
+pub fn is_user(va: usize) -> bool {
+    (USER_BASE..USER_TOP).contains(&va)
+}
+
+/// Virtual page number for `level` (2 = root)
+fn vpn(va: usize, level: usize) -> usize {
+    (va >> (12 + 9 * level)) & (ENTRIES - 1)
+}
+
+fn leaf(pa: usize, flags: usize) -> usize {
+    (pa / PAGE_SIZE) << 10 | flags | PTE_V
+}
+
+/// Valid entry pointing at a next-level table (no R/W/X)
+fn is_table(pte: usize) -> bool {
+    pte & PTE_V != 0 && pte & (PTE_R | PTE_W | PTE_X) == 0
+}
+
+fn pte_addr(pte: usize) -> usize {
+    (pte >> 10) * PAGE_SIZE
+}
@@ -0,0 +1,136 @@
+//! Checked access to the current process' user memory
+//!
+//! Syscalls hand us user virtual addresses; nothing may dereference one
+//! directly. `copy_from_user` and `copy_to_user` first check that the
+//! whole range lies in the caller's user mappings with the permission the
+//! copy needs, then copy through those mappings with `sstatus.SUM` set for
+//! just the duration of the copy.
+//!
+//! The check runs under the kernel lock, so the mappings can't change
+//! before the copy. Should the copy fault anyway, it must not take the
+//! kernel down: the copy loop's load and store are listed in an exception
+//! fixup table, and a kernel page fault at one of them resumes at the
+//! fixup, which makes the copy report how much it didn't do.
+
+use core::arch::asm;
+
+use super::paging::{self, PTE_R, PTE_U, PTE_W, USER_TOP};
+use super::{PAGE_SIZE, align_down};
+
+/// A user range that isn't mapped with the needed permission, or a copy
+/// that faulted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault;
+
+/// Copy `dst.len()` bytes from user address `src`
+pub fn copy_from_user(dst: &mut [u8], src: usize) -> Result<(), Fault> {
+    check_range(src, dst.len(), PTE_R)?;
+    with_sum(|| unsafe { user_copy(dst.as_mut_ptr(), src as *const u8, dst.len()) })
+}
+
+/// Copy `src` to user address `dst`
+pub fn copy_to_user(dst: usize, src: &[u8]) -> Result<(), Fault> {
+    check_range(dst, src.len(), PTE_W)?;
+    with_sum(|| unsafe { user_copy(dst as *mut u8, src.as_ptr(), src.len()) })
+}
+
+/// Read the `index`-th word of a user array
+pub fn read_word(base: usize, index: usize) -> Result<usize, Fault> {
+    let addr = index.checked_mul(8).and_then(|off| base.checked_add(off)).ok_or(Fault)?;
+    let mut word = [0u8; 8];
+    copy_from_user(&mut word, addr)?;
+    Ok(usize::from_le_bytes(word))
+}
+
+/// Is `[va, va + len)` mapped user memory with `need` permissions in the
+/// active address space?
+fn check_range(va: usize, len: usize, need: usize) -> Result<(), Fault> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = va.checked_add(len).ok_or(Fault)?;
+    if !paging::is_user(va) || end > USER_TOP {
+        return Err(Fault);
+    }
+
+    let leader = crate::process::current_leader().ok_or(Fault)?;
+    let space = leader.address_space.as_ref().ok_or(Fault)?;
+    // We copy through the live mappings, so they must be the ones checked
+    debug_assert_eq!(space.satp(), paging::current_satp());
+
+    let need = need | PTE_U;
+    (align_down(va)..end).step_by(PAGE_SIZE).try_for_each(|page| match space.translate(page) {
+        Some((_, flags)) if flags & need == need => Ok(()),
+        _ => Err(Fault),
+    })
+}
+
+/// Run a user copy with S-mode access to user pages (`sstatus.SUM`)
+///
+/// The copy returns how many bytes it left undone.
+fn with_sum(copy: impl FnOnce() -> usize) -> Result<(), Fault> {
+    unsafe { asm!("csrs sstatus, {}", in(reg) SSTATUS_SUM) };
+    let left = copy();
+    unsafe { asm!("csrc sstatus, {}", in(reg) SSTATUS_SUM) };
+    if left == 0 { Ok(()) } else { Err(Fault) }
+}
+
+const SSTATUS_SUM: usize = 1 << 18;
+
+/// An instruction allowed to fault, and where to resume if it does
+#[repr(C)]
+struct Fixup {
+    insn: usize,
+    fixup: usize,
+}
+
+/// Entries in `__user_copy_fixups`
+const FIXUPS: usize = 2;
+
+extern "C" {
+    /// Copy `len` bytes; returns how many were left when it faulted (0:
+    /// all copied)
+    #[link_name = "__user_copy"]
+    fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+
+    #[link_name = "__user_copy_fixups"]
+    static FIXUP_TABLE: [Fixup; FIXUPS];
+}
+
+/// Where to resume after a kernel page fault at `pc`, if it was a user
+/// copy that faulted
+pub fn fixup(pc: usize) -> Option<usize> {
+    unsafe { FIXUP_TABLE.iter() }.find(|entry| entry.insn == pc).map(|entry| entry.fixup)
+}
+
+// Byte at a time, so a fault leaves an exact count in a2
+core::arch::global_asm!(
+    r#"
+.section .text
+.align 2
+.global __user_copy
+__user_copy:
+    beqz a2, 2f
+1:
+.Luser_copy_load:
+    lb t0, 0(a1)
+.Luser_copy_store:
+    sb t0, 0(a0)
+    addi a0, a0, 1
+    addi a1, a1, 1
+    addi a2, a2, -1
+    bnez a2, 1b
+2:
+.Luser_copy_fixup:
+    mv a0, a2
+    ret
+
+.pushsection .rodata.user_copy_fixups, "a"
+.balign 8
+.global __user_copy_fixups
+__user_copy_fixups:
+    .dword .Luser_copy_load, .Luser_copy_fixup
+    .dword .Luser_copy_store, .Luser_copy_fixup
+.popsection
+"#
+);
@@ -0,0 +1,83 @@
+//! Platform-Level Interrupt Controller
+//!
+//! Device interrupts reach a hart as a supervisor external interrupt
+//! through the PLIC. Each source has a priority; each hart context has an
+//! enable bit per source and a threshold. The hart that takes the
+//! interrupt claims the source, services the device and completes it; the
+//! others find nothing to claim.
+//!
+//! On QEMU virt hart N has two contexts, M-mode (2N) and S-mode (2N + 1).
+
+use crate::smp::MAX_HARTS;
+use crate::sync::OnceCell;
+
+const COMPATIBLE: [&str; 2] = ["riscv,plic0", "sifive,plic-1.0.0"];
+
+/// Register offsets
+const PRIORITY: usize = 0x0000;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+static PLIC_BASE: OnceCell<usize> = OnceCell::new();
+
+/// Find the PLIC in the boot DTB
+pub fn init() {
+    let base = crate::fdt::get()
+        .and_then(|fdt| fdt.nodes().find(|node| COMPATIBLE.iter().any(|c| node.is_compatible(c))))
+        .and_then(|node| node.reg())
+        .map(|(base, _)| base);
+
+    match base {
+        Some(base) => {
+            let _ = PLIC_BASE.set(base);
+            crate::kprintln!("[PLIC] at {:#x}", base);
+        }
+        None => crate::kprintln!("[PLIC] not found - no device interrupts"),
+    }
+}
+
+/// Deliver source `irq` to every hart's S-mode context
+pub fn enable(irq: u32) {
+    let Some(&base) = PLIC_BASE.get() else { return };
+    let irq = irq as usize;
+    unsafe {
+        reg(base + PRIORITY + irq * 4).write_volatile(1);
+        for hart in 0..MAX_HARTS {
+            let context = s_context(hart);
+            let enable = reg(base + ENABLE + context * ENABLE_STRIDE + irq / 32 * 4);
+            enable.write_volatile(enable.read_volatile() | 1 << (irq % 32));
+            reg(base + CONTEXT + context * CONTEXT_STRIDE + THRESHOLD).write_volatile(0);
+        }
+    }
+}
+
+/// Service every source pending for this hart
+pub fn handle_interrupt() {
+    let Some(&base) = PLIC_BASE.get() else { return };
+    let claim = reg(base + CONTEXT + s_context(crate::smp::this_hart().id) * CONTEXT_STRIDE + CLAIM);
+
+    loop {
+        let irq = unsafe { claim.read_volatile() };
+        if irq == 0 {
+            break;
+        }
+        if Some(irq) == crate::console::irq() {
+            crate::console::handle_interrupt();
+        } else {
+            crate::kprintln!("[PLIC] unexpected interrupt {}", irq);
+        }
+        unsafe { claim.write_volatile(irq) };
+    }
+}
+
+fn s_context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+fn reg(addr: usize) -> *mut u32 {
+    addr as *mut u32
+}
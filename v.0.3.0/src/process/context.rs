@@ -1,9 +1,10 @@
+use crate::trap::TrapFrame;
+
 /// CPU context for RISC-V process switching
 /// 
 /// This structure holds ALL CPU state needed to pause/resume a process.
 /// RISC-V has 32 general-purpose registers (x0-x31), but x0 is hardwired to zero,
 /// so we only need to save x1-x31 (31 registers).
-
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Context {
@@ -81,18 +82,49 @@ impl Context {
         ctx
     }
     
-    /// Set return value in context (for syscalls)
-    pub fn set_return_value(&mut self, value: usize) {
-        self.a0 = value;
+    /// Create a new context for a kernel thread
+    /// 
+    /// Kernel threads run in S-mode on their own kernel stack.
+    /// 
+    /// # Arguments
+    /// * `entry_point` - Function the thread starts in
+    /// * `kernel_stack` - Top of the thread's kernel stack
+    /// * `arg` - Passed to the entry point in a0
+    pub fn new_kernel(entry_point: usize, kernel_stack: usize, arg: usize) -> Self {
+        let mut ctx = Self::zero();
+        
+        ctx.pc = entry_point;
+        ctx.sp = kernel_stack;
+        ctx.a0 = arg;
+        
+        // - SPP (bit 8) = 1 to stay in S-mode on sret
+        // - SPIE (bit 5) = 1 so the thread can be interrupted
+        ctx.sstatus = (1 << 8) | (1 << 5);
+        
+        ctx
+    }
+    
+    /// Capture the registers saved by the trap vector
+    pub fn save_from(&mut self, frame: &TrapFrame) {
+        self.gprs_mut().copy_from_slice(&frame.regs);
+        self.pc = frame.sepc;
+        self.sstatus = frame.sstatus;
+    }
+    
+    /// Load this context into a trap frame so `sret` resumes it
+    pub fn restore_to(&self, frame: &mut TrapFrame) {
+        frame.regs.copy_from_slice(self.gprs());
+        frame.sepc = self.pc;
+        frame.sstatus = self.sstatus;
     }
     
-    /// Get syscall number (from a7 register)
-    pub fn syscall_number(&self) -> usize {
-        self.a7
+    /// x1-x31 as an array, in the same order as `TrapFrame::regs`
+    fn gprs(&self) -> &[usize; 31] {
+        // repr(C): the first 31 fields are x1-x31 in order
+        unsafe { &*(self as *const Self as *const [usize; 31]) }
     }
     
-    /// Get syscall arguments (a0-a5)
-    pub fn syscall_args(&self) -> [usize; 6] {
-        [self.a0, self.a1, self.a2, self.a3, self.a4, self.a5]
+    fn gprs_mut(&mut self) -> &mut [usize; 31] {
+        unsafe { &mut *(self as *mut Self as *mut [usize; 31]) }
     }
-}
\ No newline at end of file
+}
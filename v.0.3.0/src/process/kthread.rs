@@ -0,0 +1,112 @@
+//! Kernel threads
+//!
+//! A kernel thread is a process that runs in S-mode on its own kernel
+//! stack. It sits in the process table and run queue like any user
+//! process, so background work (zeroing frames, flushing logs) happens
+//! when the CPU would otherwise be idle instead of inline in a trap.
+//!
+//! Kernel threads can't `ecall` into our own trap handler, so they enter
+//! the scheduler by raising a supervisor software interrupt on themselves.
+
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{Pid, Process, scheduler, wait::WaitQueue};
+use crate::sync::KernelCell;
+
+/// Maximum number of kernel threads alive at once
+const MAX_KTHREADS: usize = 8;
+
+/// Size of each kernel thread stack
+const KSTACK_SIZE: usize = 16 * 1024;
+
+#[repr(C, align(16))]
+struct KernelStack([u8; KSTACK_SIZE]);
+
+/// Stack pool for kernel threads (lives in BSS)
+static mut KSTACKS: [KernelStack; MAX_KTHREADS] = [const { KernelStack([0; KSTACK_SIZE]) }; MAX_KTHREADS];
+
+/// Which stacks in the pool are taken
+static KSTACK_USED: [AtomicBool; MAX_KTHREADS] = [const { AtomicBool::new(false) }; MAX_KTHREADS];
+
+/// Claim a stack from the pool, returning its slot
+fn alloc_stack() -> Option<usize> {
+    KSTACK_USED.iter().position(|used| {
+        used.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    })
+}
+
+/// Return a stack to the pool
+pub(crate) fn free_stack(slot: usize) {
+    KSTACK_USED[slot].store(false, Ordering::Release);
+}
+
+/// Top address of a pooled stack
+fn stack_top(slot: usize) -> usize {
+    unsafe { (&raw const KSTACKS[slot]) as usize + KSTACK_SIZE }
+}
+
+/// Spawn a kernel thread running `f`
+///
+/// The thread exits when `f` returns. Returns `None` if the stack pool or
+/// the process table is exhausted.
+pub fn spawn<F>(name: &'static str, f: F) -> Option<Pid>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let slot = alloc_stack()?;
+    let data = Box::into_raw(Box::new(f));
+    let entry = trampoline::<F> as *const () as usize;
+
+    let pid = super::insert(|pid| {
+        let mut thread = Process::new_kernel(pid, name, entry, stack_top(slot), data as usize);
+        thread.kernel_stack = Some(slot);
+        thread
+    });
+
+    if pid.is_none() {
+        free_stack(slot);
+        drop(unsafe { Box::from_raw(data) });
+    }
+    pid
+}
+
+/// First code a kernel thread runs: call the closure, then exit
+extern "C" fn trampoline<F: FnOnce()>(data: *mut F) -> ! {
+    let f = unsafe { Box::from_raw(data) };
+    f();
+    exit()
+}
+
+/// Terminate the calling kernel thread
+pub fn exit() -> ! {
+    super::exit_current(0);
+    yield_now();
+    // The scheduler never picks a dead thread again
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
+
+/// Give up the CPU to the scheduler
+pub fn yield_now() {
+    scheduler::request_resched();
+    raise_soft_interrupt();
+}
+
+/// Block the calling kernel thread on `queue` until woken
+pub fn block_on(queue: &KernelCell<WaitQueue>) {
+    // SAFETY: kernel threads run under the kernel lock, and the borrow
+    // ends before we yield and whoever wakes us takes the queue
+    unsafe { queue.get() }.park_current();
+    yield_now();
+}
+
+/// Trap into the scheduler via a supervisor software interrupt
+///
+/// With SIE set (as it is for kernel threads) the interrupt is taken
+/// right after the write, before `yield_now` returns.
+fn raise_soft_interrupt() {
+    unsafe { asm!("csrs sip, {}", in(reg) 1usize << 1) };
+}
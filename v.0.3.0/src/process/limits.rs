@@ -0,0 +1,118 @@
+//! Per-process resource limits
+//!
+//! A process is spawned with limits chosen by its parent, which can only
+//! narrow its own: a child never gets more than its parent has. Each
+//! limit is checked where the resource is handed out:
+//!
+//! - `frames`: every frame an address space owns, page tables included
+//!   (`AddressSpace::map`)
+//! - `threads`: table entries in the process, main thread included
+//!   (`thread::create`)
+//! - `children`: child processes, zombies included (SYS_SPAWN)
+//! - `caps`: capability table slots (`CapTable::insert`)
+//! - `ipc_depth`: senders waiting on any one of the process' endpoints
+//!   (`ipc::send`)
+//! - `cpu_percent`: share of each `CPU_PERIOD_MS` the whole process may
+//!   run for. A process over its share is blocked until the period ends.
+//!
+//! Limits live in the main thread's entry and cover all its threads.
+
+use super::{MAX_PROCESSES, Pid};
+use crate::cap::MAX_CAPS;
+use crate::loader::THREAD_STACK_SLOTS;
+use crate::process::stats;
+
+/// Length of a CPU accounting period
+const CPU_PERIOD_MS: u64 = 100;
+
+/// Words in a `Limits` block
+pub const LIMIT_WORDS: usize = 6;
+
+/// Resource limits, as passed to SYS_SPAWN (a zero field inherits the
+/// parent's value)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub frames: usize,
+    pub threads: usize,
+    pub children: usize,
+    pub caps: usize,
+    pub ipc_depth: usize,
+    pub cpu_percent: usize,
+}
+
+impl Limits {
+    /// Limits of the processes the kernel starts itself
+    pub const UNLIMITED: Limits = Limits {
+        frames: usize::MAX,
+        threads: THREAD_STACK_SLOTS + 1,
+        children: MAX_PROCESSES,
+        caps: MAX_CAPS,
+        ipc_depth: usize::MAX,
+        cpu_percent: 100,
+    };
+
+    /// Limits for a child that asked for `requested`
+    pub fn narrow(&self, requested: &Limits) -> Limits {
+        let pick = |want: usize, have: usize| if want == 0 { have } else { want.min(have) };
+        Limits {
+            frames: pick(requested.frames, self.frames),
+            threads: pick(requested.threads, self.threads),
+            children: pick(requested.children, self.children),
+            caps: pick(requested.caps, self.caps),
+            ipc_depth: pick(requested.ipc_depth, self.ipc_depth),
+            cpu_percent: pick(requested.cpu_percent, self.cpu_percent),
+        }
+    }
+}
+
+/// Which limit a request ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    Frames,
+    Threads,
+    Children,
+    Caps,
+    IpcDepth,
+}
+
+/// Charge `ticks` of CPU time used by `pid` to its process' share
+pub fn charge_cpu(pid: Pid, ticks: u64) {
+    let Some(leader) = super::get(pid).and_then(|p| super::get(p.tgid)) else { return };
+    if leader.limits.cpu_percent < 100 {
+        roll_period(pid);
+        leader.cpu_period_used += ticks;
+    }
+}
+
+/// When `pid`'s process runs out of CPU this period (`None`: no limit)
+pub fn cpu_budget_end(pid: Pid) -> Option<u64> {
+    let leader = super::get(pid).and_then(|p| super::get(p.tgid))?;
+    if leader.limits.cpu_percent >= 100 {
+        return None;
+    }
+    roll_period(pid);
+    let budget = period_ticks() * leader.limits.cpu_percent as u64 / 100;
+    Some(stats::now() + budget.saturating_sub(leader.cpu_period_used))
+}
+
+/// If `pid`'s process has used up its CPU share, when the period ends
+pub fn cpu_throttled_until(pid: Pid) -> Option<u64> {
+    let end = cpu_budget_end(pid)?;
+    let leader = super::get(pid).and_then(|p| super::get(p.tgid))?;
+    (end <= stats::now()).then(|| leader.cpu_period_start + period_ticks())
+}
+
+/// Start a new accounting period for `pid`'s process if the last one ended
+fn roll_period(pid: Pid) {
+    let Some(leader) = super::get(pid).and_then(|p| super::get(p.tgid)) else { return };
+    let now = stats::now();
+    if now >= leader.cpu_period_start + period_ticks() {
+        leader.cpu_period_start = now;
+        leader.cpu_period_used = 0;
+    }
+}
+
+fn period_ticks() -> u64 {
+    crate::time::timebase_hz() * CPU_PERIOD_MS / 1000
+}
@@ -1,11 +1,31 @@
 pub mod context;
+pub mod kthread;
+pub mod limits;
+pub mod scheduler;
+pub mod stats;
+pub mod supervisor;
+pub mod thread;
+pub mod wait;
 
+use crate::cap::CapTable;
+use crate::ipc::{IpcWait, Reply};
+use crate::memory::paging::AddressSpace;
+use crate::ring::Ring;
+use crate::sync::KernelCell;
+use crate::syscall::{Filter, Trace};
 use context::Context;
 use core::sync::atomic::{AtomicUsize, Ordering};
+use limits::Limits;
+use stats::ProcStats;
+use supervisor::{Respawn, Supervision};
+use wait::WaitQueue;
 
 /// Process ID type
 pub type Pid = usize;
 
+/// Maximum number of live processes (size of the process table)
+pub const MAX_PROCESSES: usize = 64;
+
 /// Process states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessState {
@@ -15,34 +35,186 @@ pub enum ProcessState {
     Dead,       // Finished execution
 }
 
+/// How a process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// It called SYS_EXIT with this code
+    Exited(usize),
+    /// It raised exception `cause` at `pc`, with `addr` from stval
+    Faulted { cause: usize, pc: usize, addr: usize },
+}
+
+impl ExitReason {
+    /// Exit code to report: faults read as 128 + the exception cause
+    pub fn code(&self) -> usize {
+        match *self {
+            ExitReason::Exited(code) => code,
+            ExitReason::Faulted { cause, .. } => 128 + cause,
+        }
+    }
+}
+
+/// A reaped child, as SYS_WAIT reports it
+#[derive(Debug, Clone, Copy)]
+pub struct ExitStatus {
+    pub pid: Pid,
+    pub reason: ExitReason,
+    /// Replacement started by its supervisor
+    pub restarted_as: Option<Pid>,
+}
+
+/// What privilege level a process runs at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessKind {
+    User,       // U-mode program
+    Kernel,     // S-mode kernel thread
+}
+
 /// Process Control Block (PCB)
-/// 
+///
 /// This is the kernel's view of a process. It contains everything needed
 /// to schedule, switch, and manage the process.
+///
+/// Extra threads of a process are entries too, with their own context,
+/// stack and accounting. Their `tgid` names the process they belong to;
+/// that entry (the main thread) owns the address space, capabilities and
+/// children shared by all of them.
 #[derive(Debug)]
 pub struct Process {
     pub pid: Pid,
+    /// Process this entry belongs to (its own PID for the main thread)
+    pub tgid: Pid,
     pub context: Context,
     pub state: ProcessState,
+    pub kind: ProcessKind,
     pub name: &'static str,
+    pub parent: Option<Pid>,
+    pub exit_code: usize,
+    pub exit_reason: ExitReason,
+    /// Sv39 address space; `None` runs untranslated (bare `satp`)
+    pub address_space: Option<AddressSpace>,
+    pub caps: CapTable,
+    /// Resource limits, for the whole process (kept by the main thread)
+    pub limits: Limits,
+    /// How to start this program again, if it came from the initrd
+    pub(crate) respawn: Option<Respawn>,
+    /// Restart policy set by our parent
+    pub(crate) supervision: Option<Supervision>,
+    /// The process our supervisor started to replace us
+    pub(crate) restarted_as: Option<Pid>,
+    pub stats: ProcStats,
+    /// When we last started charging CPU time to this process
+    pub(crate) cpu_mark: u64,
+    /// Start of the current CPU share period and time used in it
+    pub(crate) cpu_period_start: u64,
+    pub(crate) cpu_period_used: u64,
+    /// Processes blocked in SYS_WAIT for one of our children to exit
+    pub child_exit: WaitQueue,
+    /// Threads blocked in SYS_THREAD_JOIN on this thread
+    pub thread_exit: WaitQueue,
+    /// User stack slot of an extra thread (see `loader::thread_stack_top`)
+    pub(crate) stack_slot: Option<usize>,
+    /// Physical address of the futex word we're sleeping on
+    pub(crate) futex_key: usize,
+    /// When to give up waiting (`time` CSR value), if ever
+    pub(crate) deadline: Option<u64>,
+    /// Next process on the timer queue (intrusive link, see `timer`)
+    pub(crate) timer_next: Option<Pid>,
+    /// Next process on the wait queue we're parked on (intrusive link)
+    pub(crate) wait_next: Option<Pid>,
+    /// The wait queue we're parked on, null when not blocked
+    pub(crate) waiting_on: *mut WaitQueue,
+    /// Kernel stack slot owned by a kernel thread
+    pub(crate) kernel_stack: Option<usize>,
+    /// Harts we may run on, one bit per hart ID
+    pub affinity: usize,
+    /// Hart we last ran on, whose run queue we go back to
+    pub(crate) last_hart: Option<usize>,
+    /// Syscall tracing, if someone asked for it (see `syscall::trace`)
+    pub trace: Option<Trace>,
+    /// When the traced syscall we're blocked in was first made
+    pub(crate) trace_start: Option<u64>,
+    /// As a tracer: sequence number after the last record we read
+    pub(crate) trace_read: u64,
+    /// As a tracer: our records overwritten before we read them
+    pub(crate) trace_lost: u64,
+    /// Syscalls we may make, if restricted (see `syscall::filter`)
+    pub filter: Option<Filter>,
+    /// Submission/completion ring (main thread only, see `ring`)
+    pub(crate) ring: Option<Ring>,
+    /// What we're blocked on an IPC endpoint to do (see `ipc`)
+    pub(crate) ipc: Option<IpcWait>,
+    /// The call we received and may answer
+    pub(crate) reply: Option<Reply>,
 }
 
+// `waiting_on` points at a wait queue in a static or in another slot of
+// the process table, and is only followed under the kernel lock
+unsafe impl Send for Process {}
+
 impl Process {
     /// Create a new process
     pub fn new(pid: Pid, name: &'static str, entry_point: usize, stack: usize) -> Self {
         Process {
             pid,
+            tgid: pid,
             context: Context::new_user(entry_point, stack),
             state: ProcessState::Ready,
+            kind: ProcessKind::User,
             name,
+            parent: None,
+            exit_code: 0,
+            exit_reason: ExitReason::Exited(0),
+            address_space: None,
+            caps: CapTable::new(),
+            limits: Limits::UNLIMITED,
+            respawn: None,
+            supervision: None,
+            restarted_as: None,
+            stats: ProcStats::zero(),
+            cpu_mark: 0,
+            cpu_period_start: 0,
+            cpu_period_used: 0,
+            child_exit: WaitQueue::new(),
+            thread_exit: WaitQueue::new(),
+            stack_slot: None,
+            futex_key: 0,
+            deadline: None,
+            timer_next: None,
+            wait_next: None,
+            waiting_on: core::ptr::null_mut(),
+            kernel_stack: None,
+            affinity: crate::smp::ALL_HARTS,
+            last_hart: None,
+            trace: None,
+            trace_start: None,
+            trace_read: 0,
+            trace_lost: 0,
+            filter: None,
+            ring: None,
+            ipc: None,
+            reply: None,
         }
     }
     
+    /// Create a new kernel thread
+    pub fn new_kernel(pid: Pid, name: &'static str, entry_point: usize, stack: usize, arg: usize) -> Self {
+        let mut process = Process::new(pid, name, entry_point, stack);
+        process.context = Context::new_kernel(entry_point, stack, arg);
+        process.kind = ProcessKind::Kernel;
+        process
+    }
+
+    /// Is this an extra thread rather than a process' main thread?
+    pub fn is_thread(&self) -> bool {
+        self.tgid != self.pid
+    }
+
     /// Mark process as running
     pub fn set_running(&mut self) {
         self.state = ProcessState::Running;
     }
-    
+
     /// Mark process as ready
     pub fn set_ready(&mut self) {
         self.state = ProcessState::Ready;
@@ -57,21 +229,253 @@ pub fn alloc_pid() -> Pid {
     NEXT_PID.fetch_add(1, Ordering::Relaxed)
 }
 
-/// Simple process table (for now, just one process)
-static mut CURRENT_PROCESS: Option<Process> = None;
+/// Process table: every live (or zombie) process lives in one slot
+static PROCESS_TABLE: KernelCell<[Option<Process>; MAX_PROCESSES]> = KernelCell::new([const { None }; MAX_PROCESSES]);
+
+/// The process table, to be borrowed briefly
+///
+/// Every kernel path runs under the kernel lock. Entries handed out by
+/// `get` and `iter` may overlap the way `static mut` borrows used to, so
+/// callers keep them short and never free a slot they are still using.
+fn table() -> &'static mut [Option<Process>; MAX_PROCESSES] {
+    // SAFETY: see above
+    unsafe { PROCESS_TABLE.get() }
+}
+
+/// Look up a process by PID
+pub fn get(pid: Pid) -> Option<&'static mut Process> {
+    table().iter_mut().flatten().find(|p| p.pid == pid)
+}
+
+/// Iterate over every process in the table
+pub fn iter() -> impl Iterator<Item = &'static mut Process> {
+    table().iter_mut().flatten()
+}
+
+/// Does `pid` belong to `ancestor` or to one of its descendants?
+///
+/// Threads count as part of their process. PIDs are never reused, so
+/// following parents always ends.
+pub fn descends_from(pid: Pid, ancestor: Pid) -> bool {
+    let mut next = get(pid).map(|p| p.tgid);
+    while let Some(tgid) = next {
+        if tgid == ancestor {
+            return true;
+        }
+        next = get(tgid).and_then(|leader| leader.parent);
+    }
+    false
+}
+
+/// PID of the process running on this hart
+pub fn current_pid() -> Option<Pid> {
+    crate::smp::this_hart().current
+}
 
 /// Get the current running process
 pub fn current_process() -> Option<&'static mut Process> {
-    unsafe { CURRENT_PROCESS.as_mut() }
+    current_pid().and_then(get)
+}
+
+/// The process the current thread belongs to
+///
+/// This is where the address space, capabilities and children live.
+pub fn current_leader() -> Option<&'static mut Process> {
+    current_process().and_then(|p| get(p.tgid))
+}
+
+/// Set this hart's current process
+pub(crate) unsafe fn set_current_pid(pid: Option<Pid>) {
+    crate::smp::this_hart().current = pid;
+}
+
+/// Create a new process and hand it to the scheduler
+///
+/// Returns `None` when the process table is full.
+pub fn spawn(name: &'static str, entry_point: usize, stack: usize, parent: Option<Pid>) -> Option<Pid> {
+    insert(|pid| {
+        let mut process = Process::new(pid, name, entry_point, stack);
+        process.parent = parent;
+        process
+    })
 }
 
-/// Set the current process
-pub unsafe fn set_current_process(process: Process) {
-    CURRENT_PROCESS = Some(process);
+/// Put a freshly built process into a free table slot and make it runnable
+pub(crate) fn insert(build: impl FnOnce(Pid) -> Process) -> Option<Pid> {
+    let slot = table().iter_mut().find(|slot| slot.is_none())?;
+
+    let pid = alloc_pid();
+    *slot = Some(build(pid));
+
+    scheduler::make_ready(pid);
+    Some(pid)
+}
+
+/// Remove a process from the table, freeing its slot
+fn release(pid: Pid) {
+    if let Some(slot) = table().iter_mut().find(|s| s.as_ref().is_some_and(|p| p.pid == pid)) {
+        if let Some(stack) = slot.as_ref().and_then(|p| p.kernel_stack) {
+            kthread::free_stack(stack);
+        }
+        *slot = None;
+    }
+}
+
+/// Terminate the current process with exit code `code`
+pub fn exit_current(code: usize) {
+    terminate_current(ExitReason::Exited(code));
+}
+
+/// Terminate the current process, with all of its threads
+///
+/// The process stays in the table as a zombie until its parent collects
+/// the exit status with SYS_WAIT. A supervised process is restarted
+/// first if its policy says so. Orphans are released immediately. Any
+/// thread may call this; the others simply vanish. Threads running on
+/// other harts are interrupted there and freed by their hart's scheduler.
+pub fn terminate_current(reason: ExitReason) {
+    let Some(current) = current_process() else { return };
+    let (pid, tgid) = (current.pid, current.tgid);
+
+    // The calling thread is still on the CPU: the scheduler frees it.
+    // Unhook everyone else from their wait queues and the timer queue
+    // first, since some of those queues live in threads that are about
+    // to go away.
+    current.state = ProcessState::Dead;
+    for member in iter().filter(|p| p.tgid == tgid && p.pid != pid) {
+        if let Some(queue) = unsafe { member.waiting_on.as_mut() } {
+            queue.remove(member.pid);
+        }
+        crate::timer::cancel(member.pid);
+    }
+    crate::ipc::release(tgid);
+    for slot in table().iter_mut() {
+        let Some(thread) = slot.as_mut().filter(|t| t.is_thread() && t.tgid == tgid && t.pid != pid) else {
+            continue;
+        };
+        match crate::smp::running_on(thread.pid) {
+            Some(hart) => {
+                thread.state = ProcessState::Dead;
+                crate::smp::kick(hart);
+            }
+            None => *slot = None,
+        }
+    }
+
+    let Some(process) = get(tgid) else { return };
+    let leader_elsewhere = crate::smp::running_on(tgid).filter(|_| tgid != pid);
+    if let Some(hart) = leader_elsewhere {
+        crate::smp::kick(hart);
+    }
+    process.state = ProcessState::Dead;
+    process.exit_code = reason.code();
+    process.exit_reason = reason;
+    process.restarted_as = supervisor::restart(tgid);
+    process.respawn = None;
+
+    // Our children lose their parent (and supervisor): drop zombies,
+    // detach the rest. A zombie with a thread still on another hart is
+    // freed by `release_if_orphan` once that thread leaves the CPU.
+    for i in 0..MAX_PROCESSES {
+        let Some(child) = table()[i].as_mut().filter(|p| p.parent == Some(tgid)) else { continue };
+        child.parent = None;
+        child.supervision = None;
+        if child.state == ProcessState::Dead && !on_cpu(child.pid) {
+            release(child.pid);
+        }
+    }
+
+    match process.parent.and_then(get) {
+        Some(parent) => {
+            parent.child_exit.wake_all();
+        }
+        // Nobody will ever wait for an orphan. If it's on a CPU the
+        // scheduler frees its slot, otherwise (a thread called exit) we do
+        None if tgid != pid && leader_elsewhere.is_none() => release(tgid),
+        None => {}
+    }
+}
+
+/// Collect an exited child of `parent`
+///
+/// `pid == 0` matches any child. Returns `Ok(Some(status))` for a reaped
+/// zombie, `Ok(None)` if matching children are still running and
+/// `Err(())` if there is no such child at all. A zombie is only reaped
+/// once none of its threads is left on another hart, since its address
+/// space goes with it.
+pub fn reap_child(parent: Pid, pid: Pid) -> Result<Option<ExitStatus>, ()> {
+    let mut found = false;
+
+    for child in iter().filter(|p| p.parent == Some(parent) && (pid == 0 || p.pid == pid)) {
+        found = true;
+        if child.state == ProcessState::Dead && !on_cpu(child.pid) {
+            let result = ExitStatus { pid: child.pid, reason: child.exit_reason, restarted_as: child.restarted_as };
+            release(child.pid);
+            return Ok(Some(result));
+        }
+    }
+
+    if found { Ok(None) } else { Err(()) }
+}
+
+/// Is any thread of process `tgid` on a hart right now?
+fn on_cpu(tgid: Pid) -> bool {
+    iter().any(|t| t.tgid == tgid && crate::smp::running_on(t.pid).is_some())
+}
+
+/// Free the slot of a dead process that nobody will reap
+///
+/// Processes are reaped by their parent, threads by a join from their
+/// own process, as long as that process lives. Called when a dead
+/// process leaves the CPU, which may be what its parent's SYS_WAIT is
+/// waiting for (see `reap_child`). An orphaned zombie that was left in
+/// the table because one of its threads was still running goes too.
+pub(crate) fn release_if_orphan(pid: Pid) {
+    let Some(p) = get(pid) else { return };
+    let tgid = p.tgid;
+    let reaper = match p.is_thread() {
+        true => get(tgid).is_some_and(|leader| leader.state != ProcessState::Dead),
+        false => p.parent.is_some(),
+    };
+    if p.state == ProcessState::Dead && !reaper {
+        release(pid);
+    }
+
+    let Some(leader) = get(tgid).filter(|leader| leader.state == ProcessState::Dead) else { return };
+    match leader.parent.and_then(get) {
+        Some(parent) => {
+            parent.child_exit.wake_all();
+        }
+        None if !on_cpu(tgid) => release(tgid),
+        None => {}
+    }
+}
+
+/// Print the process table (`ps`)
+pub fn dump() {
+    crate::kprintln!("  PID  PPID  KIND    STATE    USER(ticks) KERNEL(ticks) NAME");
+    for p in iter() {
+        let kind = match p.kind {
+            ProcessKind::User if p.is_thread() => "thread",
+            ProcessKind::User => "user",
+            ProcessKind::Kernel => "kthread",
+        };
+        let state = match p.state {
+            ProcessState::Ready => "ready",
+            ProcessState::Running => "running",
+            ProcessState::Blocked => "blocked",
+            ProcessState::Dead => "zombie",
+        };
+        match p.parent {
+            Some(ppid) => crate::kprintln!("  {:<4} {:<5} {:<7} {:<8} {:<11} {:<13} {}",
+                p.pid, ppid, kind, state, p.stats.user_ticks, p.stats.kernel_ticks, p.name),
+            None => crate::kprintln!("  {:<4} {:<5} {:<7} {:<8} {:<11} {:<13} {}",
+                p.pid, "-", kind, state, p.stats.user_ticks, p.stats.kernel_ticks, p.name),
+        }
+    }
 }
 
 /// Initialize process subsystem
 pub fn init() {
-    // For now, nothing to initialize
-    // Later: process table, scheduler, etc.
-}
\ No newline at end of file
+    scheduler::init();
+}
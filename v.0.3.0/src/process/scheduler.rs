@@ -0,0 +1,298 @@
+//! Round-robin scheduler
+//!
+//! Switching works by swapping trap frames: every trap saves the
+//! interrupted registers into a `TrapFrame` on the kernel trap stack. On
+//! the way out `schedule()` may copy that frame into the current process'
+//! `Context` and load the next process' context in its place, so the
+//! trap vector's `sret` resumes a different process.
+//!
+//! Only `Ready` processes sit in the run queue. `Blocked` processes are
+//! parked on a `WaitQueue`, the timer queue or both, and re-enter the run
+//! queue when woken.
+//!
+//! Each hart has its own run queue. A process that becomes runnable goes
+//! to an idle hart it may run on if there is one (waking it with an
+//! IPI), else back to the hart it last ran on, else to the shortest
+//! queue. A hart whose queue runs dry steals from the longest one before
+//! it drops the kernel lock and waits for an interrupt. Affinity masks
+//! (SYS_AFFINITY) limit which harts a process may use.
+
+use core::arch::asm;
+
+use super::{MAX_PROCESSES, Pid, Process, ProcessKind, ProcessState};
+use crate::memory::paging;
+use crate::smp::{self, MAX_HARTS};
+use crate::sync::KernelCell;
+use crate::trap::TrapFrame;
+
+/// Fixed-size FIFO of runnable PIDs
+struct RunQueue {
+    pids: [Pid; MAX_PROCESSES],
+    head: usize,
+    len: usize,
+}
+
+impl RunQueue {
+    const fn new() -> Self {
+        RunQueue { pids: [0; MAX_PROCESSES], head: 0, len: 0 }
+    }
+
+    fn push(&mut self, pid: Pid) {
+        // A PID can only be queued once, so the queue can never overflow
+        if self.len == MAX_PROCESSES || self.contains(pid) {
+            return;
+        }
+        self.pids[(self.head + self.len) % MAX_PROCESSES] = pid;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Pid> {
+        if self.len == 0 {
+            return None;
+        }
+        let pid = self.pids[self.head];
+        self.head = (self.head + 1) % MAX_PROCESSES;
+        self.len -= 1;
+        Some(pid)
+    }
+
+    fn contains(&self, pid: Pid) -> bool {
+        (0..self.len).any(|i| self.pids[(self.head + i) % MAX_PROCESSES] == pid)
+    }
+
+    /// Remove and return the first PID that `select` accepts
+    fn take_first(&mut self, mut select: impl FnMut(Pid) -> bool) -> Option<Pid> {
+        let index = (0..self.len).find(|&i| select(self.pids[(self.head + i) % MAX_PROCESSES]))?;
+        let pid = self.pids[(self.head + index) % MAX_PROCESSES];
+        for i in index..self.len - 1 {
+            self.pids[(self.head + i) % MAX_PROCESSES] = self.pids[(self.head + i + 1) % MAX_PROCESSES];
+        }
+        self.len -= 1;
+        Some(pid)
+    }
+}
+
+/// One queue per hart. They are only touched under the big kernel lock,
+/// so they need no lock of their own (see `smp`).
+static RUN_QUEUES: KernelCell<[RunQueue; MAX_HARTS]> = KernelCell::new([const { RunQueue::new() }; MAX_HARTS]);
+
+fn queues() -> &'static mut [RunQueue; MAX_HARTS] {
+    // SAFETY: the scheduler runs under the kernel lock, and every use is
+    // a single push, pop or scan that ends before the next one
+    unsafe { RUN_QUEUES.get() }
+}
+
+/// Mark a process runnable and queue it
+pub fn make_ready(pid: Pid) {
+    if let Some(process) = super::get(pid) {
+        process.set_ready();
+        crate::timer::cancel(pid);
+        let hart = place(process);
+        queues()[hart].push(pid);
+        smp::wake(hart);
+    }
+}
+
+/// Choose the hart whose queue a runnable process joins
+fn place(process: &Process) -> usize {
+    let allowed = |id: usize| process.affinity & (1 << id) != 0 && smp::is_online(id);
+
+    // Run it right away on an idle hart, else where its caches are warm
+    (0..MAX_HARTS)
+        .find(|&id| allowed(id) && smp::hart(id).idle)
+        .or(process.last_hart.filter(|&id| allowed(id)))
+        .or_else(|| (0..MAX_HARTS).filter(|&id| allowed(id)).min_by_key(|&id| queues()[id].len))
+        // Before the other harts come online
+        .unwrap_or(smp::this_hart().id)
+}
+
+/// Change which harts `pid` may run on, returning its previous mask
+///
+/// `mask == 0` only reads the mask. Fails for an unknown PID or a mask
+/// without any online hart. A process queued or running on a hart it may
+/// no longer use moves at once.
+pub fn set_affinity(pid: Pid, mask: usize) -> Option<usize> {
+    let process = super::get(pid)?;
+    let old = process.affinity;
+    if mask == 0 {
+        return Some(old);
+    }
+    if mask & smp::online_mask() == 0 {
+        return None;
+    }
+    process.affinity = mask;
+
+    match process.state {
+        ProcessState::Ready => {
+            if queues().iter_mut().any(|queue| queue.take_first(|p| p == pid).is_some()) {
+                make_ready(pid);
+            }
+        }
+        ProcessState::Running => match smp::running_on(pid) {
+            Some(hart) if mask & (1 << hart) != 0 => {}
+            Some(hart) if hart == smp::this_hart().id => request_resched(),
+            Some(hart) => smp::kick(hart),
+            None => {}
+        },
+        ProcessState::Blocked | ProcessState::Dead => {}
+    }
+    Some(old)
+}
+
+/// Ask for this hart's process to be switched out at the next trap exit
+pub fn request_resched() {
+    smp::this_hart().need_resched = true;
+}
+
+/// Pick what runs when the current trap returns
+///
+/// Called at the end of every trap. The fast path (current process still
+/// running, nobody asked for a switch) returns without touching the frame.
+pub fn schedule(frame: &mut TrapFrame) {
+    let resched = core::mem::take(&mut smp::this_hart().need_resched);
+    let prev = super::current_pid();
+
+    if let Some(current) = super::current_process() {
+        // Used up its CPU share: sit out the rest of the period
+        if current.state == ProcessState::Running {
+            if let Some(until) = super::limits::cpu_throttled_until(current.pid) {
+                throttle(current.pid, until);
+            }
+        }
+
+        match current.state {
+            ProcessState::Running if !resched => return,
+            ProcessState::Running => {
+                current.context.save_from(frame);
+                make_ready(current.pid);
+            }
+            ProcessState::Ready | ProcessState::Blocked => {
+                // Blocked (or blocked and already woken again): park the registers
+                current.context.save_from(frame);
+            }
+            ProcessState::Dead => {
+                let pid = current.pid;
+                super::release_if_orphan(pid);
+            }
+        }
+    }
+
+    let next = pick_next(frame);
+    switch_to(next, prev, frame);
+}
+
+/// Start scheduling on this hart: run the first ready process. Never
+/// returns.
+///
+/// Called holding the kernel lock, which is dropped if the process runs
+/// in U-mode.
+pub fn start() -> ! {
+    let mut frame = TrapFrame::zero();
+    let next = pick_next(&mut frame);
+    switch_to(next, None, &mut frame);
+    super::stats::resume(Some(next));
+    if frame.is_user() {
+        smp::unlock_kernel();
+    }
+    crate::trap::enter_user_mode(&frame)
+}
+
+/// Dequeue the next runnable process, idling until there is one
+fn pick_next(frame: &mut TrapFrame) -> Pid {
+    unsafe { super::set_current_pid(None) };
+
+    let mut announced = false;
+    loop {
+        while let Some(pid) = dequeue() {
+            if super::get(pid).is_some_and(|p| p.state == ProcessState::Ready) {
+                match super::limits::cpu_throttled_until(pid) {
+                    Some(until) => throttle(pid, until),
+                    None => return pid,
+                }
+            }
+        }
+
+        // Only worth a line when the whole machine runs out of work
+        if !announced && smp::others_idle() {
+            let live = super::iter().filter(|p| p.state != ProcessState::Dead).count();
+            if live == 0 {
+                crate::kprintln!("[SCHED] No processes left - idling");
+            } else {
+                crate::kprintln!("[SCHED] All {} process(es) blocked - idling", live);
+            }
+            announced = true;
+        }
+        idle(frame);
+    }
+}
+
+/// Next process from this hart's queue, or one stolen from another hart
+fn dequeue() -> Option<Pid> {
+    let me = smp::this_hart().id;
+    if let Some(pid) = queues()[me].pop() {
+        return Some(pid);
+    }
+
+    // Longest queue first
+    let mut victims: [usize; MAX_HARTS] = core::array::from_fn(|id| id);
+    victims.sort_unstable_by_key(|&id| core::cmp::Reverse(queues()[id].len));
+    let may_run_here = |pid: Pid| super::get(pid).is_some_and(|p| p.affinity & (1 << me) != 0);
+    victims
+        .into_iter()
+        .filter(|&id| id != me)
+        .find_map(|id| queues()[id].take_first(may_run_here))
+}
+
+/// Make `pid` the current process and load its registers into the frame
+fn switch_to(pid: Pid, prev: Option<Pid>, frame: &mut TrapFrame) {
+    if let Some(next) = super::get(pid) {
+        if prev != Some(pid) {
+            next.stats.context_switches += 1;
+        }
+        next.set_running();
+        next.context.restore_to(frame);
+        // Kernel threads keep the hart pointer in tp, wherever they last ran
+        if next.kind == ProcessKind::Kernel {
+            frame.regs[3] = smp::this_hart() as *mut smp::Hart as usize;
+        }
+        smp::this_hart().need_resched = false;
+        next.last_hart = Some(smp::this_hart().id);
+        crate::timer::set_slice_end(super::limits::cpu_budget_end(pid));
+        // Threads run in their process' address space
+        match super::get(next.tgid).and_then(|p| p.address_space.as_ref()) {
+            Some(space) => space.activate(),
+            None => paging::activate_bare(),
+        }
+        unsafe { super::set_current_pid(Some(pid)) };
+    }
+}
+
+/// Block a process that used up its CPU share until `until`
+fn throttle(pid: Pid, until: u64) {
+    if let Some(process) = super::get(pid) {
+        process.state = ProcessState::Blocked;
+        crate::timer::arm(pid, until);
+    }
+}
+
+/// Wait for an interrupt with nothing to run
+///
+/// We're inside the trap handler with interrupts masked, so `wfi` wakes
+/// on a pending interrupt without trapping; service it by polling. Other
+/// harts keep running meanwhile, so we let go of the kernel lock and of
+/// the last process' address space (which they may free).
+fn idle(frame: &mut TrapFrame) {
+    let hart = smp::this_hart();
+    paging::activate_bare();
+    hart.idle = true;
+    smp::unlock_kernel();
+    unsafe { asm!("wfi") };
+    smp::lock_kernel();
+    hart.idle = false;
+    crate::trap::poll_interrupts(frame);
+}
+
+/// Initialize the scheduler
+pub fn init() {
+    smp::this_hart().need_resched = false;
+}
@@ -0,0 +1,102 @@
+//! Per-process CPU and syscall accounting
+//!
+//! Times are measured with the `time` CSR, in timebase ticks (10MHz on
+//! QEMU virt). A process is charged user time from the moment it's
+//! resumed until it traps, and kernel time for the work done by the
+//! trap handler on its behalf. Kernel threads only ever accrue kernel
+//! time.
+
+use core::arch::asm;
+
+use super::Pid;
+use crate::trap::TrapFrame;
+
+/// Syscalls numbered below this get their own counter
+pub const SYSCALL_SLOTS: usize = 64;
+
+/// Accounting data for one process
+///
+/// This is also the layout SYS_PROC_STATS copies out to user space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ProcStats {
+    pub user_ticks: u64,
+    pub kernel_ticks: u64,
+    pub context_switches: u64,
+    pub page_faults: u64,
+    pub syscalls_total: u64,
+    /// Syscalls numbered >= SYSCALL_SLOTS
+    pub syscalls_other: u64,
+    /// Per-syscall counts, indexed by syscall number
+    pub syscalls: [u64; SYSCALL_SLOTS],
+}
+
+impl ProcStats {
+    pub const fn zero() -> Self {
+        ProcStats {
+            user_ticks: 0,
+            kernel_ticks: 0,
+            context_switches: 0,
+            page_faults: 0,
+            syscalls_total: 0,
+            syscalls_other: 0,
+            syscalls: [0; SYSCALL_SLOTS],
+        }
+    }
+
+    /// Count one invocation of syscall `num`
+    pub fn count_syscall(&mut self, num: usize) {
+        self.syscalls_total += 1;
+        match self.syscalls.get_mut(num) {
+            Some(count) => *count += 1,
+            None => self.syscalls_other += 1,
+        }
+    }
+}
+
+/// Read the `time` CSR
+pub fn now() -> u64 {
+    let time: u64;
+    unsafe { asm!("rdtime {}", out(reg) time) };
+    time
+}
+
+/// Charge the time from when the current process was resumed until the
+/// trap at `entered`
+///
+/// Called on trap entry. Whether it was user or kernel time depends on
+/// the privilege level the trap came from (sstatus.SPP). Anything after
+/// `entered`, such as waiting for the kernel lock, is left for
+/// `charge_kernel`.
+pub fn trap_enter(frame: &TrapFrame, entered: u64) {
+    let Some(process) = super::current_process() else { return };
+    let elapsed = entered.saturating_sub(process.cpu_mark);
+
+    if frame.sstatus & (1 << 8) != 0 {
+        process.stats.kernel_ticks += elapsed;
+    } else {
+        process.stats.user_ticks += elapsed;
+    }
+    process.cpu_mark = entered;
+    super::limits::charge_cpu(process.pid, elapsed);
+}
+
+/// Charge the trap handler's work to the process it ran for
+///
+/// Called before the scheduler picks who runs next, so idle time spent
+/// waiting for a runnable process isn't billed to anybody.
+pub fn charge_kernel(pid: Option<Pid>) {
+    let Some(process) = pid.and_then(super::get) else { return };
+    let now = now();
+    let elapsed = now.saturating_sub(process.cpu_mark);
+    process.stats.kernel_ticks += elapsed;
+    process.cpu_mark = now;
+    super::limits::charge_cpu(process.pid, elapsed);
+}
+
+/// Start the clock for the process we're about to resume
+pub fn resume(pid: Option<Pid>) {
+    if let Some(process) = pid.and_then(super::get) {
+        process.cpu_mark = now();
+    }
+}
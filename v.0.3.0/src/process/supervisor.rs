@@ -0,0 +1,128 @@
+//! Supervised processes that are restarted when they die
+//!
+//! A parent can supervise any of its children with a restart policy.
+//! When a supervised child dies the kernel starts a fresh copy of it
+//! (same program, arguments, capabilities and limits, new PID) unless
+//! the policy says otherwise or it has already been restarted
+//! `max_restarts` times within the last window. The parent still reaps
+//! the dead child with SYS_WAIT, which reports why it died and the PID of
+//! its replacement; supervision carries over to the replacement.
+
+use super::limits::Limits;
+use super::{ExitReason, Pid, stats};
+use crate::cap::CapTable;
+use crate::loader::args::Args;
+
+/// When to restart a supervised child
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,
+    /// Whatever the reason it died
+    Always,
+    /// Only if it faulted or exited with a non-zero code
+    OnFailure,
+}
+
+impl RestartPolicy {
+    /// Decode the SYS_SUPERVISE policy argument
+    pub fn from_raw(raw: usize) -> Option<Self> {
+        match raw {
+            0 => Some(RestartPolicy::Never),
+            1 => Some(RestartPolicy::Always),
+            2 => Some(RestartPolicy::OnFailure),
+            _ => None,
+        }
+    }
+}
+
+/// A child's supervision settings and restart history
+#[derive(Debug, Clone, Copy)]
+pub struct Supervision {
+    policy: RestartPolicy,
+    /// Restarts allowed per window (0 = no limit)
+    max_restarts: usize,
+    /// Window length in `time` ticks (0 = the whole lifetime)
+    window: u64,
+    window_start: u64,
+    restarts: usize,
+}
+
+/// Everything needed to start a process again
+#[derive(Debug)]
+pub struct Respawn {
+    pub name: &'static str,
+    pub image: &'static [u8],
+    pub args: Args,
+    pub caps: CapTable,
+    pub limits: Limits,
+}
+
+/// Make `parent` the supervisor of its child `child`
+///
+/// Replaces any earlier settings. `window_ms == 0` counts restarts over
+/// the child's whole lifetime. Fails if `child` isn't a restartable child
+/// of `parent`.
+pub fn supervise(parent: Pid, child: Pid, policy: RestartPolicy, max_restarts: usize, window_ms: u64) -> Result<(), ()> {
+    let child = super::get(child)
+        .filter(|c| c.parent == Some(parent) && !c.is_thread() && c.respawn.is_some())
+        .ok_or(())?;
+
+    child.supervision = Some(Supervision {
+        policy,
+        max_restarts,
+        window: window_ms.saturating_mul(crate::time::timebase_hz()) / 1000,
+        window_start: stats::now(),
+        restarts: 0,
+    });
+    Ok(())
+}
+
+/// Start a replacement for the dead process `pid` if its supervisor
+/// wants one, returning the new PID
+pub(super) fn restart(pid: Pid) -> Option<Pid> {
+    let dead = super::get(pid)?;
+    let parent = dead.parent?;
+    let mut supervision = dead.supervision?;
+
+    let failed = match dead.exit_reason {
+        ExitReason::Exited(code) => code != 0,
+        ExitReason::Faulted { .. } => true,
+    };
+    let wanted = match supervision.policy {
+        RestartPolicy::Never => false,
+        RestartPolicy::Always => true,
+        RestartPolicy::OnFailure => failed,
+    };
+    if !wanted {
+        return None;
+    }
+
+    let now = stats::now();
+    if supervision.window != 0 && now - supervision.window_start >= supervision.window {
+        supervision.window_start = now;
+        supervision.restarts = 0;
+    }
+    if supervision.max_restarts != 0 && supervision.restarts >= supervision.max_restarts {
+        crate::kprintln!("[SUPERVISOR] {} (PID {}) restarted too often, giving up", dead.name, pid);
+        return None;
+    }
+    supervision.restarts += 1;
+
+    let respawn = dead.respawn.take()?;
+    let name = respawn.name;
+    match crate::loader::spawn(name, respawn.image, Some(parent), respawn.args, respawn.caps, respawn.limits) {
+        Ok(new) => {
+            crate::kprintln!("[SUPERVISOR] {} (PID {}) restarted as PID {}", name, pid, new);
+            if let Some(replacement) = super::get(new) {
+                replacement.supervision = Some(supervision);
+                replacement.trace = dead.trace;
+                replacement.filter = dead.filter;
+            }
+            Some(new)
+        }
+        Err(e) => {
+            crate::kprintln!("[SUPERVISOR] {} (PID {}) could not be restarted: {}", name, pid, e);
+            None
+        }
+    }
+}
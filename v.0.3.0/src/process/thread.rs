@@ -0,0 +1,103 @@
+//! User threads
+//!
+//! A thread is a process table entry that shares its process' address
+//! space and capabilities (both owned by the main thread, `tgid`). Each
+//! thread gets its own user stack from a fixed slot below the main stack
+//! and its own `tp`, which user code uses to find thread-local storage.
+//! There are `THREAD_STACK_SLOTS` slots, so that many extra threads.
+//!
+//! Exited threads linger as zombies until another thread of the same
+//! process joins them. The main thread can't be joined: when it exits,
+//! the whole process does.
+
+use super::limits::LimitError;
+use super::{Pid, Process, ProcessState};
+use crate::loader::elf::ElfError;
+use crate::loader::{self, THREAD_STACK_SLOTS};
+
+/// Start a thread in the current process
+///
+/// It begins at `entry` with `a0 = arg`, `tp = tls` and `sp` at the top
+/// of a fresh stack. Fails with `Some(limit)` if the process' thread or
+/// memory limit is reached, `None` for anything else (no address space,
+/// no stack slot or table entry left).
+pub fn create(entry: usize, arg: usize, tls: usize) -> Result<Pid, Option<LimitError>> {
+    let creator = super::current_process().ok_or(None)?;
+    let (affinity, trace, filter) = (creator.affinity, creator.trace, creator.filter);
+    let leader = super::current_leader().ok_or(None)?;
+    let tgid = leader.pid;
+    let name = leader.name;
+    if super::iter().filter(|p| p.tgid == tgid).count() >= leader.limits.threads {
+        return Err(Some(LimitError::Threads));
+    }
+    let space = leader.address_space.as_mut().ok_or(None)?;
+
+    let in_use = |slot: usize| super::iter().any(|p| p.tgid == tgid && p.stack_slot == Some(slot));
+    let slot = (0..THREAD_STACK_SLOTS).find(|&slot| !in_use(slot)).ok_or(None)?;
+    let stack_top = loader::map_thread_stack(space, slot).map_err(|e| match e {
+        ElfError::FrameLimit => Some(LimitError::Frames),
+        _ => None,
+    })?;
+
+    let tid = super::insert(|tid| {
+        let mut thread = Process::new(tid, name, entry, stack_top);
+        thread.tgid = tgid;
+        thread.context.a0 = arg;
+        thread.context.tp = tls;
+        thread.stack_slot = Some(slot);
+        thread.affinity = affinity;
+        thread.trace = trace;
+        thread.filter = filter;
+        thread
+    });
+    if tid.is_none() {
+        loader::unmap_thread_stack(space, slot);
+    }
+    tid.ok_or(None)
+}
+
+/// End the current thread with `value` for whoever joins it
+///
+/// Called on the main thread this exits the whole process.
+pub fn exit_current(value: usize) {
+    let Some(thread) = super::current_process() else { return };
+    if !thread.is_thread() {
+        return super::exit_current(value);
+    }
+
+    thread.state = ProcessState::Dead;
+    thread.exit_code = value;
+
+    // Nothing will run on the stack again; give it back right away
+    if let Some(slot) = thread.stack_slot.take() {
+        if let Some(space) = super::get(thread.tgid).and_then(|p| p.address_space.as_mut()) {
+            loader::unmap_thread_stack(space, slot);
+        }
+    }
+
+    // Nobody else can answer the call it received
+    if let Some(reply) = thread.reply.take() {
+        crate::ipc::drop_reply(reply);
+    }
+    thread.thread_exit.wake_all();
+}
+
+/// Collect thread `tid` of the current process
+///
+/// Returns `Ok(Some(value))` for a reaped zombie, `Ok(None)` if it is
+/// still running and `Err(())` if it isn't a joinable thread.
+pub fn join(tid: Pid) -> Result<Option<usize>, ()> {
+    let current = super::current_process().ok_or(())?;
+    let (self_pid, tgid) = (current.pid, current.tgid);
+
+    let thread = super::get(tid)
+        .filter(|t| t.is_thread() && t.tgid == tgid && t.pid != self_pid)
+        .ok_or(())?;
+
+    if thread.state == ProcessState::Dead {
+        let value = thread.exit_code;
+        super::release(tid);
+        return Ok(Some(value));
+    }
+    Ok(None)
+}
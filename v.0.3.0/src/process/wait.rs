@@ -0,0 +1,153 @@
+//! Kernel wait queues
+//!
+//! A wait queue is the place a process parks itself while it waits for
+//! something to happen (a child to exit, a message, input, a deadline).
+//! Parked processes are in the `Blocked` state and never picked by the
+//! scheduler, so they cost no CPU until somebody wakes them.
+//!
+//! The queue is intrusive: the links live in the process table
+//! (`Process::wait_next`), so parking never allocates.
+
+use super::{Pid, Process, ProcessState, scheduler};
+
+/// FIFO queue of blocked processes
+#[derive(Debug)]
+pub struct WaitQueue {
+    head: Option<Pid>,
+    tail: Option<Pid>,
+}
+
+impl WaitQueue {
+    /// Create an empty wait queue
+    pub const fn new() -> Self {
+        WaitQueue { head: None, tail: None }
+    }
+
+    /// The longest waiting process, left parked
+    pub fn front(&self) -> Option<Pid> {
+        self.head
+    }
+
+    /// How many processes are waiting here
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        let mut cursor = self.head;
+        while let Some(process) = cursor.and_then(super::get) {
+            len += 1;
+            cursor = process.wait_next;
+        }
+        len
+    }
+
+    /// Park the current process on this queue
+    ///
+    /// The process is marked `Blocked`; the switch away happens when the
+    /// trap handler hands the CPU back to the scheduler. The queue must
+    /// not move while processes are parked on it.
+    pub fn park_current(&mut self) {
+        if let Some(pid) = super::current_pid() {
+            self.park(pid);
+        }
+    }
+
+    /// Park a specific process on this queue
+    pub fn park(&mut self, pid: Pid) {
+        let Some(process) = super::get(pid) else { return };
+
+        process.state = ProcessState::Blocked;
+        process.wait_next = None;
+        process.waiting_on = self as *mut WaitQueue;
+
+        match self.tail.and_then(super::get) {
+            Some(tail) => tail.wait_next = Some(pid),
+            None => self.head = Some(pid),
+        }
+        self.tail = Some(pid);
+    }
+
+    /// Wake the longest waiting process, returning its PID
+    pub fn wake_one(&mut self) -> Option<Pid> {
+        let pid = self.pop()?;
+        scheduler::make_ready(pid);
+        Some(pid)
+    }
+
+    /// Wake every waiting process, returning how many were woken
+    pub fn wake_all(&mut self) -> usize {
+        let mut woken = 0;
+        while self.wake_one().is_some() {
+            woken += 1;
+        }
+        woken
+    }
+
+    /// Wake up to `max` waiters accepted by `select`, oldest first
+    ///
+    /// `select` may update a process it accepts before it is woken.
+    /// Returns how many were woken.
+    pub fn wake_matching(&mut self, max: usize, mut select: impl FnMut(&mut Process) -> bool) -> usize {
+        let mut woken = 0;
+        let mut cursor = self.head;
+
+        while let Some(pid) = cursor {
+            if woken == max {
+                break;
+            }
+            let Some(process) = super::get(pid) else { break };
+            cursor = process.wait_next;
+
+            if select(process) {
+                self.remove(pid);
+                scheduler::make_ready(pid);
+                woken += 1;
+            }
+        }
+        woken
+    }
+
+    /// Take a specific process off this queue without waking it
+    ///
+    /// Used when a wait is abandoned (timeout, kill). Returns `false` if the
+    /// process wasn't parked here.
+    pub fn remove(&mut self, pid: Pid) -> bool {
+        let mut prev: Option<Pid> = None;
+        let mut cursor = self.head;
+
+        while let Some(current) = cursor {
+            let Some(process) = super::get(current) else { return false };
+            let next = process.wait_next;
+
+            if current == pid {
+                match prev.and_then(super::get) {
+                    Some(prev) => prev.wait_next = next,
+                    None => self.head = next,
+                }
+                if self.tail == Some(pid) {
+                    self.tail = prev;
+                }
+                process.wait_next = None;
+                process.waiting_on = core::ptr::null_mut();
+                return true;
+            }
+
+            prev = Some(current);
+            cursor = next;
+        }
+
+        false
+    }
+
+    /// Unlink the head of the queue
+    fn pop(&mut self) -> Option<Pid> {
+        let pid = self.head?;
+        let process = super::get(pid)?;
+
+        self.head = process.wait_next;
+        if self.head.is_none() {
+            self.tail = None;
+        }
+        process.wait_next = None;
+        process.waiting_on = core::ptr::null_mut();
+        Some(pid)
+    }
+}
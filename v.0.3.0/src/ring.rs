@@ -0,0 +1,227 @@
+//! Submission/completion rings
+//!
+//! A process can batch syscalls instead of trapping for each one. It
+//! sets up a ring, a page shared with the kernel at `loader::RING_ADDR`,
+//! writes requests into the submission queue and makes one SYS_RING_ENTER
+//! to have the kernel run them all. Results come back in the completion
+//! queue, tagged with the caller's `user_data`.
+//!
+//! Most requests complete during the enter. A `SYS_SLEEP` request is a
+//! timeout instead: it completes (with 0) once its time is up, posted
+//! from the timer interrupt, while the process gets on with other work.
+//! IPC requests likewise complete whenever the other side of the
+//! endpoint turns up, a `SYS_CALL` once its answer comes (see `ipc`).
+//! Other requests that would block the caller itself are refused with
+//! `Inval`.
+//!
+//! ```text
+//! 0      header: sq_head, sq_tail, cq_head, cq_tail, sq_entries,
+//!        cq_entries, cq_overflow (u32 each)
+//! 64     SQ_ENTRIES × Sqe { num, user_data, args[6] }
+//! 2112   CQ_ENTRIES × Cqe { user_data, result, regs[5] }
+//! ```
+//!
+//! The process owns sq_tail and cq_head; the kernel owns the rest. Heads
+//! and tails count up forever and index the queues modulo their size. A
+//! completion that finds the queue full is dropped and counted in
+//! cq_overflow.
+//!
+//! The ring belongs to the process' main thread entry and its page to
+//! the address space, so both go away when the process is reaped.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::memory::frame;
+use crate::memory::paging::{MapError, PTE_R, PTE_W};
+use crate::process::limits::LimitError;
+use crate::process::wait::WaitQueue;
+use crate::process::{self, ProcessState};
+use crate::sync::KernelCell;
+
+/// Queue sizes (powers of two)
+pub const SQ_ENTRIES: usize = 32;
+pub const CQ_ENTRIES: usize = 32;
+
+/// Timeouts a ring can have pending at once
+const MAX_TIMEOUTS: usize = 16;
+
+/// Byte offsets into the ring page
+const SQ_OFFSET: usize = 64;
+const CQ_OFFSET: usize = SQ_OFFSET + SQ_ENTRIES * core::mem::size_of::<Sqe>();
+
+const _: () = assert!(CQ_OFFSET + CQ_ENTRIES * core::mem::size_of::<Cqe>() <= crate::memory::PAGE_SIZE);
+
+#[repr(C)]
+struct Header {
+    sq_head: AtomicU32,
+    sq_tail: AtomicU32,
+    cq_head: AtomicU32,
+    cq_tail: AtomicU32,
+    sq_entries: AtomicU32,
+    cq_entries: AtomicU32,
+    cq_overflow: AtomicU32,
+}
+
+/// A submitted request: a syscall number and its arguments
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Sqe {
+    pub num: u64,
+    pub user_data: u64,
+    pub args: [u64; 6],
+}
+
+/// A finished request: a0 as the syscall would have returned it, then
+/// a1-a5 (what SYS_RECV returns there, say)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Cqe {
+    user_data: u64,
+    result: u64,
+    regs: [u64; 5],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Timeout {
+    /// `time` CSR value it completes at
+    deadline: u64,
+    user_data: u64,
+}
+
+/// A process' ring
+#[derive(Debug)]
+pub struct Ring {
+    /// Physical address of the shared page (the kernel's identity map
+    /// reaches it whatever address space is active)
+    page: usize,
+    timeouts: [Option<Timeout>; MAX_TIMEOUTS],
+    /// Threads in SYS_RING_ENTER waiting for completions
+    pub waiters: WaitQueue,
+}
+
+/// Earliest pending timeout of any ring, for the timer
+static NEXT_DEADLINE: KernelCell<Option<u64>> = KernelCell::new(None);
+
+impl Ring {
+    /// Map a fresh ring page into the current process
+    ///
+    /// Returns its user address; a process that already has a ring gets
+    /// the same one again. Fails with the limit that stopped it, or
+    /// `None` when out of memory.
+    pub fn setup() -> Result<usize, Option<LimitError>> {
+        let leader = process::current_leader().ok_or(None)?;
+        if leader.ring.is_some() {
+            return Ok(crate::loader::RING_ADDR);
+        }
+        let space = leader.address_space.as_mut().ok_or(None)?;
+        let page = frame::alloc_zeroed_frame().ok_or(None)?;
+        if let Err(e) = space.map(crate::loader::RING_ADDR, page, PTE_R | PTE_W) {
+            frame::free_frame(page);
+            return Err((e == MapError::LimitExceeded).then_some(LimitError::Frames));
+        }
+
+        let ring = Ring { page, timeouts: [None; MAX_TIMEOUTS], waiters: WaitQueue::new() };
+        let header = ring.header();
+        header.sq_entries.store(SQ_ENTRIES as u32, Ordering::Relaxed);
+        header.cq_entries.store(CQ_ENTRIES as u32, Ordering::Relaxed);
+        leader.ring = Some(ring);
+        Ok(crate::loader::RING_ADDR)
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.page as *const Header) }
+    }
+
+    /// Take the next submission, if there is one
+    pub fn next_submission(&mut self) -> Option<Sqe> {
+        let header = self.header();
+        let head = header.sq_head.load(Ordering::Relaxed);
+        if header.sq_tail.load(Ordering::Acquire) == head {
+            return None;
+        }
+        let slot = head as usize % SQ_ENTRIES;
+        let sqe = unsafe { ((self.page + SQ_OFFSET) as *const Sqe).add(slot).read_volatile() };
+        header.sq_head.store(head.wrapping_add(1), Ordering::Release);
+        Some(sqe)
+    }
+
+    /// Completions posted that the process hasn't consumed yet
+    pub fn ready(&self) -> usize {
+        let header = self.header();
+        let pending = header.cq_tail.load(Ordering::Relaxed).wrapping_sub(header.cq_head.load(Ordering::Acquire));
+        (pending as usize).min(CQ_ENTRIES)
+    }
+
+    /// Post a completion with a0-a5 and wake anybody waiting for one
+    pub fn complete(&mut self, user_data: u64, registers: [usize; 6]) {
+        self.post(user_data, registers);
+        self.waiters.wake_all();
+    }
+
+    fn post(&self, user_data: u64, registers: [usize; 6]) {
+        let header = self.header();
+        if self.ready() >= CQ_ENTRIES {
+            header.cq_overflow.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let tail = header.cq_tail.load(Ordering::Relaxed);
+        let [result, regs @ ..] = registers.map(|reg| reg as u64);
+        let cqe = Cqe { user_data, result, regs };
+        let slot = tail as usize % CQ_ENTRIES;
+        unsafe { ((self.page + CQ_OFFSET) as *mut Cqe).add(slot).write_volatile(cqe) };
+        header.cq_tail.store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Complete `user_data` with 0 at `deadline`; false if too many
+    /// timeouts are pending
+    pub fn add_timeout(&mut self, deadline: u64, user_data: u64) -> bool {
+        let Some(slot) = self.timeouts.iter_mut().find(|t| t.is_none()) else {
+            return false;
+        };
+        *slot = Some(Timeout { deadline, user_data });
+
+        // SAFETY: ring timeouts are added and expired under the kernel
+        // lock, and rearming the timer only reads the deadline afterwards
+        let next = unsafe { NEXT_DEADLINE.get() };
+        if next.is_none_or(|next| deadline < next) {
+            *next = Some(deadline);
+            crate::timer::rearm();
+        }
+        true
+    }
+
+    /// Complete every timeout due by `now`, returning the earliest one
+    /// left
+    fn expire(&mut self, now: u64) -> Option<u64> {
+        for i in 0..MAX_TIMEOUTS {
+            if let Some(timeout) = self.timeouts[i].take_if(|t| t.deadline <= now) {
+                self.post(timeout.user_data, [0; 6]);
+            }
+        }
+        self.waiters.wake_all();
+        self.timeouts.iter().flatten().map(|t| t.deadline).min()
+    }
+}
+
+/// Earliest ring timeout, if any
+pub fn next_deadline() -> Option<u64> {
+    // SAFETY: see `Ring::add_timeout`
+    unsafe { *NEXT_DEADLINE.get() }
+}
+
+/// Timer interrupt: complete every ring timeout due by `now`
+pub fn expire(now: u64) {
+    // SAFETY: see `Ring::add_timeout`
+    let next = unsafe { NEXT_DEADLINE.get() };
+    if next.is_none_or(|next| next > now) {
+        return;
+    }
+
+    *next = None;
+    for process in process::iter().filter(|p| p.state != ProcessState::Dead) {
+        let Some(ring) = process.ring.as_mut() else { continue };
+        if let Some(deadline) = ring.expire(now) {
+            *next = Some(next.map_or(deadline, |next| next.min(deadline)));
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Goldfish real-time clock
+//!
+//! QEMU virt has a `google,goldfish-rtc` device that counts nanoseconds
+//! since the Unix epoch, initialized from the host clock. Reading
+//! TIME_LOW latches the high half into TIME_HIGH, so the two reads give a
+//! consistent 64-bit value.
+
+const COMPATIBLE: &str = "google,goldfish-rtc";
+
+/// Register offsets
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+pub struct Rtc {
+    base_address: usize,
+}
+
+impl Rtc {
+    /// The RTC described by the boot DTB, if there is one
+    pub fn find() -> Option<Self> {
+        let node = crate::fdt::get()?.find_compatible(COMPATIBLE)?;
+        let (base_address, _) = node.reg()?;
+        Some(Rtc { base_address })
+    }
+
+    /// Nanoseconds since 1970-01-01 00:00:00 UTC
+    pub fn read_ns(&self) -> u64 {
+        unsafe {
+            let low = ((self.base_address + TIME_LOW) as *const u32).read_volatile();
+            let high = ((self.base_address + TIME_HIGH) as *const u32).read_volatile();
+            ((high as u64) << 32) | low as u64
+        }
+    }
+}
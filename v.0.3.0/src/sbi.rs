@@ -0,0 +1,51 @@
+//! Supervisor Binary Interface calls into OpenSBI
+//!
+//! Convention: extension ID in a7, function ID in a6, arguments in
+//! a0-a5; OpenSBI returns an error code in a0 and a value in a1.
+
+use core::arch::asm;
+
+/// Timer extension ("TIME")
+const EID_TIME: usize = 0x5449_4D45;
+
+/// Inter-processor interrupt extension ("sPI")
+const EID_IPI: usize = 0x0073_5049;
+
+/// Hart state management extension ("HSM")
+const EID_HSM: usize = 0x0048_534D;
+
+/// Make an SBI call, returning (error, value)
+fn call(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> (isize, usize) {
+    let error: isize;
+    let value: usize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") arg0 => error,
+            inlateout("a1") arg1 => value,
+            in("a2") arg2,
+            in("a6") fid,
+            in("a7") eid,
+        );
+    }
+    (error, value)
+}
+
+/// Raise a supervisor timer interrupt once `time` reaches `stime`
+///
+/// Also clears a pending timer interrupt; `u64::MAX` disarms the timer.
+pub fn set_timer(stime: u64) {
+    call(EID_TIME, 0, stime as usize, 0, 0);
+}
+
+/// Start the stopped hart `hartid` in S-mode at `start_addr`, with
+/// `opaque` in a1 (and its hart ID in a0); returns the SBI error code
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> isize {
+    call(EID_HSM, 0, hartid, start_addr, opaque).0
+}
+
+/// Raise a supervisor software interrupt on each hart in `mask`, whose
+/// bit 0 is hart `base`
+pub fn send_ipi(mask: usize, base: usize) {
+    call(EID_IPI, 0, mask, base, 0);
+}
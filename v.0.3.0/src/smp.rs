@@ -0,0 +1,231 @@
+//! Symmetric multiprocessing: per-hart state and secondary hart bring-up
+//!
+//! OpenSBI starts only the boot hart; the others sit in the HSM
+//! "stopped" state until we ask for them. Each one then gets a boot stack
+//! and a trap stack of its own, sets up its trap vector and timer and
+//! joins the scheduler.
+//!
+//! While a hart runs kernel code `tp` points at its `Hart` block. User
+//! programs keep their own `tp` (thread-local storage): the trap vector
+//! saves it in the trap frame and loads the hart pointer, which sits just
+//! above the hart's trap stack.
+//!
+//! Kernel data is shared by all harts. Most of it (`sync::KernelCell`) is
+//! guarded by one big kernel lock, a ticket lock so harts get it in turn.
+//! A hart holds it whenever it runs kernel code: it takes it on a trap
+//! from U-mode and drops it when returning to U-mode or going idle.
+//! Kernel threads run with it held.
+//!
+//! The big lock is an interim step. It made every kernel path safe on
+//! several harts at once, but only one hart runs kernel code at a time:
+//! user code runs in parallel, syscalls and interrupt handling do not.
+//! Per-hart structures such as the run queues therefore buy locality,
+//! not concurrency, and take no locks of their own. The plan is to split
+//! it up a subsystem at a time, starting with the run queues and the
+//! timer queue, then the process table, futexes and IPC, each getting
+//! its own lock, until the trap path no longer needs the big one.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::process::Pid;
+use crate::sbi;
+use crate::sync::TicketLock;
+
+/// Most harts we run on; higher hart IDs are left stopped
+pub const MAX_HARTS: usize = 8;
+
+/// Affinity mask allowing every hart
+pub const ALL_HARTS: usize = (1 << MAX_HARTS) - 1;
+
+/// Size of each secondary hart's boot stack
+const BOOT_STACK_SIZE: usize = 16 * 1024;
+
+/// Per-hart kernel state; `tp` points at the running hart's block
+#[repr(C)]
+pub struct Hart {
+    pub id: usize,
+    /// PID of the process on this hart
+    pub current: Option<Pid>,
+    /// Switch away from the current process at the next trap exit even
+    /// though it is still runnable
+    pub need_resched: bool,
+    /// When the current process' CPU budget runs out (see `timer`)
+    pub slice_end: Option<u64>,
+    /// Waiting in `wfi` for something to run, without the kernel lock
+    pub idle: bool,
+}
+
+impl Hart {
+    const fn new(id: usize) -> Self {
+        Hart { id, current: None, need_resched: false, slice_end: None, idle: false }
+    }
+}
+
+static mut HARTS: [Hart; MAX_HARTS] = {
+    let mut harts = [const { Hart::new(0) }; MAX_HARTS];
+    let mut id = 0;
+    while id < MAX_HARTS {
+        harts[id].id = id;
+        id += 1;
+    }
+    harts
+};
+
+/// Which harts have joined the scheduler
+static ONLINE: [AtomicBool; MAX_HARTS] = [const { AtomicBool::new(false) }; MAX_HARTS];
+
+#[repr(C, align(16))]
+struct BootStack([u8; BOOT_STACK_SIZE]);
+
+static mut BOOT_STACKS: [BootStack; MAX_HARTS] = [const { BootStack([0; BOOT_STACK_SIZE]) }; MAX_HARTS];
+
+/// The running hart's block
+pub fn this_hart() -> &'static mut Hart {
+    let tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) tp);
+        &mut *(tp as *mut Hart)
+    }
+}
+
+/// Block of hart `id`
+pub fn hart(id: usize) -> &'static mut Hart {
+    let harts = &raw mut HARTS;
+    unsafe { &mut (*harts)[id] }
+}
+
+/// Has hart `id` joined the scheduler?
+pub fn is_online(id: usize) -> bool {
+    ONLINE.get(id).is_some_and(|online| online.load(Ordering::Relaxed))
+}
+
+/// Mask of the harts taking part in scheduling
+pub fn online_mask() -> usize {
+    (0..MAX_HARTS).filter(|&id| is_online(id)).fold(0, |mask, id| mask | 1 << id)
+}
+
+/// Point `tp` at hart `id`'s block
+///
+/// # Safety
+/// Call once per hart, first thing, with its own hart ID.
+pub unsafe fn init_hart(id: usize) {
+    asm!("mv tp, {}", in(reg) hart(id) as *mut Hart);
+}
+
+/// Start every other hart listed in the device tree
+///
+/// Called on the boot hart, holding the kernel lock, once the kernel is
+/// initialized. The new harts wait for the lock before they schedule.
+pub fn start_secondary_harts() {
+    ONLINE[this_hart().id].store(true, Ordering::Relaxed);
+
+    let Some(fdt) = crate::fdt::get() else { return };
+    let cpus = fdt.nodes().filter(|node| {
+        node.name.starts_with("cpu@")
+            && node.property_str("device_type") == Some("cpu")
+            && node.property_str("status").is_none_or(|status| status == "okay")
+    });
+
+    for cpu in cpus {
+        let Some(id) = cpu.property_u64("reg").map(|id| id as usize) else { continue };
+        if id == this_hart().id {
+            continue;
+        }
+        if id >= MAX_HARTS {
+            crate::kprintln!("[SMP] hart {} ignored (MAX_HARTS = {})", id, MAX_HARTS);
+            continue;
+        }
+
+        let stack_top = unsafe { (&raw const BOOT_STACKS[id]) as usize + BOOT_STACK_SIZE };
+        let error = sbi::hart_start(id, _secondary_start as *const () as usize, stack_top);
+        if error != 0 {
+            crate::kprintln!("[SMP] hart {} failed to start (SBI error {})", id, error);
+        }
+    }
+}
+
+extern "C" {
+    fn _secondary_start();
+}
+
+// Secondary hart entry (from SBI HSM): a0 = hart ID, a1 = boot stack top
+core::arch::global_asm!(
+    r#"
+.section .text
+.align 4
+.global _secondary_start
+_secondary_start:
+    mv sp, a1
+    call {main}
+"#,
+    main = sym secondary_main,
+);
+
+extern "C" fn secondary_main(id: usize) -> ! {
+    unsafe { init_hart(id) };
+    lock_kernel();
+
+    crate::trap::init_hart();
+    crate::timer::init_hart();
+    ONLINE[id].store(true, Ordering::Relaxed);
+    crate::datapage::harts_changed();
+    crate::kprintln!("[SMP] hart {} online", id);
+
+    crate::process::scheduler::start()
+}
+
+/// Hart currently running `pid`, if any
+pub fn running_on(pid: Pid) -> Option<usize> {
+    (0..MAX_HARTS).find(|&id| is_online(id) && hart(id).current == Some(pid))
+}
+
+/// Make hart `id` trap into the scheduler
+pub fn kick(id: usize) {
+    if id != this_hart().id {
+        sbi::send_ipi(1, id);
+    }
+}
+
+/// Wake hart `id` if it is idle, to pick up newly queued work
+///
+/// It stops counting as idle right away, so the next runnable process
+/// looks for another hart.
+pub fn wake(id: usize) {
+    let target = hart(id);
+    if target.idle {
+        target.idle = false;
+        kick(id);
+    }
+}
+
+/// Are all other online harts idle?
+pub fn others_idle() -> bool {
+    let me = this_hart().id;
+    (0..MAX_HARTS).all(|id| id == me || !is_online(id) || hart(id).idle)
+}
+
+/// The big kernel lock
+static KERNEL_LOCK: TicketLock<()> = TicketLock::new(());
+
+/// Hart holding the kernel lock (`NO_OWNER` when free)
+static KERNEL_LOCK_OWNER: AtomicUsize = AtomicUsize::new(NO_OWNER);
+
+const NO_OWNER: usize = usize::MAX;
+
+/// Take the big kernel lock, waiting for the harts that asked first
+pub fn lock_kernel() {
+    core::mem::forget(KERNEL_LOCK.lock());
+    KERNEL_LOCK_OWNER.store(this_hart().id, Ordering::Relaxed);
+}
+
+/// Release the big kernel lock
+pub fn unlock_kernel() {
+    KERNEL_LOCK_OWNER.store(NO_OWNER, Ordering::Relaxed);
+    unsafe { KERNEL_LOCK.force_unlock() };
+}
+
+/// Does this hart hold the big kernel lock?
+pub fn holds_kernel_lock() -> bool {
+    KERNEL_LOCK_OWNER.load(Ordering::Relaxed) == this_hart().id
+}
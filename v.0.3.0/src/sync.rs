@@ -0,0 +1,266 @@
+//! Kernel synchronization primitives
+//!
+//! - `SpinLock`: test-and-set lock for short critical sections
+//! - `TicketLock`: FIFO-fair lock for contended ones (the big kernel lock)
+//! - `IrqGuard`: masks interrupts on this hart until dropped;
+//!   `SpinLock::lock_irq` holds one alongside the lock, for data an
+//!   interrupt handler on the same hart also takes
+//! - `OnceCell`: written once during boot, read-only afterwards
+//! - `KernelCell`: state only touched with the big kernel lock held (see
+//!   `smp`), such as the process table and the queues linked through it
+
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+/// Interrupts masked on this hart until dropped
+///
+/// Restores the previous state, so guards nest.
+pub struct IrqGuard {
+    was_enabled: bool,
+}
+
+impl IrqGuard {
+    pub fn new() -> Self {
+        let sstatus: usize;
+        unsafe { asm!("csrrci {}, sstatus, 0x2", out(reg) sstatus) }; // SIE bit
+        IrqGuard { was_enabled: sstatus & 0x2 != 0 }
+    }
+}
+
+impl Drop for IrqGuard {
+    fn drop(&mut self) {
+        if self.was_enabled {
+            unsafe { asm!("csrsi sstatus, 0x2") };
+        }
+    }
+}
+
+/// A lock guard that also keeps interrupts masked
+///
+/// Fields drop in order: the lock is released before interrupts return.
+pub struct IrqLockGuard<G> {
+    guard: G,
+    _irq: IrqGuard,
+}
+
+impl<G: Deref> Deref for IrqLockGuard<G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &G::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for IrqLockGuard<G> {
+    fn deref_mut(&mut self) -> &mut G::Target {
+        &mut self.guard
+    }
+}
+
+/// Test-and-set spinlock
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        SpinLock { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+
+    /// Spin until the lock is ours
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Take the lock if it is free
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinLockGuard { lock: self })
+    }
+
+    /// Mask interrupts, then take the lock
+    pub fn lock_irq(&self) -> IrqLockGuard<SpinLockGuard<'_, T>> {
+        let irq = IrqGuard::new();
+        IrqLockGuard { guard: self.lock(), _irq: irq }
+    }
+
+    /// Release the lock without a guard
+    ///
+    /// # Safety
+    /// Whoever holds the lock must never touch the data again (e.g. it
+    /// panicked while holding it).
+    pub unsafe fn force_unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Ticket lock: waiters get the lock in the order they asked for it
+pub struct TicketLock<T> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+unsafe impl<T: Send> Send for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(value: T) -> Self {
+        TicketLock {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Wait for our turn
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            core::hint::spin_loop();
+        }
+        TicketLockGuard { lock: self }
+    }
+
+    /// Let the next waiter in without a guard
+    ///
+    /// For locks held across code that can't carry a guard, like the big
+    /// kernel lock over a return to user mode.
+    ///
+    /// # Safety
+    /// The caller holds the lock (its guard was forgotten).
+    pub unsafe fn force_unlock(&self) {
+        self.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+pub struct TicketLockGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+}
+
+impl<T> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.lock.force_unlock() };
+    }
+}
+
+const UNINIT: u8 = 0;
+const INITIALIZING: u8 = 1;
+const READY: u8 = 2;
+
+/// A value set once, typically during boot
+pub struct OnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        OnceCell { state: AtomicU8::new(UNINIT), value: UnsafeCell::new(None) }
+    }
+
+    /// Store `value`, or hand it back if the cell was already set
+    pub fn set(&self, value: T) -> Result<&T, T> {
+        if self.state.compare_exchange(UNINIT, INITIALIZING, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return Err(value);
+        }
+        unsafe { *self.value.get() = Some(value) };
+        self.state.store(READY, Ordering::Release);
+        Ok(self.get().unwrap())
+    }
+
+    /// The value, once set
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) != READY {
+            return None;
+        }
+        unsafe { (*self.value.get()).as_ref() }
+    }
+}
+
+/// State guarded by the big kernel lock
+///
+/// Hands out `&mut` like the `static mut`s it replaces, so `get` is
+/// unsafe: the lock keeps other harts out, but nothing stops two borrows
+/// on this hart from overlapping. Each caller states why its borrow is
+/// the only one. Debug builds also check that the lock is held.
+pub struct KernelCell<T> {
+    value: UnsafeCell<T>,
+}
+
+// Only the hart holding the kernel lock gets at the value, so sharing
+// the cell amounts to sending the value between harts
+unsafe impl<T: Send> Sync for KernelCell<T> {}
+
+impl<T> KernelCell<T> {
+    pub const fn new(value: T) -> Self {
+        KernelCell { value: UnsafeCell::new(value) }
+    }
+
+    /// The value, for as long as the caller's borrow lasts
+    ///
+    /// # Safety
+    ///
+    /// The caller holds the big kernel lock and no other borrow of the
+    /// value is live until this one ends.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get(&self) -> &mut T {
+        debug_assert!(crate::smp::holds_kernel_lock(), "kernel state touched without the kernel lock");
+        unsafe { &mut *self.value.get() }
+    }
+}
@@ -0,0 +1,76 @@
+//! Console syscalls
+//!
+//! All of them need the console capability.
+
+use super::{Errno, SysResult};
+use crate::cap::Capability;
+use crate::memory::user::{copy_from_user, copy_to_user};
+use crate::trap::TrapFrame;
+
+/// Most bytes a read or write moves
+///
+/// The UART is slow and the kernel lock is held throughout, so a long
+/// write would stall every other hart; it is cut short instead.
+const CHUNK: usize = 128;
+
+fn check_console() -> Result<(), Errno> {
+    match crate::process::current_leader() {
+        Some(p) if p.caps.has(Capability::Console) => Ok(()),
+        _ => Err(Errno::Perm),
+    }
+}
+
+/// SYS_PUTC: a0 = byte to write (low 8 bits)
+pub fn putc(frame: &mut TrapFrame) -> SysResult {
+    check_console()?;
+    crate::uart::putc(frame.arg(0) as u8);
+    Ok(0)
+}
+
+/// SYS_WRITE: a0 = buffer, a1 = length. Returns how many bytes were
+/// written, at most `CHUNK`
+pub fn write(frame: &mut TrapFrame) -> SysResult {
+    check_console()?;
+    let (buf, len) = (frame.arg(0), frame.arg(1));
+
+    let mut chunk = [0u8; CHUNK];
+    let n = len.min(CHUNK);
+    copy_from_user(&mut chunk[..n], buf)?;
+    crate::uart::write_bytes(&chunk[..n]);
+    Ok(n)
+}
+
+/// SYS_READ: a0 = buffer, a1 = length. Returns how many bytes were read
+///
+/// Blocks until there is input. In cooked mode that is a whole line, and
+/// a read returns at most one line.
+pub fn read(frame: &mut TrapFrame) -> SysResult {
+    check_console()?;
+    let (buf, len) = (frame.arg(0), frame.arg(1));
+    if len == 0 {
+        return Ok(0);
+    }
+
+    let mut chunk = [0u8; CHUNK];
+    let Some(n) = crate::console::peek(&mut chunk[..len.min(CHUNK)]) else {
+        // Nothing typed yet: park until input arrives, then retry
+        crate::console::wait_for_input();
+        return Err(Errno::Restart);
+    };
+    // Input only goes once the reader has it
+    copy_to_user(buf, &chunk[..n])?;
+    crate::console::consume(n);
+    Ok(n)
+}
+
+/// SYS_CONSOLE_MODE: a0 = mode bits to change, a1 = their new values
+/// (see `console::MODE_*`). Returns the previous mode; a0 = 0 just reads
+/// it
+pub fn mode(frame: &mut TrapFrame) -> SysResult {
+    check_console()?;
+    let (mask, value) = (frame.arg(0), frame.arg(1));
+    if mask & !crate::console::MODE_ALL != 0 {
+        return Err(Errno::Inval);
+    }
+    Ok(crate::console::set_mode(mask, value))
+}
@@ -0,0 +1,78 @@
+//! Syscall error numbers
+//!
+//! A failing syscall returns `-errno` in a0, so every error reads as a
+//! value in `-4095..=-1` and a successful result never does. The common
+//! numbers match their POSIX meanings; woflOS-specific ones start at 200.
+
+use crate::memory::user::Fault;
+use crate::process::limits::LimitError;
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+    /// The caller lacks the capability, or the target isn't its own
+    Perm = 1,
+    /// No such program in the initrd
+    NoEnt = 2,
+    /// No such process or thread
+    Srch = 3,
+    /// Too many arguments, environment strings or capabilities
+    TooBig = 7,
+    /// The program isn't a loadable executable
+    NoExec = 8,
+    /// No such child to wait for
+    Child = 10,
+    /// Out of a shared resource; trying again later may work
+    Again = 11,
+    /// The kernel ran out of memory
+    NoMem = 12,
+    /// A user pointer is unmapped or misaligned
+    Fault = 14,
+    /// An argument is out of range
+    Inval = 22,
+    /// The IPC endpoint was closed by its owner
+    Pipe = 32,
+    /// No such syscall
+    NoSys = 38,
+    /// The process' memory (frame) limit is used up
+    FrameLimit = 200,
+    /// The process has as many threads as its limit allows
+    ThreadLimit = 201,
+    /// The process has as many children as its limit allows
+    ChildLimit = 202,
+    /// The process can't hold that many capabilities
+    CapLimit = 203,
+    /// An IPC queue is as deep as its limit allows
+    IpcDepthLimit = 204,
+    /// Not an error: the caller was parked and re-executes the ecall when
+    /// woken. Never reaches user space
+    Restart = 512,
+    /// Not an error: a ring request was accepted and completes later.
+    /// Never reaches user space
+    Pending = 513,
+}
+
+impl Errno {
+    /// The value returned to user space in a0
+    pub fn to_user(self) -> usize {
+        (self as usize).wrapping_neg()
+    }
+}
+
+impl From<LimitError> for Errno {
+    fn from(limit: LimitError) -> Self {
+        match limit {
+            LimitError::Frames => Errno::FrameLimit,
+            LimitError::Threads => Errno::ThreadLimit,
+            LimitError::Children => Errno::ChildLimit,
+            LimitError::Caps => Errno::CapLimit,
+            LimitError::IpcDepth => Errno::IpcDepthLimit,
+        }
+    }
+}
+
+impl From<Fault> for Errno {
+    fn from(_: Fault) -> Self {
+        Errno::Fault
+    }
+}
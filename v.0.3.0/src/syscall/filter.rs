@@ -0,0 +1,186 @@
+//! Per-process syscall filters
+//!
+//! A filter is an allowlist: the syscalls a process may make, each
+//! optionally guarded by predicates on its arguments. A call the filter
+//! doesn't allow is denied with `Perm` or kills the process, as the
+//! filter's action says.
+//!
+//! Filters only ever tighten. A process installs one on itself with
+//! SYS_FILTER or has one installed by its parent at spawn, and a new
+//! filter is combined with the one already in place: a call must pass
+//! both, and the stricter action wins. Children and threads inherit the
+//! filter, and so does the replacement a supervisor starts.
+//!
+//! User memory layout (all words):
+//!
+//! ```text
+//! action, count, rules          action 0 = deny with -EPERM, 1 = kill
+//! rules[i] = num, op, arg, value
+//! ```
+//!
+//! A rule allows syscall `num`; with `op` other than `OP_ALLOW` it also
+//! requires `args[arg] <op> value` (unsigned). All predicates on one
+//! syscall must hold.
+
+use super::{Errno, SysResult, SYSCALLS};
+use crate::memory::user::read_word;
+use crate::process::ExitReason;
+use crate::trap::TrapFrame;
+
+/// Most rules a filter may be given in one go
+pub const MAX_RULES: usize = 32;
+
+/// Most argument predicates a process' combined filter holds
+pub const MAX_PREDICATES: usize = 8;
+
+/// Rule operators
+const OP_ALLOW: usize = 0; // no predicate
+const OP_EQ: usize = 1;
+const OP_NE: usize = 2;
+const OP_LT: usize = 3;
+const OP_LE: usize = 4;
+const OP_GT: usize = 5;
+const OP_GE: usize = 6;
+const OP_MASK_CLEAR: usize = 7; // none of the bits in `value` set
+
+/// `allowed` has one bit per table entry
+const _: () = assert!(SYSCALLS.len() <= 64);
+
+/// What happens to a call the filter doesn't allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Deny,
+    Kill,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Predicate {
+    /// Index into `SYSCALLS`
+    index: usize,
+    arg: usize,
+    op: usize,
+    value: usize,
+}
+
+impl Predicate {
+    fn holds(&self, frame: &TrapFrame) -> bool {
+        let arg = frame.arg(self.arg);
+        match self.op {
+            OP_EQ => arg == self.value,
+            OP_NE => arg != self.value,
+            OP_LT => arg < self.value,
+            OP_LE => arg <= self.value,
+            OP_GT => arg > self.value,
+            OP_GE => arg >= self.value,
+            OP_MASK_CLEAR => arg & self.value == 0,
+            _ => false,
+        }
+    }
+}
+
+/// A process' syscall filter
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    /// Bit i allows `SYSCALLS[i]`
+    allowed: u64,
+    predicates: [Option<Predicate>; MAX_PREDICATES],
+    action: Action,
+}
+
+impl Filter {
+    /// What a call to table entry `index` (`None` = unknown number) runs
+    /// into: `None` if it is allowed
+    pub fn check(&self, index: Option<usize>, frame: &TrapFrame) -> Option<Action> {
+        let allowed = index.is_some_and(|index| {
+            self.allowed & 1 << index != 0
+                && self.predicates.iter().flatten().filter(|p| p.index == index).all(|p| p.holds(frame))
+        });
+        (!allowed).then_some(self.action)
+    }
+
+    /// The filter that passes only what both `self` and `other` pass
+    ///
+    /// Fails if the predicates don't all fit.
+    pub fn restrict(&self, other: &Filter) -> Result<Filter, Errno> {
+        let mut combined = Filter { allowed: self.allowed & other.allowed, ..*self };
+        combined.action = self.action.max(other.action);
+        for &predicate in other.predicates.iter().flatten() {
+            combined.add_predicate(predicate)?;
+        }
+        Ok(combined)
+    }
+
+    fn add_predicate(&mut self, predicate: Predicate) -> Result<(), Errno> {
+        let slot = self.predicates.iter_mut().find(|slot| slot.is_none()).ok_or(Errno::TooBig)?;
+        *slot = Some(predicate);
+        Ok(())
+    }
+
+    /// Read a filter from user memory at `ptr`
+    pub fn from_user(ptr: usize) -> Result<Filter, Errno> {
+        let action = match read_word(ptr, 0)? {
+            0 => Action::Deny,
+            1 => Action::Kill,
+            _ => return Err(Errno::Inval),
+        };
+        let (count, rules) = (read_word(ptr, 1)?, read_word(ptr, 2)?);
+        if count > MAX_RULES {
+            return Err(Errno::TooBig);
+        }
+
+        let mut filter = Filter { allowed: 0, predicates: [None; MAX_PREDICATES], action };
+        for i in 0..count {
+            let word = |k: usize| read_word(rules, 4 * i + k);
+            let (num, op, arg, value) = (word(0)?, word(1)?, word(2)?, word(3)?);
+            let index = SYSCALLS.binary_search_by_key(&num, |s| s.num).map_err(|_| Errno::Inval)?;
+            if op > OP_MASK_CLEAR || (op != OP_ALLOW && arg >= 6) {
+                return Err(Errno::Inval);
+            }
+            filter.allowed |= 1 << index;
+            if op != OP_ALLOW {
+                filter.add_predicate(Predicate { index, arg, op, value })?;
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// `filter` tightened by `new`, for a process that may already have one
+pub fn combine(filter: Option<Filter>, new: Filter) -> Result<Filter, Errno> {
+    match filter {
+        Some(filter) => filter.restrict(&new),
+        None => Ok(new),
+    }
+}
+
+/// Deal with syscall `num`, which the current process' filter refused
+pub fn violation(action: Action, num: usize, frame: &TrapFrame) -> SysResult {
+    let pid = crate::process::current_pid().unwrap_or(0);
+    match action {
+        Action::Deny => {
+            crate::kprintln!("[FILTER] PID {} denied syscall {}", pid, num);
+            Err(Errno::Perm)
+        }
+        // Killed as though the ecall itself had faulted, with the
+        // syscall number in place of a faulting address
+        Action::Kill => {
+            crate::kprintln!("[FILTER] PID {} killed for syscall {}", pid, num);
+            crate::process::terminate_current(ExitReason::Faulted { cause: 8, pc: frame.sepc - 4, addr: num });
+            Ok(0)
+        }
+    }
+}
+
+/// SYS_FILTER: a0 = pointer to a filter. Returns 0
+///
+/// Tightens the calling process' filter (all of its threads) by the one
+/// given.
+pub fn install(frame: &mut TrapFrame) -> SysResult {
+    let new = Filter::from_user(frame.arg(0))?;
+    let current = crate::process::current_process().ok_or(Errno::Srch)?;
+    let (tgid, filter) = (current.tgid, combine(current.filter, new)?);
+    for thread in crate::process::iter().filter(|p| p.tgid == tgid) {
+        thread.filter = Some(filter);
+    }
+    Ok(0)
+}
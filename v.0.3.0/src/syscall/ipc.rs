@@ -0,0 +1,132 @@
+//! IPC endpoint syscalls
+//!
+//! Endpoints are named by capability handle; holding the capability is
+//! all it takes to send and receive.
+//!
+//! SYS_CALL and SYS_REPLY_RECV move a message each way through one
+//! buffer. Their a2 holds two sizes: the length of the message going
+//! out in the low 32 bits, the room for the one coming back (written
+//! over it) in the high 32.
+
+use super::{Errno, SysResult};
+use crate::cap::Capability;
+use crate::ipc::{EndpointId, Message, Via};
+use crate::process::limits::LimitError;
+use crate::trap::TrapFrame;
+
+/// The endpoint behind the caller's capability `handle`
+fn endpoint(handle: usize) -> Result<EndpointId, Errno> {
+    match crate::process::current_leader().and_then(|p| p.caps.get(handle)) {
+        Some(Capability::Endpoint(id)) => Ok(id),
+        _ => Err(Errno::Perm),
+    }
+}
+
+/// SYS_ENDPOINT: create an endpoint. Returns the capability handle for it
+///
+/// The endpoint belongs to the caller and closes when it exits.
+pub fn create(_frame: &mut TrapFrame) -> SysResult {
+    let leader = crate::process::current_leader().ok_or(Errno::Srch)?;
+    let id = crate::ipc::create(leader.pid).ok_or(Errno::Again)?;
+    leader.caps.insert(Capability::Endpoint(id)).ok_or_else(|| {
+        crate::ipc::close(id);
+        Errno::from(LimitError::Caps)
+    })
+}
+
+/// SYS_SEND: a0 = endpoint handle, a1 = buffer, a2 = length, a3-a5 =
+/// message words. Returns 0 once a receiver has the message
+///
+/// Blocks until somebody receives it. Fails with -EPIPE if the endpoint
+/// closes first.
+pub fn send(frame: &mut TrapFrame) -> SysResult {
+    send_via(frame, Via::Trap)
+}
+
+/// SYS_SEND made `via` a trap or a ring
+pub fn send_via(frame: &mut TrapFrame, via: Via) -> SysResult {
+    let id = endpoint(frame.arg(0))?;
+    crate::ipc::send(id, message(frame, frame.arg(2)), None, via).map(|()| 0)
+}
+
+/// SYS_RECV: a0 = endpoint handle, a1 = buffer, a2 = its size. Returns
+/// the message length, with the sender's PID in a1, its thread ID in a2
+/// and the message words in a3-a5
+///
+/// Blocks until a message arrives. A length above the buffer size means
+/// the rest was dropped. Receiving a SYS_CALL message makes the calling
+/// thread the one to answer it, with SYS_REPLY_RECV.
+pub fn recv(frame: &mut TrapFrame) -> SysResult {
+    recv_via(frame, Via::Trap)
+}
+
+/// SYS_RECV made `via` a trap or a ring
+pub fn recv_via(frame: &mut TrapFrame, via: Via) -> SysResult {
+    let id = endpoint(frame.arg(0))?;
+    receive(frame, id, frame.arg(2), via)
+}
+
+/// SYS_CALL: a0 = endpoint handle, a1 = buffer, a2 = sizes, a3-a5 =
+/// message words. Returns the answer as SYS_RECV does
+///
+/// Sends like SYS_SEND, then blocks until the receiver answers. Fails
+/// with -EPIPE if the endpoint closes or the receiver goes away without
+/// answering.
+pub fn call(frame: &mut TrapFrame) -> SysResult {
+    call_via(frame, Via::Trap)
+}
+
+/// SYS_CALL made `via` a trap or a ring
+pub fn call_via(frame: &mut TrapFrame, via: Via) -> SysResult {
+    let id = endpoint(frame.arg(0))?;
+    let (len, capacity) = sizes(frame.arg(2));
+    // The one who answers fills in our registers
+    crate::ipc::send(id, message(frame, len), Some(capacity), via).map(|()| 0)
+}
+
+/// SYS_REPLY_RECV: a0 = endpoint handle, a1 = buffer, a2 = sizes, a3-a5
+/// = answer words. Returns the next message as SYS_RECV does
+///
+/// Answers the last call the calling thread received, if it has one
+/// left to answer, then receives on the endpoint.
+pub fn reply_recv(frame: &mut TrapFrame) -> SysResult {
+    reply_recv_via(frame, Via::Trap)
+}
+
+/// SYS_REPLY_RECV made `via` a trap or a ring
+pub fn reply_recv_via(frame: &mut TrapFrame, via: Via) -> SysResult {
+    let id = endpoint(frame.arg(0))?;
+    let (len, capacity) = sizes(frame.arg(2));
+    let current = crate::process::current_process().ok_or(Errno::Srch)?;
+    if let Some(reply) = current.reply.take() {
+        if let Err(e) = crate::ipc::reply(reply, message(frame, len)) {
+            current.reply = Some(reply);
+            return Err(e);
+        }
+    }
+    receive(frame, id, capacity, via)
+}
+
+/// The outgoing message: buffer in a1, words in a3-a5
+fn message(frame: &TrapFrame, len: usize) -> Message {
+    Message { buf: frame.arg(1), len, words: [frame.arg(3), frame.arg(4), frame.arg(5)] }
+}
+
+/// Split a2 of SYS_CALL / SYS_REPLY_RECV: (message length, room for the
+/// answer)
+fn sizes(sizes: usize) -> (usize, usize) {
+    (sizes & 0xffff_ffff, sizes >> 32)
+}
+
+/// Receive into the buffer in a1, returning what SYS_RECV does
+fn receive(frame: &mut TrapFrame, id: EndpointId, capacity: usize, via: Via) -> SysResult {
+    match crate::ipc::recv(id, frame.arg(1), capacity, via)? {
+        Some(received) => {
+            let registers = received.registers();
+            frame.regs[10..15].copy_from_slice(&registers[1..]);
+            Ok(registers[0])
+        }
+        // The sender fills in our registers
+        None => Ok(0),
+    }
+}
@@ -1,34 +1,210 @@
-//! Syscall definitions for Layer 1 bring-up.
+//! Syscall numbers and dispatch
 //!
-//! woflOS is “distributed-native” from day 1, but Layer 1 only needs a
-//! *minimal* syscall surface so we can prove:
+//! Convention: number in a7, arguments in a0-a5, result in a0 (a few
+//! calls also return a second value in a1). A failing call returns
+//! `-errno` (see `Errno`), so results in `-4095..=-1` are errors.
 //!
-//! Boot → enter U-mode → `ecall` → trap → handle syscall → return → exit.
+//! `SYSCALLS` is the one authoritative table: each number maps to its
+//! name and argument names (for the trace) and handler. Handlers live in
+//! the submodules, grouped by subsystem, and return a `SysResult`;
+//! `dispatch` writes it back to a0 and records the call if the process
+//! is traced (see `trace`).
 //!
-//! Everything else (IPC, capabilities, networking) will be layered on later.
+//! woflOS is “distributed-native” from day 1: the remote numbers are
+//! reserved now and answer `NoSys` until that layer lands.
+
+mod console;
+mod errno;
+mod filter;
+mod ipc;
+mod process;
+mod ring;
+mod sync;
+mod thread;
+mod time;
+mod trace;
+
+pub use errno::Errno;
+pub use filter::Filter;
+pub use trace::Trace;
+
+use crate::ring::Sqe;
+use crate::trap::TrapFrame;
 
 /// Layer 1 core syscalls
 pub const SYS_TEST: usize = 0; // return 42
-pub const SYS_EXIT: usize = 1; // halt for now
+pub const SYS_PUTC: usize = 1; // write one byte to the serial console
+pub const SYS_EXIT: usize = 2; // terminate the calling process
+pub const SYS_GETPID: usize = 3; // PID of the calling process
+pub const SYS_YIELD: usize = 4; // give up the CPU
+
+/// IPC
+pub const SYS_SEND: usize = 10; // send a message on an endpoint, blocking until received
+pub const SYS_RECV: usize = 11; // receive a message on an endpoint, blocking until one comes
+pub const SYS_ENDPOINT: usize = 12; // create an endpoint
+pub const SYS_CALL: usize = 13; // send a message and wait for the answer
+pub const SYS_REPLY_RECV: usize = 14; // answer the last call received, then receive
+
+/// Process management
+pub const SYS_WAIT: usize = 20; // wait for a child to exit
+pub const SYS_PROC_STATS: usize = 21; // CPU time and syscall counts for a PID
+pub const SYS_SPAWN: usize = 22; // start an initrd program with args, env and capabilities
+pub const SYS_THREAD_CREATE: usize = 23; // start a thread in the calling process
+pub const SYS_THREAD_EXIT: usize = 24; // end the calling thread
+pub const SYS_THREAD_JOIN: usize = 25; // wait for a thread and collect its exit value
+pub const SYS_SUPERVISE: usize = 26; // restart a child automatically when it dies
+pub const SYS_AFFINITY: usize = 27; // read or set which harts a thread may run on
+pub const SYS_FILTER: usize = 28; // restrict which syscalls the caller may make
+
+/// Time
+pub const SYS_SLEEP: usize = 30; // block for a number of nanoseconds
+pub const SYS_CLOCK_GET: usize = 31; // read a clock in nanoseconds
+
+/// Synchronization
+pub const SYS_FUTEX_WAIT: usize = 40; // sleep while a user word holds a value
+pub const SYS_FUTEX_WAKE: usize = 41; // wake sleepers on a user word
+pub const SYS_RING_SETUP: usize = 42; // map the caller's submission/completion ring
+pub const SYS_RING_ENTER: usize = 43; // run ring submissions, wait for completions
+
+/// Console
+pub const SYS_WRITE: usize = 50; // write a buffer to the console
+pub const SYS_READ: usize = 51; // read console input, blocking until there is some
+pub const SYS_CONSOLE_MODE: usize = 52; // switch cooked/raw input and echo
 
-/// Reserved for Layer 3 IPC
-pub const SYS_SEND: usize = 10;
-pub const SYS_RECV: usize = 11;
+/// Debugging
+pub const SYS_TRACE: usize = 60; // start or stop tracing a process' syscalls
+pub const SYS_TRACE_READ: usize = 61; // copy out trace records
 
 /// Reserved for Layer 6+ distributed operations
 pub const SYS_SEND_REMOTE: usize = 1000;
 pub const SYS_RECV_REMOTE: usize = 1001;
 pub const SYS_NODE_DISCOVER: usize = 1010;
 
-pub fn syscall_name(n: usize) -> &'static str {
-    match n {
-        SYS_TEST => "SYS_TEST",
-        SYS_EXIT => "SYS_EXIT",
-        SYS_SEND => "SYS_SEND",
-        SYS_RECV => "SYS_RECV",
-        SYS_SEND_REMOTE => "SYS_SEND_REMOTE",
-        SYS_RECV_REMOTE => "SYS_RECV_REMOTE",
-        SYS_NODE_DISCOVER => "SYS_NODE_DISCOVER",
-        _ => "SYS_UNKNOWN",
+/// What a handler hands back for a0
+pub type SysResult = Result<usize, Errno>;
+
+/// One row of the syscall table
+struct Syscall {
+    num: usize,
+    name: &'static str,
+    /// Argument names, for the trace
+    args: &'static [&'static str],
+    handler: fn(&mut TrapFrame) -> SysResult,
+}
+
+/// Build the table from `NUMBER(arg, ...) => handler` rows
+macro_rules! syscalls {
+    ($($num:ident($($arg:ident),*) => $handler:path,)*) => {
+        &[$(Syscall { num: $num, name: stringify!($num), args: &[$(stringify!($arg)),*], handler: $handler }),*]
+    };
+}
+
+/// Every syscall, sorted by number
+static SYSCALLS: &[Syscall] = syscalls! {
+    SYS_TEST() => sys_test,
+    SYS_PUTC(byte) => console::putc,
+    SYS_EXIT(code) => process::exit,
+    SYS_GETPID() => process::getpid,
+    SYS_YIELD() => process::yield_now,
+    SYS_SEND(endpoint, buf, len, w0, w1, w2) => ipc::send,
+    SYS_RECV(endpoint, buf, len) => ipc::recv,
+    SYS_ENDPOINT() => ipc::create,
+    SYS_CALL(endpoint, buf, sizes, w0, w1, w2) => ipc::call,
+    SYS_REPLY_RECV(endpoint, buf, sizes, w0, w1, w2) => ipc::reply_recv,
+    SYS_WAIT(pid, info) => process::wait,
+    SYS_PROC_STATS(pid, buf) => process::proc_stats,
+    SYS_SPAWN(request) => process::spawn,
+    SYS_THREAD_CREATE(entry, arg, tp) => thread::create,
+    SYS_THREAD_EXIT(value) => thread::exit,
+    SYS_THREAD_JOIN(tid) => thread::join,
+    SYS_SUPERVISE(pid, policy, max, window) => process::supervise,
+    SYS_AFFINITY(tid, mask) => process::affinity,
+    SYS_FILTER(filter) => filter::install,
+    SYS_SLEEP(ns) => time::sleep,
+    SYS_CLOCK_GET(clock) => time::clock_get,
+    SYS_FUTEX_WAIT(addr, expected, timeout) => sync::futex_wait,
+    SYS_FUTEX_WAKE(addr, count) => sync::futex_wake,
+    SYS_RING_SETUP() => ring::setup,
+    SYS_RING_ENTER(submit, wait) => ring::enter,
+    SYS_WRITE(buf, len) => console::write,
+    SYS_READ(buf, len) => console::read,
+    SYS_CONSOLE_MODE(mask, mode) => console::mode,
+    SYS_TRACE(pid, flags) => trace::trace,
+    SYS_TRACE_READ(from, buf, count) => trace::read,
+    SYS_SEND_REMOTE(endpoint, buf, len) => not_yet_remote,
+    SYS_RECV_REMOTE(endpoint, buf, len) => not_yet_remote,
+    SYS_NODE_DISCOVER() => not_yet_remote,
+};
+
+fn lookup(num: usize) -> Option<&'static Syscall> {
+    SYSCALLS.binary_search_by_key(&num, |s| s.num).ok().map(|i| &SYSCALLS[i])
+}
+
+/// Handle an `ecall` from U-mode
+pub fn dispatch(frame: &mut TrapFrame) {
+    // Advance sepc past the ecall instruction (4 bytes) up front, so a
+    // process that blocks here resumes *after* the ecall once woken
+    frame.sepc += 4;
+
+    match execute(frame, |syscall, frame| (syscall.handler)(frame)) {
+        Ok(value) => frame.regs[9] = value,
+        // Parked: run the ecall again once woken, arguments intact
+        Err(Errno::Restart) => frame.sepc -= 4,
+        Err(errno) => frame.regs[9] = errno.to_user(),
+    }
+}
+
+/// Run a ring request as though the current process had made it as a
+/// syscall (the rest of `frame` as its context)
+///
+/// a6 carries the request's `user_data`, for one that completes later.
+/// Returns the result with a1-a5 as the syscall left them.
+fn submit(frame: &TrapFrame, sqe: &Sqe) -> (SysResult, [usize; 5]) {
+    let mut scratch = TrapFrame { regs: frame.regs, sepc: frame.sepc, sstatus: frame.sstatus, sscratch: frame.sscratch };
+    for (reg, &arg) in scratch.regs[9..15].iter_mut().zip(&sqe.args) {
+        *reg = arg as usize;
+    }
+    scratch.regs[15] = sqe.user_data as usize;
+    scratch.regs[16] = sqe.num as usize;
+    let result = execute(&mut scratch, ring::run);
+    (result, scratch.regs[10..15].try_into().unwrap_or_default())
+}
+
+/// Run the syscall in `frame` (number in a7) through `handler`, with
+/// the accounting, filtering and tracing every call gets
+fn execute(frame: &mut TrapFrame, handler: impl FnOnce(&Syscall, &mut TrapFrame) -> SysResult) -> SysResult {
+    let num = frame.regs[16]; // a7
+    let index = SYSCALLS.binary_search_by_key(&num, |s| s.num).ok();
+
+    let mut call = None;
+    let mut denied = None;
+    if let Some(process) = crate::process::current_process() {
+        process.stats.count_syscall(num);
+        call = trace::begin(process, num, frame);
+        denied = process.filter.as_ref().and_then(|filter| filter.check(index, frame));
     }
+
+    let result = match (denied, index) {
+        (Some(action), _) => filter::violation(action, num, frame),
+        (None, Some(index)) => handler(&SYSCALLS[index], frame),
+        (None, None) => {
+            crate::kprintln!("[SYSCALL] Unknown syscall: {}", num);
+            Err(Errno::NoSys)
+        }
+    };
+
+    if let Some(call) = call {
+        call.finish(result);
+    }
+    result
+}
+
+fn sys_test(_frame: &mut TrapFrame) -> SysResult {
+    crate::kprintln!("[SYSCALL] Test syscall from user mode - SUCCESS! 🐺");
+    Ok(42)
+}
+
+fn not_yet_remote(_frame: &mut TrapFrame) -> SysResult {
+    crate::kprintln!("[SYSCALL] Distributed operation not yet implemented (Layer 6 feature)");
+    Err(Errno::NoSys)
 }
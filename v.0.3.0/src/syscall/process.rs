@@ -0,0 +1,240 @@
+//! Process lifecycle syscalls
+
+use super::filter::{self, Filter};
+use super::{Errno, SysResult};
+use crate::cap::{CapTable, Capability, MAX_CAPS};
+use crate::loader::args::{Args, ArgsError, MAX_ARGS, MAX_ENV};
+use crate::loader::elf::ElfError;
+use crate::memory::user::{copy_from_user, copy_to_user, read_word};
+use crate::process::limits::{LIMIT_WORDS, LimitError, Limits};
+use crate::process::stats::ProcStats;
+use crate::process::supervisor::RestartPolicy;
+use crate::process::ExitReason;
+use crate::trap::TrapFrame;
+
+/// SYS_EXIT: a0 = exit code
+pub fn exit(frame: &mut TrapFrame) -> SysResult {
+    let exit_code = frame.arg(0);
+    if let Some(process) = crate::process::current_process() {
+        crate::kprintln!("[SYSCALL] Process {} '{}' exit (code: {})", process.pid, process.name, exit_code);
+        crate::kprintln!("[SYSCALL]   cpu: user={} kernel={} ticks, {} switches, {} syscalls",
+            process.stats.user_ticks, process.stats.kernel_ticks,
+            process.stats.context_switches, process.stats.syscalls_total);
+    }
+    crate::process::exit_current(exit_code);
+    Ok(0)
+}
+
+/// SYS_GETPID: returns the caller's process ID (its main thread's ID, in
+/// any thread)
+pub fn getpid(_frame: &mut TrapFrame) -> SysResult {
+    crate::process::current_leader().map(|p| p.pid).ok_or(Errno::Srch)
+}
+
+/// SYS_YIELD: let other processes run. Returns 0
+pub fn yield_now(_frame: &mut TrapFrame) -> SysResult {
+    crate::process::scheduler::request_resched();
+    Ok(0)
+}
+
+/// SYS_WAIT: a0 = child PID to wait for (0 = any child), a1 = exit info
+/// buffer (0 = none)
+///
+/// Returns the child PID in a0 and its exit code in a1. With a1 set, also
+/// fills in six words there: reason (0 = exited, 1 = faulted), exit code,
+/// exception cause, pc and address of a fault, and the PID its supervisor
+/// restarted it as (0 = none).
+pub fn wait(frame: &mut TrapFrame) -> SysResult {
+    let parent = crate::process::current_leader().ok_or(Errno::Child)?;
+    let (target, info) = (frame.arg(0), frame.arg(1));
+
+    match crate::process::reap_child(parent.pid, target) {
+        Ok(Some(status)) => {
+            let (kind, cause, pc, addr) = match status.reason {
+                ExitReason::Exited(_) => (0, 0, 0, 0),
+                ExitReason::Faulted { cause, pc, addr } => (1, cause, pc, addr),
+            };
+            let words = [kind, status.reason.code(), cause, pc, addr, status.restarted_as.unwrap_or(0)];
+            let mut bytes = [0u8; 48];
+            for (chunk, word) in bytes.as_chunks_mut::<8>().0.iter_mut().zip(words) {
+                *chunk = word.to_le_bytes();
+            }
+            // The child is reaped either way; a bad buffer only loses the details
+            if info != 0 && copy_to_user(info, &bytes).is_err() {
+                crate::kprintln!("[SYSCALL] wait: bad exit info buffer {:#x}", info);
+            }
+            frame.regs[10] = status.reason.code();
+            Ok(status.pid)
+        }
+        Ok(None) => {
+            // Nothing to collect yet: park until a child exits, then
+            // re-execute the ecall to try again
+            parent.child_exit.park_current();
+            Err(Errno::Restart)
+        }
+        Err(()) => Err(Errno::Child),
+    }
+}
+
+/// SYS_PROC_STATS: a0 = PID (0 = caller), a1 = pointer to a `ProcStats`
+/// buffer. Returns 0
+///
+/// The target is one of the caller's threads or a descendant; a
+/// supervisor is always its child's parent, so it can watch it too.
+pub fn proc_stats(frame: &mut TrapFrame) -> SysResult {
+    let caller = crate::process::current_process().ok_or(Errno::Srch)?;
+    let pid = match frame.arg(0) {
+        0 => caller.pid,
+        pid => pid,
+    };
+    let buf = frame.arg(1);
+
+    let process = crate::process::get(pid).ok_or(Errno::Srch)?;
+    if !crate::process::descends_from(pid, caller.tgid) {
+        return Err(Errno::Perm);
+    }
+    let stats = process.stats;
+    // All u64 fields, so no padding bytes to leak
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&stats as *const ProcStats as *const u8, core::mem::size_of::<ProcStats>())
+    };
+    copy_to_user(buf, bytes)?;
+    Ok(0)
+}
+
+/// SYS_SUPERVISE: a0 = child PID, a1 = policy (0 never, 1 always, 2 on
+/// failure), a2 = max restarts per window (0 = no limit), a3 = window in
+/// ms (0 = lifetime). Returns 0
+pub fn supervise(frame: &mut TrapFrame) -> SysResult {
+    let (child, policy, max, window) = (frame.arg(0), frame.arg(1), frame.arg(2), frame.arg(3));
+    let parent = crate::process::current_leader().ok_or(Errno::Srch)?.pid;
+    let policy = RestartPolicy::from_raw(policy).ok_or(Errno::Inval)?;
+    crate::process::supervisor::supervise(parent, child, policy, max, window as u64).map_err(|()| Errno::Srch)?;
+    Ok(0)
+}
+
+/// SYS_AFFINITY: a0 = thread ID (0 = caller), a1 = hart mask (0 = just
+/// read it). Returns the previous mask
+///
+/// The target is one of the caller's threads or a child process' main
+/// thread.
+pub fn affinity(frame: &mut TrapFrame) -> SysResult {
+    let (tid, mask) = (frame.arg(0), frame.arg(1));
+    let caller = crate::process::current_process().ok_or(Errno::Srch)?;
+    let target = match tid {
+        0 => caller.pid,
+        tid => tid,
+    };
+    let process = crate::process::get(target).ok_or(Errno::Srch)?;
+    if process.tgid != caller.tgid && process.parent != Some(caller.tgid) {
+        return Err(Errno::Perm);
+    }
+    // Only fails for a mask without any online hart
+    crate::process::scheduler::set_affinity(target, mask).ok_or(Errno::Inval)
+}
+
+/// SYS_SPAWN: a0 = pointer to a spawn request; returns the child PID
+///
+/// Starts an initrd program on behalf of the caller. The request is ten
+/// words in user memory:
+///
+/// ```text
+/// path, path_len      program name in the initrd
+/// argv, argc          array of (ptr, len) strings
+/// envp, envc          array of (ptr, len) `KEY=value` strings
+/// caps, ncaps         array of the caller's capability handles to grant
+/// limits              `Limits` for the child, 0 for the caller's own
+/// filter              syscall filter for the child (see `filter`), 0 for none
+/// ```
+///
+/// Requires the spawn capability. The child's handles are numbered from 0
+/// in the order given. Its limits never exceed the caller's. A caller
+/// tracing its children (`TRACE_CHILDREN`) has the child traced from its
+/// first syscall. The child inherits the caller's syscall filter,
+/// tightened by the one given.
+pub fn spawn(frame: &mut TrapFrame) -> SysResult {
+    let request = frame.arg(0);
+    let parent = crate::process::current_leader().ok_or(Errno::Srch)?;
+    if !parent.caps.has(Capability::Spawn) {
+        crate::kprintln!("[SPAWN] PID {} lacks the spawn capability", parent.pid);
+        return Err(Errno::Perm);
+    }
+
+    let field = |i: usize| read_word(request, i);
+    let (path, path_len) = (field(0)?, field(1)?);
+    let (argv, argc) = (field(2)?, field(3)?);
+    let (envp, envc) = (field(4)?, field(5)?);
+    let (caps, ncaps) = (field(6)?, field(7)?);
+    let (limits, filter) = (field(8)?, field(9)?);
+    if argc > MAX_ARGS || envc > MAX_ENV || ncaps > MAX_CAPS {
+        return Err(Errno::TooBig);
+    }
+
+    let limits = match limits {
+        0 => parent.limits,
+        ptr => {
+            let mut requested = [0usize; LIMIT_WORDS];
+            for (i, word) in requested.iter_mut().enumerate() {
+                *word = read_word(ptr, i)?;
+            }
+            let [frames, threads, children, caps, ipc_depth, cpu_percent] = requested;
+            parent.limits.narrow(&Limits { frames, threads, children, caps, ipc_depth, cpu_percent })
+        }
+    };
+    let filter = match filter {
+        0 => parent.filter,
+        ptr => Some(filter::combine(parent.filter, Filter::from_user(ptr)?)?),
+    };
+    let children = crate::process::iter().filter(|p| p.parent == Some(parent.pid)).count();
+    if children >= parent.limits.children {
+        return Err(LimitError::Children.into());
+    }
+
+    // Longer than any name the initrd holds
+    let mut name = [0u8; 64];
+    let name = name.get_mut(..path_len).ok_or(Errno::NoEnt)?;
+    copy_from_user(name, path)?;
+    let name = core::str::from_utf8(name).map_err(|_| Errno::NoEnt)?;
+    let file = crate::initrd::get().and_then(|initrd| initrd.find(name)).ok_or(Errno::NoEnt)?;
+
+    // An unreadable string or one holding a NUL is an invalid argument
+    let args_errno = |e: ArgsError| match e {
+        ArgsError::TooMany | ArgsError::TooLarge => Errno::TooBig,
+        ArgsError::BadString => Errno::Inval,
+    };
+    let mut args = Args::new().ok_or(Errno::NoMem)?;
+    let string = |array: usize, i: usize| Ok::<_, Errno>((read_word(array, 2 * i)?, read_word(array, 2 * i + 1)?));
+    for i in 0..argc {
+        let (ptr, len) = string(argv, i)?;
+        args.push_arg_with(len, |buf| copy_from_user(buf, ptr).map_err(|_| ArgsError::BadString))
+            .map_err(args_errno)?;
+    }
+    for i in 0..envc {
+        let (ptr, len) = string(envp, i)?;
+        args.push_env_with(len, |buf| copy_from_user(buf, ptr).map_err(|_| ArgsError::BadString))
+            .map_err(args_errno)?;
+    }
+
+    let mut granted = CapTable::with_limit(limits.caps);
+    for i in 0..ncaps {
+        let handle = read_word(caps, i)?;
+        let cap = parent.caps.get(handle).ok_or(Errno::Inval)?;
+        granted.insert(cap).ok_or(Errno::from(LimitError::Caps))?;
+    }
+
+    let child = crate::loader::spawn(file.name, file.data, Some(parent.pid), args, granted, limits).map_err(|e| {
+        crate::kprintln!("[SPAWN] {}: {}", file.name, e);
+        match e {
+            ElfError::FrameLimit => LimitError::Frames.into(),
+            ElfError::OutOfMemory => Errno::NoMem,
+            _ => Errno::NoExec,
+        }
+    })?;
+    // The child can't make a syscall before we drop the kernel lock, so
+    // none of them is missed
+    if let Some(process) = crate::process::get(child) {
+        process.trace = parent.trace.and_then(|trace| trace.inherited());
+        process.filter = filter;
+    }
+    Ok(child)
+}
@@ -0,0 +1,90 @@
+//! Submission/completion ring syscalls
+
+use super::{
+    Errno, SysResult, Syscall, SYS_CALL, SYS_EXIT, SYS_FUTEX_WAIT, SYS_READ, SYS_RECV, SYS_REPLY_RECV,
+    SYS_RING_ENTER, SYS_RING_SETUP, SYS_SEND, SYS_SLEEP, SYS_THREAD_EXIT, SYS_THREAD_JOIN, SYS_WAIT, SYS_YIELD,
+};
+use crate::ipc::Via;
+use crate::process::{stats, ProcessState};
+use crate::ring::{Ring, CQ_ENTRIES};
+use crate::trap::TrapFrame;
+
+/// Syscalls a ring won't run: they end or block the caller itself
+const NOT_IN_RING: &[usize] = &[
+    SYS_EXIT, SYS_YIELD, SYS_WAIT, SYS_THREAD_EXIT, SYS_THREAD_JOIN, SYS_FUTEX_WAIT, SYS_READ, SYS_RING_SETUP,
+    SYS_RING_ENTER,
+];
+
+/// SYS_RING_SETUP: returns the address of the caller's ring, mapping it
+/// on first use
+pub fn setup(_frame: &mut TrapFrame) -> SysResult {
+    Ring::setup().map_err(|limit| limit.map_or(Errno::NoMem, Errno::from))
+}
+
+/// SYS_RING_ENTER: a0 = most submissions to run, a1 = completions to wait
+/// for. Returns how many completions are waiting to be consumed
+///
+/// Each request's result is posted as it completes. A request the
+/// process' filter forbids completes with -EPERM, or kills the process.
+pub fn enter(frame: &mut TrapFrame) -> SysResult {
+    let (to_submit, min_complete) = (frame.arg(0), frame.arg(1).min(CQ_ENTRIES));
+    let tgid = crate::process::current_process().ok_or(Errno::Srch)?.tgid;
+    // Handlers may touch the process table, so look the ring up afresh
+    let ring = || crate::process::get(tgid).and_then(|p| p.ring.as_mut());
+    ring().ok_or(Errno::Inval)?;
+
+    let mut submitted = 0;
+    while submitted < to_submit {
+        let Some(sqe) = ring().and_then(|ring| ring.next_submission()) else { break };
+        submitted += 1;
+
+        let (result, [a1, a2, a3, a4, a5]) = super::submit(frame, &sqe);
+        if crate::process::current_process().is_none_or(|p| p.state == ProcessState::Dead) {
+            return Ok(0); // Killed by its filter
+        }
+        let value = match result {
+            Ok(value) => value,
+            Err(Errno::Pending) => continue,
+            Err(errno) => errno.to_user(),
+        };
+        if let Some(ring) = ring() {
+            ring.complete(sqe.user_data, [value, a1, a2, a3, a4, a5]);
+        }
+    }
+    // A restarted enter mustn't count these again
+    frame.regs[9] = to_submit - submitted;
+
+    let ring = ring().ok_or(Errno::Inval)?;
+    if ring.ready() < min_complete {
+        ring.waiters.park_current();
+        return Err(Errno::Restart);
+    }
+    Ok(ring.ready())
+}
+
+/// Run one ring request: most as the syscall itself, `SYS_SLEEP` as a
+/// timeout that completes later, IPC as a request that completes when
+/// the other side turns up
+pub fn run(syscall: &Syscall, frame: &mut TrapFrame) -> SysResult {
+    if NOT_IN_RING.contains(&syscall.num) {
+        return Err(Errno::Inval);
+    }
+    // `submit` put the request's user_data in a6 for us
+    let user_data = frame.regs[15] as u64;
+    match syscall.num {
+        SYS_SLEEP => timeout(frame, user_data),
+        SYS_SEND => super::ipc::send_via(frame, Via::Ring(user_data)),
+        SYS_RECV => super::ipc::recv_via(frame, Via::Ring(user_data)),
+        SYS_CALL => super::ipc::call_via(frame, Via::Ring(user_data)),
+        SYS_REPLY_RECV => super::ipc::reply_recv_via(frame, Via::Ring(user_data)),
+        _ => (syscall.handler)(frame),
+    }
+}
+
+/// A ring's SYS_SLEEP: a0 = nanoseconds
+fn timeout(frame: &TrapFrame, user_data: u64) -> SysResult {
+    let ns = frame.arg(0) as u64;
+    let deadline = stats::now().saturating_add(crate::time::ns_to_ticks(ns));
+    let ring = crate::process::current_leader().and_then(|p| p.ring.as_mut()).ok_or(Errno::Inval)?;
+    if ring.add_timeout(deadline, user_data) { Err(Errno::Pending) } else { Err(Errno::Again) }
+}
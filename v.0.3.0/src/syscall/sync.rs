@@ -0,0 +1,22 @@
+//! Futex syscalls
+
+use super::{Errno, SysResult};
+use crate::process::stats;
+use crate::trap::TrapFrame;
+
+/// SYS_FUTEX_WAIT: a0 = address of a u32, a1 = expected value, a2 =
+/// timeout in ns (0 = none)
+///
+/// Returns 0 when woken, 1 if the value differed, 2 on timeout.
+pub fn futex_wait(frame: &mut TrapFrame) -> SysResult {
+    let (uaddr, expected, timeout) = (frame.arg(0), frame.arg(1) as u32, frame.arg(2) as u64);
+    let deadline = (timeout != 0).then(|| stats::now().saturating_add(crate::time::ns_to_ticks(timeout)));
+    crate::futex::wait(uaddr, expected, deadline).ok_or(Errno::Fault)
+}
+
+/// SYS_FUTEX_WAKE: a0 = address of a u32, a1 = most waiters to wake
+///
+/// Returns how many woke.
+pub fn futex_wake(frame: &mut TrapFrame) -> SysResult {
+    crate::futex::wake(frame.arg(0), frame.arg(1)).ok_or(Errno::Fault)
+}
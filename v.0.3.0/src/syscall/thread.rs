@@ -0,0 +1,36 @@
+//! Thread syscalls
+
+use super::{Errno, SysResult};
+use crate::trap::TrapFrame;
+
+/// SYS_THREAD_CREATE: a0 = entry point, a1 = argument (passed in a0),
+/// a2 = tp
+///
+/// Returns the new thread's ID.
+pub fn create(frame: &mut TrapFrame) -> SysResult {
+    let (entry, arg, tls) = (frame.arg(0), frame.arg(1), frame.arg(2));
+    crate::process::thread::create(entry, arg, tls).map_err(|limit| limit.map_or(Errno::Again, Errno::from))
+}
+
+/// SYS_THREAD_EXIT: a0 = exit value for the joiner
+pub fn exit(frame: &mut TrapFrame) -> SysResult {
+    crate::process::thread::exit_current(frame.arg(0));
+    Ok(0)
+}
+
+/// SYS_THREAD_JOIN: a0 = thread ID; returns its exit value
+pub fn join(frame: &mut TrapFrame) -> SysResult {
+    let tid = frame.arg(0);
+
+    match crate::process::thread::join(tid) {
+        Ok(Some(value)) => Ok(value),
+        Ok(None) => {
+            // Still running: park on the thread, then retry the ecall
+            if let Some(thread) = crate::process::get(tid) {
+                thread.thread_exit.park_current();
+            }
+            Err(Errno::Restart)
+        }
+        Err(()) => Err(Errno::Srch), // Not a joinable thread
+    }
+}
@@ -0,0 +1,21 @@
+//! Clock and sleep syscalls
+
+use super::{Errno, SysResult};
+use crate::process::stats;
+use crate::trap::TrapFrame;
+
+/// SYS_SLEEP: a0 = nanoseconds to sleep; 0 just yields. Returns 0
+pub fn sleep(frame: &mut TrapFrame) -> SysResult {
+    let ns = frame.arg(0) as u64;
+    if ns == 0 {
+        crate::process::scheduler::request_resched();
+    } else {
+        crate::timer::sleep_current_until(stats::now().saturating_add(crate::time::ns_to_ticks(ns)));
+    }
+    Ok(0)
+}
+
+/// SYS_CLOCK_GET: a0 = clock ID (see `time::CLOCK_*`); returns nanoseconds
+pub fn clock_get(frame: &mut TrapFrame) -> SysResult {
+    crate::time::clock_ns(frame.arg(0)).map(|ns| ns as usize).ok_or(Errno::Inval)
+}
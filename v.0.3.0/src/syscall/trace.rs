@@ -0,0 +1,239 @@
+//! Syscall tracing
+//!
+//! A traced process has each syscall it makes recorded: the number, all
+//! six argument registers, the value returned in a0 and how long the
+//! call took (from its first attempt, for one that blocked). Records go
+//! into one ring buffer, tagged with the tracer that asked for them, and
+//! SYS_TRACE_READ hands each tracer back only its own. With
+//! `TRACE_PRINT` every record is also printed on the kernel console,
+//! arguments decoded by name.
+//!
+//! When the buffer wraps, records their tracer hasn't read yet are lost;
+//! SYS_TRACE_READ says how many.
+//!
+//! Untraced processes pay one branch per syscall.
+
+use super::{Errno, SysResult};
+use crate::memory::user::copy_to_user;
+use crate::process::{stats, Pid, Process};
+use crate::sync::KernelCell;
+use crate::trap::TrapFrame;
+
+/// Flags for SYS_TRACE
+pub const TRACE_ON: usize = 1 << 0; // record the target's syscalls
+pub const TRACE_CHILDREN: usize = 1 << 1; // trace children it spawns from now on
+pub const TRACE_PRINT: usize = 1 << 2; // also print each record on the console
+pub const TRACE_ALL: usize = TRACE_ON | TRACE_CHILDREN | TRACE_PRINT;
+
+/// Records kept before the oldest is overwritten
+const TRACE_ENTRIES: usize = 256;
+
+/// How a process is traced, and for whom
+#[derive(Debug, Clone, Copy)]
+pub struct Trace {
+    /// Process the records belong to
+    pub tracer: Pid,
+    pub flags: usize,
+}
+
+impl Trace {
+    /// What a child spawned under this trace starts with, if anything
+    pub fn inherited(&self) -> Option<Trace> {
+        (self.flags & TRACE_CHILDREN != 0).then_some(Trace { tracer: self.tracer, flags: self.flags | TRACE_ON })
+    }
+}
+
+/// One traced syscall
+///
+/// This is also the layout SYS_TRACE_READ copies out to user space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TraceRecord {
+    /// Position in the trace; consecutive records of one tracer may skip
+    /// numbers that belong to others
+    pub seq: u64,
+    /// Thread that made the call
+    pub pid: u64,
+    pub num: u64,
+    pub args: [u64; 6],
+    /// a0 on return (`-errno` for a failure)
+    pub ret: u64,
+    /// Nanoseconds since boot when the call was made
+    pub start_ns: u64,
+    pub duration_ns: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    tracer: Pid,
+    record: TraceRecord,
+}
+
+struct TraceBuffer {
+    entries: [Option<Entry>; TRACE_ENTRIES],
+    /// Sequence number of the next record
+    next: u64,
+}
+
+static BUFFER: KernelCell<TraceBuffer> = KernelCell::new(TraceBuffer { entries: [None; TRACE_ENTRIES], next: 0 });
+
+/// A traced syscall in progress
+pub struct Call {
+    pid: Pid,
+    trace: Trace,
+    num: usize,
+    args: [usize; 6],
+    start: u64,
+}
+
+/// Start tracing the call `process` is making, if it is traced
+pub fn begin(process: &mut Process, num: usize, frame: &TrapFrame) -> Option<Call> {
+    let trace = process.trace.filter(|trace| trace.flags & TRACE_ON != 0)?;
+    Some(Call {
+        pid: process.pid,
+        trace,
+        num,
+        args: core::array::from_fn(|i| frame.arg(i)),
+        start: process.trace_start.take().unwrap_or_else(stats::now),
+    })
+}
+
+impl Call {
+    /// Record the finished call
+    ///
+    /// A call that parked to be restarted isn't finished: it keeps its
+    /// start time for the attempt that completes. A ring request that
+    /// completes later is recorded as returning 0.
+    pub fn finish(self, result: SysResult) {
+        let end = stats::now();
+        let ret = match result {
+            Ok(value) => value,
+            Err(Errno::Restart) => {
+                if let Some(process) = crate::process::get(self.pid) {
+                    process.trace_start = Some(self.start);
+                }
+                return;
+            }
+            Err(Errno::Pending) => 0,
+            Err(errno) => errno.to_user(),
+        };
+
+        // SAFETY: syscalls finish under the kernel lock, and nothing this
+        // function calls touches the buffer
+        let buffer = unsafe { BUFFER.get() };
+        let record = TraceRecord {
+            seq: buffer.next,
+            pid: self.pid as u64,
+            num: self.num as u64,
+            args: self.args.map(|arg| arg as u64),
+            ret: ret as u64,
+            start_ns: crate::time::ticks_to_ns(self.start.saturating_sub(crate::time::boot_ticks())),
+            duration_ns: crate::time::ticks_to_ns(end.saturating_sub(self.start)),
+        };
+        let slot = &mut buffer.entries[buffer.next as usize % TRACE_ENTRIES];
+        if let Some(old) = slot {
+            if let Some(tracer) = crate::process::get(old.tracer).filter(|t| old.record.seq >= t.trace_read) {
+                tracer.trace_lost += 1;
+            }
+        }
+        *slot = Some(Entry { tracer: self.trace.tracer, record });
+        buffer.next += 1;
+
+        if self.trace.flags & TRACE_PRINT != 0 {
+            print(&record);
+        }
+    }
+}
+
+/// `[TRACE] 5 SYS_WRITE(buf=0x10a2c, len=12) = 12 <31 us>`
+fn print(record: &TraceRecord) {
+    let syscall = super::lookup(record.num as usize);
+    let name = syscall.map_or("SYS_UNKNOWN", |s| s.name);
+    let names = syscall.map_or(&[][..], |s| s.args);
+
+    crate::kprint!("[TRACE] {} {}(", record.pid, name);
+    for (i, (name, &value)) in names.iter().zip(&record.args).enumerate() {
+        let sep = if i == 0 { "" } else { ", " };
+        // Small values are counts and flags; big ones are addresses
+        if value < 0x1000 {
+            crate::kprint!("{}{}={}", sep, name, value);
+        } else {
+            crate::kprint!("{}{}={:#x}", sep, name, value);
+        }
+    }
+    crate::kprintln!(") = {} <{} us>", record.ret as i64, record.duration_ns / 1000);
+}
+
+/// SYS_TRACE: a0 = PID (0 = caller), a1 = `TRACE_*` flags (0 = stop).
+/// Returns the previous flags
+///
+/// The target is the caller or one of its children; the caller becomes
+/// its tracer. Every thread of the target is traced. A target someone
+/// else already traces is left to them, so a traced process can't stop
+/// or take over its own trace.
+pub fn trace(frame: &mut TrapFrame) -> SysResult {
+    let (pid, flags) = (frame.arg(0), frame.arg(1));
+    if flags & !TRACE_ALL != 0 {
+        return Err(Errno::Inval);
+    }
+    let tracer = crate::process::current_leader().ok_or(Errno::Srch)?.pid;
+    let target = match pid {
+        0 => tracer,
+        pid => pid,
+    };
+    let process = crate::process::get(target).ok_or(Errno::Srch)?;
+    if process.is_thread() {
+        return Err(Errno::Srch);
+    }
+    if process.pid != tracer && process.parent != Some(tracer) {
+        return Err(Errno::Perm);
+    }
+    if process.trace.is_some_and(|trace| trace.tracer != tracer) {
+        return Err(Errno::Perm);
+    }
+
+    let old = process.trace.map_or(0, |trace| trace.flags);
+    let trace = (flags != 0).then_some(Trace { tracer, flags });
+    for thread in crate::process::iter().filter(|p| p.tgid == target) {
+        thread.trace = trace;
+        thread.trace_start = None;
+    }
+    Ok(old)
+}
+
+/// SYS_TRACE_READ: a0 = first sequence number wanted, a1 = buffer of
+/// `TraceRecord`s, a2 = how many it holds. Returns how many were copied,
+/// and in a1 how many records were lost since the last read
+///
+/// Only the caller's own records are copied, oldest first. Records that
+/// have been overwritten are skipped, so the first `seq` returned may be
+/// past the one asked for; ask for the last `seq` + 1 next time. Those
+/// the caller never got to read count as lost; ones before `from` don't.
+pub fn read(frame: &mut TrapFrame) -> SysResult {
+    let (from, buf, capacity) = (frame.arg(0) as u64, frame.arg(1), frame.arg(2));
+    let leader = crate::process::current_leader().ok_or(Errno::Srch)?;
+    let tracer = leader.pid;
+    // SAFETY: syscalls run under the kernel lock, and copying out to the
+    // user never records a trace
+    let buffer = unsafe { BUFFER.get() };
+
+    let oldest = buffer.next.saturating_sub(TRACE_ENTRIES as u64);
+    let ours = (from.max(oldest)..buffer.next)
+        .filter_map(|seq| buffer.entries[seq as usize % TRACE_ENTRIES])
+        .filter(|entry| entry.tracer == tracer)
+        .take(capacity);
+
+    let size = core::mem::size_of::<TraceRecord>();
+    let mut copied = 0;
+    let mut read_up_to = from;
+    for entry in ours {
+        // All u64 fields, so no padding bytes to leak
+        let bytes = unsafe { core::slice::from_raw_parts(&entry.record as *const TraceRecord as *const u8, size) };
+        copy_to_user(buf + copied * size, bytes)?;
+        copied += 1;
+        read_up_to = entry.record.seq + 1;
+    }
+    leader.trace_read = leader.trace_read.max(read_up_to);
+    frame.regs[10] = core::mem::take(&mut leader.trace_lost) as usize;
+    Ok(copied)
+}
@@ -0,0 +1,88 @@
+//! Clocks
+//!
+//! The `time` CSR counts at the platform's timebase frequency, which the
+//! DTB publishes as `/cpus/timebase-frequency`. We record it, and the
+//! counter value at boot, once during early boot. Wall-clock time comes
+//! from reading the goldfish RTC at the same moment: after that it is
+//! boot time plus uptime, so it never jumps.
+
+use crate::process::stats;
+use crate::rtc::Rtc;
+use crate::sync::OnceCell;
+
+/// Clock IDs for SYS_CLOCK_GET
+pub const CLOCK_MONOTONIC: usize = 0; // since the `time` CSR started counting
+pub const CLOCK_REALTIME: usize = 1; // since the Unix epoch
+pub const CLOCK_UPTIME: usize = 2; // since the kernel booted
+pub const CLOCK_BOOT_TIME: usize = 3; // wall-clock time the kernel booted
+
+/// QEMU virt's timebase, used when the DTB doesn't say
+const DEFAULT_TIMEBASE_HZ: u64 = 10_000_000;
+
+/// What `init` recorded at boot
+struct BootClock {
+    ticks: u64,
+    timebase_hz: u64,
+    /// Nanoseconds since the Unix epoch at `ticks` (None without an RTC)
+    time_ns: Option<u64>,
+}
+
+static BOOT_CLOCK: OnceCell<BootClock> = OnceCell::new();
+
+/// Record the boot timestamp, the timebase frequency and the wall clock
+///
+/// Call once at boot, after `fdt::init`.
+pub fn init() {
+    let ticks = stats::now();
+    let time_ns = Rtc::find().map(|rtc| rtc.read_ns());
+
+    let hz = crate::fdt::get()
+        .and_then(|fdt| fdt.find_node("/cpus"))
+        .and_then(|cpus| cpus.property_u64("timebase-frequency"));
+    let timebase_hz = hz.filter(|&hz| hz != 0).unwrap_or(DEFAULT_TIMEBASE_HZ);
+
+    let _ = BOOT_CLOCK.set(BootClock { ticks, timebase_hz, time_ns });
+}
+
+/// `time` CSR value when the kernel booted
+pub fn boot_ticks() -> u64 {
+    BOOT_CLOCK.get().map_or(0, |clock| clock.ticks)
+}
+
+/// Ticks per second of the `time` CSR
+pub fn timebase_hz() -> u64 {
+    BOOT_CLOCK.get().map_or(DEFAULT_TIMEBASE_HZ, |clock| clock.timebase_hz)
+}
+
+/// Convert nanoseconds to `time` CSR ticks (rounding up)
+pub fn ns_to_ticks(ns: u64) -> u64 {
+    let ticks = (ns as u128 * timebase_hz() as u128).div_ceil(1_000_000_000);
+    ticks.min(u64::MAX as u128) as u64
+}
+
+/// Convert `time` CSR ticks to nanoseconds (rounding down)
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    let ns = ticks as u128 * 1_000_000_000 / timebase_hz() as u128;
+    ns.min(u64::MAX as u128) as u64
+}
+
+/// Nanoseconds since the kernel booted
+pub fn uptime_ns() -> u64 {
+    ticks_to_ns(stats::now() - boot_ticks())
+}
+
+/// Wall-clock time the kernel booted, in nanoseconds since the epoch
+pub fn boot_time_ns() -> Option<u64> {
+    BOOT_CLOCK.get().and_then(|clock| clock.time_ns)
+}
+
+/// Read clock `id` in nanoseconds (`None` for an unknown or absent clock)
+pub fn clock_ns(id: usize) -> Option<u64> {
+    match id {
+        CLOCK_MONOTONIC => Some(ticks_to_ns(stats::now())),
+        CLOCK_REALTIME => boot_time_ns().map(|boot| boot + uptime_ns()),
+        CLOCK_UPTIME => Some(uptime_ns()),
+        CLOCK_BOOT_TIME => boot_time_ns(),
+        _ => None,
+    }
+}
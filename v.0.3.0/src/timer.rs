@@ -0,0 +1,168 @@
+//! Kernel timer queue
+//!
+//! A process can block with a deadline: if nobody wakes it first, the
+//! timer interrupt takes it off its wait queue (if any) and makes it
+//! runnable again. It then sees whatever its syscall left in a0 when it
+//! blocked, so a blocking syscall stores its "timed out" result before
+//! parking and a waker overwrites it.
+//!
+//! Pending deadlines form a list sorted by deadline, linked through the
+//! process table (`Process::timer_next`) like wait queues are. Each hart
+//! has its own SBI timer; whichever hart last changed the head of the
+//! list is armed for it, and any hart whose timer fires wakes everybody
+//! due. Waking a process any other way takes it off the list (see
+//! `scheduler::make_ready`).
+//!
+//! A hart's timer also ends the running process' slice when it has a CPU
+//! share limit, so the scheduler gets to throttle it, and completes ring
+//! timeouts (see `ring`).
+
+use core::arch::asm;
+
+use crate::process::wait::WaitQueue;
+use crate::process::{self, Pid, ProcessState, scheduler, stats};
+use crate::sync::KernelCell;
+use crate::{sbi, smp};
+
+/// Processes with a deadline, earliest first (equal deadlines in arming order)
+static HEAD: KernelCell<Option<Pid>> = KernelCell::new(None);
+
+/// Empty the timer queue and set up the boot hart's timer
+pub fn init() {
+    set_head(None);
+    init_hart();
+}
+
+/// Disarm the calling hart's timer and enable its timer interrupts
+pub fn init_hart() {
+    smp::this_hart().slice_end = None;
+    sbi::set_timer(u64::MAX);
+    unsafe { asm!("csrs sie, {}", in(reg) 1usize << 5) }; // STIE bit
+}
+
+/// Park the current process on `queue` until woken or until `time`
+/// reaches `deadline`
+pub fn park_current_until(queue: &mut WaitQueue, deadline: u64) {
+    queue.park_current();
+    if let Some(pid) = process::current_pid() {
+        arm(pid, deadline);
+    }
+}
+
+/// Block the current process until `time` reaches `deadline`
+pub fn sleep_current_until(deadline: u64) {
+    let Some(current) = process::current_process() else { return };
+    current.state = ProcessState::Blocked;
+    arm(current.pid, deadline);
+}
+
+/// Give `pid` a deadline, replacing any it already has
+pub fn arm(pid: Pid, deadline: u64) {
+    cancel(pid);
+
+    // Find the last entry that expires no later than us
+    let mut prev: Option<Pid> = None;
+    let mut cursor = head();
+    while let Some(p) = cursor.and_then(process::get) {
+        if p.deadline.is_some_and(|d| d > deadline) {
+            break;
+        }
+        prev = cursor;
+        cursor = p.timer_next;
+    }
+
+    let Some(process) = process::get(pid) else { return };
+    process.deadline = Some(deadline);
+    process.timer_next = cursor;
+    match prev.and_then(process::get) {
+        Some(prev) => prev.timer_next = Some(pid),
+        None => {
+            set_head(Some(pid));
+            rearm();
+        }
+    }
+}
+
+/// Drop `pid`'s deadline, if it has one
+///
+/// The SBI timer stays armed; if this was the earliest deadline the
+/// interrupt finds nothing due and rearms for the next one.
+pub fn cancel(pid: Pid) {
+    let Some(process) = process::get(pid) else { return };
+    if process.deadline.take().is_none() {
+        return;
+    }
+    let next = process.timer_next.take();
+
+    let mut prev: Option<Pid> = None;
+    let mut cursor = head();
+    while let Some(current) = cursor {
+        if current == pid {
+            match prev.and_then(process::get) {
+                Some(prev) => prev.timer_next = next,
+                None => set_head(next),
+            }
+            return;
+        }
+        prev = cursor;
+        cursor = process::get(current).and_then(|p| p.timer_next);
+    }
+}
+
+/// Interrupt this hart's running process at `end` (`None`: let it run)
+pub fn set_slice_end(end: Option<u64>) {
+    let hart = smp::this_hart();
+    if hart.slice_end != end {
+        hart.slice_end = end;
+        rearm();
+    }
+}
+
+/// Timer interrupt: end an expired slice and wake everybody whose
+/// deadline has passed
+pub fn handle_interrupt() {
+    let now = stats::now();
+
+    let hart = smp::this_hart();
+    if hart.slice_end.is_some_and(|end| end <= now) {
+        hart.slice_end = None;
+        scheduler::request_resched();
+    }
+
+    while let Some(process) = head().and_then(process::get) {
+        if process.deadline.is_some_and(|d| d > now) {
+            break;
+        }
+        set_head(process.timer_next.take());
+        process.deadline = None;
+
+        if let Some(queue) = unsafe { process.waiting_on.as_mut() } {
+            queue.remove(process.pid);
+        }
+        if process.state == ProcessState::Blocked {
+            scheduler::make_ready(process.pid);
+        }
+    }
+    crate::ring::expire(now);
+
+    rearm();
+}
+
+fn head() -> Option<Pid> {
+    // SAFETY: the timer queue is only touched under the kernel lock, and
+    // the borrow ends with the copy
+    unsafe { *HEAD.get() }
+}
+
+fn set_head(head: Option<Pid>) {
+    // SAFETY: as for `head`
+    unsafe { *HEAD.get() = head };
+}
+
+/// Program this hart's SBI timer for the earliest pending deadline or
+/// its slice end
+pub fn rearm() {
+    let deadline = head().and_then(process::get).and_then(|p| p.deadline);
+    let next = deadline.into_iter().chain(crate::ring::next_deadline()).chain(smp::this_hart().slice_end).min();
+    sbi::set_timer(next.unwrap_or(u64::MAX));
+}
@@ -1,7 +1,9 @@
 // src/trap.rs - Layer 1 Context Switching Foundation
 
 use core::arch::asm;
-use crate::syscall::*;
+use crate::process::{ExitReason, ProcessState};
+use crate::process::stats;
+use crate::smp::{Hart, MAX_HARTS};
 
 /// Trap frame: saved register state for context switching
 #[repr(C)]
@@ -9,6 +11,7 @@ pub struct TrapFrame {
     pub regs: [usize; 31],  // x1-x31 (x0 is hardwired zero)
     pub sepc: usize,        // Saved program counter
     pub sstatus: usize,     // Saved status register
+    pub sscratch: usize,    // Value the vector puts back in sscratch on exit
 }
 
 impl TrapFrame {
@@ -17,22 +20,44 @@ impl TrapFrame {
             regs: [0; 31],
             sepc: 0,
             sstatus: 0,
+            sscratch: 0,
         }
     }
+    
+    /// Did this trap arrive while we were already handling another one?
+    pub fn is_nested(&self) -> bool {
+        self.sscratch == 0
+    }
+    
+    /// Did this trap come from U-mode (sstatus.SPP clear)?
+    pub fn is_user(&self) -> bool {
+        self.sstatus & (1 << 8) == 0
+    }
+    
+    /// Syscall argument `n` (a0-a5)
+    pub fn arg(&self, n: usize) -> usize {
+        self.regs[9 + n]
+    }
 }
 
-/// Initialize trap handling for Layer 1
+/// Size of each hart's kernel trap stack
+const TRAP_STACK_SIZE: usize = 16 * 1024;
+
+/// Kernel stack used while handling traps
+///
+/// The trap vector swaps onto this stack via `sscratch`, so trap frames
+/// never live on a (user controlled) process stack and the scheduler can
+/// swap them freely. The top two words are not stack: the highest holds
+/// the hart's `smp::Hart` pointer, which the vector loads into `tp`.
+#[repr(C, align(16))]
+struct TrapStack([u8; TRAP_STACK_SIZE]);
+
+static mut TRAP_STACKS: [TrapStack; MAX_HARTS] = [const { TrapStack([0; TRAP_STACK_SIZE]) }; MAX_HARTS];
+
+/// Initialize trap handling for Layer 1 on the boot hart
 pub fn init() {
+    init_hart();
     unsafe {
-        // Set trap vector to our handler
-        extern "C" {
-            fn _trap_vector();
-        }
-        asm!(
-            "csrw stvec, {handler}",
-            handler = in(reg) _trap_vector as usize,
-        );
-        
         // Enable supervisor interrupts (we'll need timer later)
         asm!("csrsi sstatus, 0x2"); // SIE bit
         
@@ -44,23 +69,33 @@ pub fn init() {
     crate::kprintln!("[TRAP] Layer 1 initialized - context switching ready");
 }
 
-/// Create a fake user context for testing
-pub fn create_test_user_context(entry: usize, stack: usize) -> TrapFrame {
-    let mut frame = TrapFrame::zero();
-    
-    // Set program counter to user function
-    frame.sepc = entry;
-    
-    // Set user stack pointer (x2/sp)
-    // regs[0] is x1 (ra), regs[1] is x2 (sp)
-    frame.regs[1] = stack;
-    
-    // Set sstatus for user mode:
-    // - SPP=0 (return to user mode)
-    // - SPIE=1 (enable interrupts after sret)
-    frame.sstatus = 0x20; // SPIE bit
-    
-    frame
+/// Install the trap vector and trap stack on the calling hart
+pub fn init_hart() {
+    let hart = crate::smp::this_hart();
+    unsafe {
+        // Set trap vector to our handler
+        extern "C" {
+            fn _trap_vector();
+        }
+        asm!(
+            "csrw stvec, {handler}",
+            handler = in(reg) _trap_vector as usize,
+        );
+        
+        // sscratch holds the trap stack top whenever we're *not* handling a trap
+        let trap_stack_top = (&raw const TRAP_STACKS[hart.id]) as usize + TRAP_STACK_SIZE - 16;
+        ((trap_stack_top + 8) as *mut usize).write(hart as *mut Hart as usize);
+        asm!("csrw sscratch, {}", in(reg) trap_stack_top);
+        
+        // Software interrupts are how kernel threads enter the scheduler
+        // and how other harts get our attention; external ones bring
+        // device input
+        asm!("csrs sie, {}", in(reg) 1usize << 1 | 1usize << 9); // SSIE, SEIE bits
+
+        // Let user code read the `time` CSR, so clocks need no syscall,
+        // and `cycle`, for benchmarks
+        asm!("csrs scounteren, {}", in(reg) 1usize << 0 | 1usize << 1); // CY, TM bits
+    }
 }
 
 /// Jump to user mode with the given context
@@ -121,8 +156,18 @@ pub fn enter_user_mode(frame: &TrapFrame) -> ! {
 }
 
 /// The trap handler (called from assembly vector)
+///
+/// Traps from U-mode take the big kernel lock; it is dropped again if the
+/// trap returns to U-mode, possibly in another process.
 #[no_mangle]
 pub extern "C" fn trap_handler(frame: &mut TrapFrame) {
+    // Read before the lock, so spinning on it counts as kernel time
+    // rather than user time
+    let entered = stats::now();
+    if frame.is_user() {
+        crate::smp::lock_kernel();
+    }
+    
     // Read trap cause
     let scause: usize;
     let stval: usize;
@@ -135,18 +180,63 @@ pub extern "C" fn trap_handler(frame: &mut TrapFrame) {
     let is_interrupt = (scause >> 63) != 0; // MSB indicates interrupt
     let code = scause & 0x7FFFFFFFFFFFFFFF;
     
+    let prev = crate::process::current_pid();
+    if !frame.is_nested() {
+        stats::trap_enter(frame, entered);
+    }
+    
+    // Another hart may have killed us while we waited for the lock:
+    // don't act on behalf of a dead process
+    let killed = crate::process::current_process().is_some_and(|p| p.state == ProcessState::Dead);
+    
     if is_interrupt {
         handle_interrupt(code, frame);
-    } else {
+    } else if !killed {
         handle_exception(code, stval, frame);
     }
+    
+    // Let the scheduler decide who we return to
+    if !frame.is_nested() {
+        stats::charge_kernel(prev);
+        crate::process::scheduler::schedule(frame);
+        stats::resume(crate::process::current_pid());
+        if frame.is_user() {
+            crate::smp::unlock_kernel();
+        }
+    }
+}
+
+/// Service pending interrupts without taking a trap
+///
+/// Used by the idle loop, which runs inside the trap handler with
+/// interrupts masked and wakes from `wfi` on a pending interrupt.
+pub fn poll_interrupts(frame: &mut TrapFrame) {
+    let sip: usize;
+    let sie: usize;
+    unsafe {
+        asm!("csrr {}, sip", out(reg) sip);
+        asm!("csrr {}, sie", out(reg) sie);
+    }
+    
+    let pending = sip & sie;
+    for code in 0..usize::BITS as usize {
+        if pending & (1 << code) != 0 {
+            handle_interrupt(code, frame);
+        }
+    }
 }
 
 fn handle_interrupt(code: usize, _frame: &mut TrapFrame) {
     match code {
-        5 => { // Supervisor timer interrupt
-            crate::kprintln!("[TRAP] Timer interrupt");
-            // Acknowledge by setting next timer (we'll do this properly in Layer 3)
+        1 => { // Supervisor software interrupt: a kernel thread yielding or another hart's IPI
+            unsafe { asm!("csrc sip, {}", in(reg) 1usize << 1) };
+            crate::process::scheduler::request_resched();
+        }
+        5 => { // Supervisor timer interrupt: a deadline passed
+            crate::timer::handle_interrupt();
+        }
+        9 => { // Supervisor external interrupt: a device, via the PLIC
+            crate::plic::handle_interrupt();
         }
         _ => {
             crate::kprintln!("[TRAP] Unknown interrupt: {}", code);
@@ -157,93 +247,74 @@ fn handle_interrupt(code: usize, _frame: &mut TrapFrame) {
 fn handle_exception(code: usize, stval: usize, frame: &mut TrapFrame) {
     match code {
         8 => { // Environment call from U-mode (ecall)
-            handle_syscall(frame);
+            crate::syscall::dispatch(frame);
         }
         9 => { // Environment call from S-mode
             panic!("Unexpected ecall from S-mode");
         }
-        12 => { // Instruction page fault
-            panic!("Instruction page fault at {:#x}", stval);
-        }
-        13 => { // Load page fault
-            panic!("Load page fault at {:#x}", stval);
-        }
-        15 => { // Store page fault
-            panic!("Store page fault at {:#x}", stval);
+        12 | 13 | 15 => { // Instruction / load / store page fault
+            if !frame.is_nested() {
+                if let Some(process) = crate::process::current_process() {
+                    process.stats.page_faults += 1;
+                }
+            }
+            let kind = match code {
+                12 => "Instruction",
+                13 => "Load",
+                _ => "Store",
+            };
+            if frame.is_user() {
+                return kill_faulting(code, stval, frame);
+            }
+            // A user copy hit a bad page: let it report the fault
+            if let Some(fixup) = crate::memory::user::fixup(frame.sepc) {
+                frame.sepc = fixup;
+                return;
+            }
+            panic!("{} page fault at {:#x}", kind, stval);
         }
+        _ if frame.is_user() => kill_faulting(code, stval, frame),
         _ => {
             panic!("Unhandled exception: code={}, stval={:#x}", code, stval);
         }
     }
 }
 
-fn handle_syscall(frame: &mut TrapFrame) {
-    // Syscall number in a7 (regs[16])
-    // Arguments in a0-a5 (regs[9-14])
-    // Return value goes in a0 (regs[9])
-    let syscall_num = frame.regs[16]; // a7
-    
-    crate::kprintln!("[SYSCALL] {} ({})", syscall_name(syscall_num), syscall_num);
-    
-    match syscall_num {
-        SYS_TEST => {
-            // Test syscall - just print success and return value
-            crate::kprintln!("[SYSCALL] Test syscall from user mode - SUCCESS! 🐺");
-            frame.regs[9] = 42; // Return value in a0
-        }
-        
-        SYS_EXIT => {
-            // Exit syscall - for now, just halt
-            let exit_code = frame.regs[9]; // a0
-            crate::kprintln!("[SYSCALL] User process exit (code: {})", exit_code);
-            crate::kprintln!("\n🎉 Layer 1 Context Switching: OPERATIONAL ✓");
-            crate::kprintln!("Ready to proceed to Layer 2 (PMP)!\n");
-            loop {
-                unsafe { asm!("wfi"); }
-            }
-        }
-        
-        // Future syscalls (Layer 3+)
-        SYS_SEND | SYS_RECV => {
-            crate::kprintln!("[SYSCALL] IPC not yet implemented (Layer 3 feature)");
-            frame.regs[9] = usize::MAX; // Error return
-        }
-        
-        // Distributed syscalls (Layer 6+)
-        SYS_SEND_REMOTE | SYS_RECV_REMOTE | SYS_NODE_DISCOVER => {
-            crate::kprintln!("[SYSCALL] Distributed operation not yet implemented (Layer 6 feature)");
-            frame.regs[9] = usize::MAX; // Error return
-        }
-        
-        _ => {
-            crate::kprintln!("[SYSCALL] Unknown syscall: {}", syscall_num);
-            frame.regs[9] = usize::MAX; // Error return
-        }
+/// A user program raised an exception: end it rather than the kernel
+fn kill_faulting(code: usize, stval: usize, frame: &TrapFrame) {
+    let reason = ExitReason::Faulted { cause: code, pc: frame.sepc, addr: stval };
+    if let Some(process) = crate::process::current_process() {
+        crate::kprintln!("[TRAP] PID {} '{}' killed: exception {} at pc {:#x} (stval {:#x})",
+            process.pid, process.name, code, frame.sepc, stval);
     }
-    
-    // Advance sepc past the ecall instruction (4 bytes)
-    frame.sepc += 4;
+    crate::process::terminate_current(reason);
 }
 
 // The actual trap vector (assembly trampoline)
+//
+// sscratch holds the top of the hart's kernel trap stack while we're
+// outside the handler and 0 while we're inside it. Swapping it with sp on
+// entry moves us onto the trap stack; a zero means the trap hit the
+// handler itself, so we stay on the current stack instead.
+//
+// Coming from outside the handler, tp may be a user thread pointer: it is
+// saved in the frame and replaced by the hart pointer stored just above
+// the trap stack top.
 core::arch::global_asm!(
     r#"
 .section .text
 .align 4
 .global _trap_vector
 _trap_vector:
-    # Save context to kernel stack
-    # For now, simplified: assume we have space on current stack
-    # Layer 2 will use sscratch CSR for proper kernel/user stack separation
-    addi sp, sp, -264  # sizeof(TrapFrame) = 33*8 bytes
-
-    # Save original (user) sp value into the TrapFrame slot for x2.
-    # After the subtraction above, original_sp == sp + 264.
-    addi t0, sp, 264
+    csrrw sp, sscratch, sp
+    bnez sp, 1f
+    # Nested trap: undo the swap and keep using the current kernel stack
+    csrrw sp, sscratch, sp
+1:
+    addi sp, sp, -272  # sizeof(TrapFrame) = 34*8 bytes, 16-byte aligned
     
-    # Save all registers
+    # Save all registers except sp
     sd ra, 0(sp)
-    sd t0, 8(sp)       # Save original user stack pointer
     sd gp, 16(sp)
     sd tp, 24(sp)
     sd t0, 32(sp)
@@ -274,6 +345,20 @@ _trap_vector:
     sd t5, 232(sp)
     sd t6, 240(sp)
     
+    # Recover the interrupted sp; sscratch becomes 0 ("inside handler")
+    csrrw t0, sscratch, zero
+    addi t1, sp, 272
+    beqz t0, 2f
+    # From outside the handler: t0 = interrupted sp, t1 = trap stack top
+    sd t0, 8(sp)
+    sd t1, 264(sp)
+    ld tp, 8(t1)
+    j 3f
+2:
+    # Nested: the interrupted sp sits right above this frame
+    sd t1, 8(sp)
+    sd zero, 264(sp)
+3:
     # Save sepc and sstatus
     csrr t0, sepc
     sd t0, 248(sp)
@@ -284,7 +369,9 @@ _trap_vector:
     mv a0, sp          # Pass frame pointer as argument
     call trap_handler
     
-    # Restore context
+    # Restore context (the scheduler may have swapped the frame contents)
+    ld t0, 264(sp)
+    csrw sscratch, t0
     ld t0, 256(sp)
     csrw sstatus, t0
     ld t0, 248(sp)
@@ -322,9 +409,9 @@ _trap_vector:
     ld t5, 232(sp)
     ld t6, 240(sp)
 
-    # Deallocate TrapFrame; this restores sp back to the original user value.
-    addi sp, sp, 264
+    # Finally switch to the resumed context's stack
+    ld sp, 8(sp)
     
     sret
     "#
-);
\ No newline at end of file
+);
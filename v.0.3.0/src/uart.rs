@@ -1,6 +1,8 @@
 /// UART driver for 16550-compatible serial console
 use core::fmt;
 
+use crate::sync::SpinLock;
+
 pub struct Uart {
     base_address: usize,
 }
@@ -29,7 +31,6 @@ impl Uart {
         }
     }
     
-    #[allow(dead_code)]
     pub fn getc(&self) -> Option<u8> {
         unsafe {
             let lsr = ((self.base_address + 5) as *const u8).read_volatile();
@@ -42,6 +43,11 @@ impl Uart {
         }
     }
     
+    /// Raise an interrupt whenever a byte arrives (IER.ERBFI)
+    pub fn enable_rx_interrupt(&self) {
+        unsafe { ((self.base_address + 1) as *mut u8).write_volatile(0x01) };
+    }
+    
     #[allow(dead_code)]
     pub fn getline(&self, buffer: &mut [u8]) -> usize {
         let mut pos = 0;
@@ -61,7 +67,7 @@ impl Uart {
                             self.putc(0x08);
                         }
                     }
-                    byte if byte >= 0x20 && byte < 0x7F && pos < buffer.len() => {
+                    byte if (0x20..0x7F).contains(&byte) && pos < buffer.len() => {
                         buffer[pos] = byte;
                         pos += 1;
                         self.putc(byte);
@@ -105,11 +111,45 @@ impl fmt::Write for UartWriter {
     }
 }
 
+/// Held while writing to the console, so lines from different harts
+/// don't interleave
+static CONSOLE: SpinLock<UartWriter> = SpinLock::new(UartWriter);
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use fmt::Write;
-    let mut w = UartWriter;
-    let _ = w.write_fmt(args);
+    let _ = CONSOLE.lock_irq().write_fmt(args);
+}
+
+/// Write one raw byte to the console
+pub fn putc(byte: u8) {
+    let _console = CONSOLE.lock_irq();
+    Uart::new(0x1000_0000).putc(byte);
+}
+
+/// Write raw bytes to the console without other output in between
+pub fn write_bytes(bytes: &[u8]) {
+    let _console = CONSOLE.lock_irq();
+    let uart = Uart::new(0x1000_0000);
+    bytes.iter().for_each(|&byte| uart.putc(byte));
+}
+
+/// Next received byte, if one is waiting
+pub fn getc() -> Option<u8> {
+    Uart::new(0x1000_0000).getc()
+}
+
+/// Interrupt when input arrives
+pub fn enable_rx_interrupt() {
+    Uart::new(0x1000_0000).enable_rx_interrupt();
+}
+
+/// Free the console for the panic handler
+///
+/// # Safety
+/// Only for a hart that is going down and may have panicked mid-print.
+pub unsafe fn force_unlock() {
+    CONSOLE.force_unlock();
 }
 
 /// Kernel print (no newline)
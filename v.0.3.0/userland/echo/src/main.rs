@@ -0,0 +1,24 @@
+//! Print the arguments (after the program name), separated by spaces
+//!
+//! `PREFIX` in the environment is printed first if set.
+
+#![no_std]
+#![no_main]
+
+use woflrt::{env, print, println};
+
+woflrt::entry!(main);
+
+fn main() -> i32 {
+    if let Some(prefix) = env::var("PREFIX") {
+        print!("{}", prefix);
+    }
+    for (i, arg) in env::args().skip(1).enumerate() {
+        if i > 0 {
+            print!(" ");
+        }
+        print!("{}", arg);
+    }
+    println!();
+    0
+}
@@ -0,0 +1,19 @@
+//! Crash with a load page fault, to exercise supervision
+//!
+//! With an argument, exit normally with that code instead.
+
+#![no_std]
+#![no_main]
+
+use woflrt::{env, println};
+
+woflrt::entry!(main);
+
+fn main() -> i32 {
+    println!("[fault] PID {} starting", env::pid());
+    if let Some(code) = env::args().nth(1) {
+        return code.parse().unwrap_or(0);
+    }
+    let nowhere = core::ptr::null::<u32>();
+    unsafe { nowhere.read_volatile() as i32 }
+}
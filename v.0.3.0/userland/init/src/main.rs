@@ -0,0 +1,224 @@
+//! First user program: proves the runtime and syscall wrappers work
+
+#![no_std]
+#![no_main]
+
+use core::time::Duration;
+
+use woflrt::env::{self, CAP_CONSOLE, CAP_SPAWN};
+use woflrt::filter::{Action, Filter};
+use woflrt::process::RestartPolicy;
+use woflrt::syscall::{SYS_EXIT, SYS_SLEEP, SYS_WRITE};
+use woflrt::sync::Mutex;
+use woflrt::{datapage, ipc, println, process, ring, syscall, thread, time};
+
+/// Grand total the threads add their partial sums into
+static TOTAL: Mutex<usize> = Mutex::new(0);
+
+woflrt::entry!(main);
+
+fn main() -> i32 {
+    println!("[init] hello from userspace, PID {}", env::pid());
+    for (i, arg) in env::args().enumerate() {
+        println!("[init] argv[{}] = {}", i, arg);
+    }
+    for (key, value) in env::vars() {
+        println!("[init] env {} = {}", key, value);
+    }
+    for cap in env::caps() {
+        println!("[init] capability handle {} kind {}", cap.handle, cap.kind);
+    }
+    println!("[init] SYS_TEST says {}", syscall::test());
+    println!("[init] process::id() says {}", process::id());
+    match (datapage::kernel_version(), datapage::harts()) {
+        (Some(version), Some(harts)) => println!("[init] data page: {}, {} harts online", version, harts),
+        _ => println!("[init] no kernel data page"),
+    }
+
+    // Hand the console (and nothing else) to a child with some arguments
+    let caps: &[usize] = match env::cap(CAP_CONSOLE) {
+        Some(console) => &[console],
+        None => &[],
+    };
+    match process::spawn("echo", &["echo", "argv", "works"], &["PREFIX=[echo] "], caps) {
+        Ok(pid) => match process::wait(Some(pid)) {
+            Ok((_, code)) => println!("[init] echo (PID {}) exited with {}", pid, code),
+            Err(e) => println!("[init] wait failed: {:?}", e),
+        },
+        Err(e) => println!("[init] spawn failed: {:?}", e),
+    }
+
+    // The same, traced: strace needs the spawn capability to start it
+    let spawn_caps: &[usize] = match (env::cap(CAP_CONSOLE), env::cap(CAP_SPAWN)) {
+        (Some(console), Some(spawn)) => &[console, spawn],
+        _ => &[],
+    };
+    match process::spawn("strace", &["strace", "echo", "traced"], &[], spawn_caps) {
+        Ok(pid) => {
+            if let Err(e) = process::wait(Some(pid)) {
+                println!("[init] wait failed: {:?}", e);
+            }
+        }
+        Err(e) => println!("[init] spawn failed: {:?}", e),
+    }
+
+    // A traced child can neither stop its trace nor take it over
+    match process::spawn("strace", &["strace", "untrace"], &[], spawn_caps) {
+        Ok(pid) => {
+            if let Err(e) = process::wait(Some(pid)) {
+                println!("[init] wait failed: {:?}", e);
+            }
+        }
+        Err(e) => println!("[init] spawn failed: {:?}", e),
+    }
+
+    // Round-trip times for call/reply IPC between two processes
+    match process::spawn("ipcbench", &["ipcbench"], &[], spawn_caps) {
+        Ok(pid) => {
+            if let Err(e) = process::wait(Some(pid)) {
+                println!("[init] wait failed: {:?}", e);
+            }
+        }
+        Err(e) => println!("[init] spawn failed: {:?}", e),
+    }
+
+    // A child allowed nothing but SYS_EXIT dies at its first write
+    let exit_only = Filter::new(Action::Kill).allow(SYS_EXIT);
+    match process::spawn_with_filter("echo", &["echo", "filtered"], &[], caps, &exit_only) {
+        Ok(pid) => match process::wait_status(Some(pid)) {
+            Ok(status) => println!("[init] filtered echo ended: {:?}", status.reason),
+            Err(e) => println!("[init] wait failed: {:?}", e),
+        },
+        Err(e) => println!("[init] spawn failed: {:?}", e),
+    }
+
+    // A child that can't fit in four frames is refused with a distinct error
+    let tiny = process::Limits { frames: 4, ..Default::default() };
+    match process::spawn_with_limits("echo", &["echo"], &[], caps, &tiny) {
+        Ok(pid) => println!("[init] echo fit in 4 frames as PID {}?", pid),
+        Err(e) => println!("[init] echo in 4 frames: {:?}", e),
+    }
+
+    // A child that keeps faulting: restarted twice, then left dead
+    match process::spawn("fault", &["fault"], &[], caps) {
+        Ok(pid) => {
+            if let Err(e) = process::supervise(pid, RestartPolicy::OnFailure, 2, Some(Duration::from_secs(1))) {
+                println!("[init] supervise failed: {:?}", e);
+            }
+            let mut next = Some(pid);
+            while let Some(pid) = next {
+                match process::wait_status(Some(pid)) {
+                    Ok(status) => {
+                        println!("[init] fault (PID {}) ended: {:?}", status.pid, status.reason);
+                        next = status.restarted_as;
+                    }
+                    Err(e) => {
+                        println!("[init] wait failed: {:?}", e);
+                        next = None;
+                    }
+                }
+            }
+        }
+        Err(e) => println!("[init] spawn failed: {:?}", e),
+    }
+
+    match thread::affinity(0) {
+        Ok(mask) => println!("[init] may run on harts {:#010b}", mask),
+        Err(e) => println!("[init] affinity failed: {:?}", e),
+    }
+
+    // Two threads summing halves of 1..=100, each keeping its partial sum
+    // in a thread-local slot and adding it to TOTAL under the mutex
+    let halves = [thread::spawn(sum_range, 1), thread::spawn(sum_range, 51)];
+    for handle in halves {
+        if let Err(e) = handle.and_then(|h| h.join()) {
+            println!("[init] thread failed: {:?}", e);
+        }
+    }
+    println!("[init] threads say 1 + .. + 100 = {}", *TOTAL.lock());
+
+    // A thread serving an endpoint; the kernel tells it who each
+    // message is from
+    match ipc::Endpoint::new() {
+        Ok(endpoint) => {
+            let server = thread::spawn(serve, endpoint.handle());
+            for (i, msg) in ["ping", "hello", "bye"].iter().enumerate() {
+                if let Err(e) = endpoint.send([i, msg.len(), 0], msg.as_bytes()) {
+                    println!("[init] send failed: {:?}", e);
+                }
+            }
+            if let Err(e) = server.and_then(|h| h.join()) {
+                println!("[init] server failed: {:?}", e);
+            }
+        }
+        Err(e) => println!("[init] no endpoint: {:?}", e),
+    }
+
+    match time::realtime() {
+        Ok(now) => println!("[init] {} s since the epoch, up {} ms", now.as_secs(), time::uptime().as_millis()),
+        Err(e) => println!("[init] no wall clock: {:?}", e),
+    }
+    let start = time::monotonic();
+    thread::sleep(Duration::from_millis(50));
+    println!("[init] asked to sleep 50 ms, slept {} us", (time::monotonic() - start).as_micros());
+
+    // Three writes and a 10 ms timeout in one trap; the writes complete
+    // at once, the timeout once it expires
+    match ring::Ring::new() {
+        Ok(mut ring) => {
+            for (i, line) in ["[init] ring: one\n", "[init] ring: two\n", "[init] ring: three\n"].iter().enumerate() {
+                ring.push(SYS_WRITE, [line.as_ptr() as usize, line.len(), 0, 0, 0, 0], i as u64);
+            }
+            ring.push(SYS_SLEEP, [10_000_000, 0, 0, 0, 0, 0], 99);
+            if let Err(e) = ring.enter(4) {
+                println!("[init] ring enter failed: {:?}", e);
+            }
+            while let Some(done) = ring.pop() {
+                println!("[init] ring: request {} -> {:?}", done.user_data, done.result);
+            }
+        }
+        Err(e) => println!("[init] no ring: {:?}", e),
+    }
+
+    match process::stats(None) {
+        Ok(stats) => println!(
+            "[init] {} syscalls, {} user ticks, {} kernel ticks",
+            stats.syscalls_total, stats.user_ticks, stats.kernel_ticks
+        ),
+        Err(e) => println!("[init] stats failed: {:?}", e),
+    }
+    0
+}
+
+/// Receive three messages on the endpoint behind `handle`
+fn serve(handle: usize) -> usize {
+    let endpoint = ipc::Endpoint::from_handle(handle);
+    let mut buf = [0u8; 16];
+    for _ in 0..3 {
+        match endpoint.recv(&mut buf) {
+            Ok(msg) => println!(
+                "[init] message {} from PID {} thread {}: {}",
+                msg.words[0],
+                msg.sender,
+                msg.thread,
+                core::str::from_utf8(&buf[..msg.len.min(buf.len())]).unwrap_or("?")
+            ),
+            Err(e) => println!("[init] recv failed: {:?}", e),
+        }
+    }
+    0
+}
+
+/// Sum `start..start + 50` in a thread-local accumulator, then add it to
+/// the shared total
+fn sum_range(start: usize) -> usize {
+    // Spread the halves over harts 1 and 2 (ignored if they aren't up)
+    let _ = thread::set_affinity(0, 1 << (start / 50 + 1));
+    thread::local_set(0, 0);
+    for n in start..start + 50 {
+        thread::local_set(0, thread::local_get(0) + n);
+    }
+    let sum = thread::local_get(0);
+    *TOTAL.lock() += sum;
+    sum
+}
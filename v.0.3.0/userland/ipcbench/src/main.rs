@@ -0,0 +1,131 @@
+//! Time IPC call/reply round trips
+//!
+//! `ipcbench [ROUNDS]` starts a second copy of itself as an echo server
+//! on a new endpoint, then times ROUNDS (default 1000) SYS_CALL round
+//! trips to it for a few message sizes, in CPU cycles. The server
+//! answers each call and takes the next with a single SYS_REPLY_RECV.
+//! Both stay on hart 0, so the cycle counts come from one counter.
+//!
+//! Needs the spawn capability; the console one is passed on to the
+//! server.
+
+#![no_std]
+#![no_main]
+
+use woflrt::env::{self, CAP_CONSOLE, CAP_ENDPOINT};
+use woflrt::ipc::{Endpoint, MAX_MSG_LEN, MSG_WORDS};
+use woflrt::{Error, println, process, thread, time};
+
+woflrt::entry!(main);
+
+const DEFAULT_ROUNDS: usize = 1000;
+
+/// Untimed calls before each size, to settle caches and the scheduler
+const WARMUP: usize = 16;
+
+/// Message sizes timed, in bytes
+const SIZES: [usize; 3] = [0, 64, MAX_MSG_LEN];
+
+fn main() -> i32 {
+    let mut args = env::args().skip(1);
+    let _ = thread::set_affinity(0, 1);
+    match args.next() {
+        Some("--server") => serve(),
+        arg => match arg.map(str::parse::<usize>) {
+            None => bench(DEFAULT_ROUNDS),
+            Some(Ok(rounds)) if rounds > 0 => bench(rounds),
+            _ => {
+                println!("usage: ipcbench [ROUNDS]");
+                2
+            }
+        },
+    }
+}
+
+/// Echo every call back to its caller until the endpoint closes
+fn serve() -> i32 {
+    let Some(endpoint) = env::cap(CAP_ENDPOINT).map(Endpoint::from_handle) else {
+        println!("[ipcbench] server has no endpoint");
+        return 1;
+    };
+    let mut buf = [0u8; MAX_MSG_LEN];
+    let (mut words, mut len) = ([0; MSG_WORDS], 0);
+    loop {
+        match endpoint.reply_recv(words, &mut buf, len) {
+            Ok(msg) => (words, len) = (msg.words, msg.len.min(buf.len())),
+            // The client is done
+            Err(Error::Closed) => return 0,
+            Err(e) => {
+                println!("[ipcbench] server: {:?}", e);
+                return 1;
+            }
+        }
+    }
+}
+
+/// Time `rounds` calls of each size to a fresh server
+fn bench(rounds: usize) -> i32 {
+    let endpoint = match Endpoint::new() {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            println!("[ipcbench] no endpoint: {:?}", e);
+            return 1;
+        }
+    };
+    let mut caps = [endpoint.handle(), 0];
+    let caps = match env::cap(CAP_CONSOLE) {
+        Some(console) => {
+            caps[1] = console;
+            &caps[..]
+        }
+        None => &caps[..1],
+    };
+    if let Err(e) = process::spawn("ipcbench", &["ipcbench", "--server"], &[], caps) {
+        println!("[ipcbench] can't start the server: {:?}", e);
+        return 1;
+    }
+
+    let mut buf = [0u8; MAX_MSG_LEN];
+    println!("[ipcbench] {} call/reply round trips per size", rounds);
+    for size in SIZES {
+        for _ in 0..WARMUP {
+            if let Err(e) = endpoint.call([0; MSG_WORDS], &mut buf, size) {
+                println!("[ipcbench] call failed: {:?}", e);
+                return 1;
+            }
+        }
+
+        let (mut min, mut max, mut total) = (u64::MAX, 0, 0);
+        let start = time::monotonic();
+        for i in 0..rounds {
+            let before = time::cycles();
+            let answer = endpoint.call([i, 0, 0], &mut buf, size);
+            let cycles = time::cycles() - before;
+            match answer {
+                Ok(msg) if msg.words[0] == i && msg.len == size => {}
+                Ok(msg) => {
+                    println!("[ipcbench] wrong answer: {:?}", msg);
+                    return 1;
+                }
+                Err(e) => {
+                    println!("[ipcbench] call failed: {:?}", e);
+                    return 1;
+                }
+            }
+            min = min.min(cycles);
+            max = max.max(cycles);
+            total += cycles;
+        }
+        let elapsed = time::monotonic() - start;
+        println!(
+            "[ipcbench] {:>4} bytes: min {} avg {} max {} cycles, {} ns per round trip",
+            size,
+            min,
+            total / rounds as u64,
+            max,
+            elapsed.as_nanos() / rounds as u128
+        );
+    }
+    // Exiting closes the endpoint, which ends the server
+    0
+}
@@ -0,0 +1,71 @@
+//! Run a program and print the syscalls it makes
+//!
+//! `strace PROGRAM [ARGS...]` spawns the initrd program with our console
+//! capability, waits for it and prints its trace, one line per syscall.
+//! Children it spawns are traced too. Exits with the program's exit code.
+
+#![no_std]
+#![no_main]
+
+use woflrt::env::{self, CAP_CONSOLE};
+use woflrt::process::{self, MAX_ARGS};
+use woflrt::{println, trace};
+
+woflrt::entry!(main);
+
+fn main() -> i32 {
+    let mut args = [""; MAX_ARGS];
+    let mut argc = 0;
+    for (slot, arg) in args.iter_mut().zip(env::args().skip(1)) {
+        *slot = arg;
+        argc += 1;
+    }
+    let args = &args[..argc];
+    let Some(&program) = args.first() else {
+        println!("usage: strace PROGRAM [ARGS...]");
+        return 2;
+    };
+
+    if let Err(e) = trace::set(None, trace::CHILDREN) {
+        println!("[strace] can't trace: {:?}", e);
+        return 1;
+    }
+    let caps: &[usize] = match env::cap(CAP_CONSOLE) {
+        Some(console) => &[console],
+        None => &[],
+    };
+    let code = match process::spawn(program, args, &[], caps).and_then(|pid| process::wait(Some(pid))) {
+        Ok((_, code)) => code,
+        Err(e) => {
+            println!("[strace] {}: {:?}", program, e);
+            return 1;
+        }
+    };
+
+    let mut records = [trace::Record::default(); 16];
+    let (mut next, mut lost) = (0, 0);
+    loop {
+        let n = match trace::read(next, &mut records) {
+            Ok((n, dropped)) => {
+                lost += dropped;
+                if n == 0 {
+                    break;
+                }
+                n
+            }
+            Err(e) => {
+                println!("[strace] reading the trace failed: {:?}", e);
+                break;
+            }
+        };
+        for record in &records[..n] {
+            println!("[strace] {}", record);
+            next = record.seq + 1;
+        }
+    }
+    if lost != 0 {
+        println!("[strace] {} records lost: the trace buffer filled up", lost);
+    }
+    println!("[strace] {} exited with {}", program, code);
+    code as i32
+}
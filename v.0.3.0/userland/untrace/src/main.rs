@@ -0,0 +1,26 @@
+//! Try to stop our own trace, then to make ourselves our tracer
+//!
+//! Run it traced (`strace untrace`): both attempts should fail with
+//! `PermissionDenied`, leaving the trace with its tracer. Exits with 0 if
+//! they did, 1 if either got through.
+
+#![no_std]
+#![no_main]
+
+use woflrt::{println, trace, Error};
+
+woflrt::entry!(main);
+
+fn main() -> i32 {
+    let mut code = 0;
+    for (what, flags) in [("detach", 0), ("trace ourselves", trace::ON)] {
+        match trace::set(None, flags) {
+            Err(Error::PermissionDenied) => println!("[untrace] {}: refused", what),
+            result => {
+                println!("[untrace] {}: {:?}, expected PermissionDenied", what, result);
+                code = 1;
+            }
+        }
+    }
+    code
+}
@@ -0,0 +1,126 @@
+//! Kernel data page
+//!
+//! The kernel maps a read-only page into every process with its PID,
+//! the clock parameters, the online harts and the kernel version. Reading
+//! it takes no syscall, and with the `time` CSR readable from user mode
+//! neither do the clocks in [`time`](crate::time). Everything here is
+//! `None` on a kernel that doesn't provide the page; callers fall back to
+//! the syscalls.
+//!
+//! ```ignore
+//! if let Some(version) = datapage::kernel_version() {
+//!     println!("running on {} with {} harts", version, datapage::harts().unwrap_or(1));
+//! }
+//! ```
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering, fence};
+
+use crate::env::{AT_DATA_PAGE, aux};
+use crate::process::Pid;
+use crate::time::Clock;
+
+/// Layout version we understand
+const VERSION: u64 = 1;
+
+/// `flags`: `boot_time_ns` is valid
+const REALTIME: u64 = 1 << 0;
+
+/// Layout matches the kernel's `datapage`
+#[repr(C)]
+struct DataPage {
+    version: u64,
+    seq: AtomicU64,
+    pid: u64,
+    ppid: u64,
+    timebase_hz: u64,
+    boot_ticks: u64,
+    boot_time_ns: u64,
+    flags: u64,
+    harts: AtomicU64,
+    hart_mask: AtomicU64,
+    kernel: [u8; 32],
+}
+
+/// Address of the page; 0 = not looked up yet, 1 = there is none
+static ADDR: AtomicUsize = AtomicUsize::new(0);
+
+fn page() -> Option<&'static DataPage> {
+    let addr = match ADDR.load(Ordering::Relaxed) {
+        0 => {
+            let addr = aux(AT_DATA_PAGE).filter(|&a| a != 0).unwrap_or(1);
+            ADDR.store(addr, Ordering::Relaxed);
+            addr
+        }
+        addr => addr,
+    };
+    if addr == 1 {
+        return None;
+    }
+    let page = unsafe { &*(addr as *const DataPage) };
+    (unsafe { (&raw const page.version).read_volatile() } == VERSION).then_some(page)
+}
+
+/// The `time` CSR
+pub fn ticks() -> u64 {
+    let ticks: u64;
+    unsafe { core::arch::asm!("rdtime {}", out(reg) ticks) };
+    ticks
+}
+
+/// This process' PID
+pub fn pid() -> Option<Pid> {
+    page().map(|page| page.pid as Pid)
+}
+
+/// Read the fields the kernel may change, consistently
+fn read<T>(page: &DataPage, fields: impl Fn(&DataPage) -> T) -> T {
+    loop {
+        let seq = page.seq.load(Ordering::Acquire);
+        if seq % 2 == 0 {
+            let value = fields(page);
+            fence(Ordering::Acquire);
+            if page.seq.load(Ordering::Relaxed) == seq {
+                return value;
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Number of harts online
+pub fn harts() -> Option<usize> {
+    page().map(|page| read(page, |p| p.harts.load(Ordering::Relaxed) as usize))
+}
+
+/// Bit per online hart
+pub fn hart_mask() -> Option<usize> {
+    page().map(|page| read(page, |p| p.hart_mask.load(Ordering::Relaxed) as usize))
+}
+
+/// The kernel's name and version, e.g. `woflOS 0.4.0`
+pub fn kernel_version() -> Option<&'static str> {
+    let kernel = &page()?.kernel;
+    let len = kernel.iter().position(|&b| b == 0).unwrap_or(kernel.len());
+    core::str::from_utf8(&kernel[..len]).ok()
+}
+
+/// `time` ticks to nanoseconds, rounding down like the kernel
+fn ticks_to_ns(page: &DataPage, ticks: u64) -> u64 {
+    let ns = ticks as u128 * 1_000_000_000 / page.timebase_hz as u128;
+    ns.min(u64::MAX as u128) as u64
+}
+
+/// Read `clock` without a syscall; `None` when the page can't say
+/// (no page, or no wall clock)
+pub(crate) fn clock_ns(clock: Clock) -> Option<u64> {
+    let page = page()?;
+    let now = ticks();
+    let uptime = || ticks_to_ns(page, now.saturating_sub(page.boot_ticks));
+    let boot_time = || (page.flags & REALTIME != 0).then_some(page.boot_time_ns);
+    match clock {
+        Clock::Monotonic => Some(ticks_to_ns(page, now)),
+        Clock::Realtime => boot_time().map(|boot| boot + uptime()),
+        Clock::Uptime => Some(uptime()),
+        Clock::BootTime => boot_time(),
+    }
+}
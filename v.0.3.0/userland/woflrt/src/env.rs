@@ -0,0 +1,139 @@
+//! Arguments, environment and auxiliary vector
+//!
+//! The kernel starts a program with `sp` pointing at this block:
+//!
+//! ```text
+//! sp ──► argc
+//!        argv[0] .. argv[argc-1], 0
+//!        envp[0] .. envp[envc-1], 0
+//!        auxv: (type, value) pairs, ended by (AT_NULL, 0)
+//!        capability info: AT_CAPS_COUNT × CapInfo
+//!        NUL-terminated strings
+//! ```
+//!
+//! Every word is 8 bytes and every pointer is a user address. The block
+//! lives at the top of the stack for the whole life of the process.
+
+use core::ptr;
+
+/// Auxiliary vector types
+pub const AT_NULL: usize = 0;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_ENTRY: usize = 9;
+pub const AT_PID: usize = 0x100;
+pub const AT_PPID: usize = 0x101;
+pub const AT_BOOT_TICKS: usize = 0x102;
+pub const AT_TIMEBASE_FREQ: usize = 0x103;
+pub const AT_CAPS: usize = 0x104;
+pub const AT_CAPS_COUNT: usize = 0x105;
+pub const AT_DATA_PAGE: usize = 0x106;
+
+/// Capability kinds in [`CapInfo::kind`]
+pub const CAP_CONSOLE: u32 = 1;
+pub const CAP_SPAWN: u32 = 2;
+pub const CAP_ENDPOINT: u32 = 3;
+
+/// A capability the process started with
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CapInfo {
+    /// Handle to pass to syscalls
+    pub handle: u32,
+    pub kind: u32,
+    /// Object the capability refers to (0 for kernel-wide services)
+    pub object: u64,
+}
+
+static mut ARGC: usize = 0;
+static mut ARGV: *const *const u8 = ptr::null();
+static mut ENVP: *const *const u8 = ptr::null();
+static mut AUXV: *const [usize; 2] = ptr::null();
+
+/// Record where the startup block is
+///
+/// # Safety
+/// `sp` must be the stack pointer the kernel started the process with.
+pub(crate) unsafe fn init(sp: *const usize) {
+    let argc = *sp;
+    let argv = sp.add(1) as *const *const u8;
+    let envp = argv.add(argc + 1);
+
+    let mut envc = 0;
+    while !(*envp.add(envc)).is_null() {
+        envc += 1;
+    }
+
+    ARGC = argc;
+    ARGV = argv;
+    ENVP = envp;
+    AUXV = envp.add(envc + 1) as *const [usize; 2];
+}
+
+/// The program's arguments; the first is its name
+pub fn args() -> impl Iterator<Item = &'static str> {
+    let (argv, argc) = unsafe { (ARGV, ARGC) };
+    (0..argc).map(move |i| unsafe { cstr(*argv.add(i)) })
+}
+
+/// The environment as `(key, value)` pairs
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    let envp = unsafe { ENVP };
+    (0..)
+        .map(move |i| unsafe { envp.add(i).read() })
+        .take_while(|p| !p.is_null())
+        .map(|p| {
+            let var = unsafe { cstr(p) };
+            var.split_once('=').unwrap_or((var, ""))
+        })
+}
+
+/// Value of environment variable `key`
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find(|&(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// Value of auxiliary vector entry `kind`
+pub fn aux(kind: usize) -> Option<usize> {
+    let mut entry = unsafe { AUXV };
+    loop {
+        let [k, v] = unsafe { entry.read() };
+        match k {
+            AT_NULL => return None,
+            _ if k == kind => return Some(v),
+            _ => entry = unsafe { entry.add(1) },
+        }
+    }
+}
+
+/// This process's PID
+pub fn pid() -> usize {
+    aux(AT_PID).unwrap_or(0)
+}
+
+/// The parent's PID, if the process has a parent
+pub fn ppid() -> Option<usize> {
+    aux(AT_PPID).filter(|&p| p != 0)
+}
+
+/// The capabilities this process started with
+pub fn caps() -> &'static [CapInfo] {
+    match (aux(AT_CAPS), aux(AT_CAPS_COUNT)) {
+        (Some(ptr), Some(len)) if len > 0 => unsafe { core::slice::from_raw_parts(ptr as *const CapInfo, len) },
+        _ => &[],
+    }
+}
+
+/// Handle of the first startup capability of `kind`
+pub fn cap(kind: u32) -> Option<usize> {
+    caps().iter().find(|c| c.kind == kind).map(|c| c.handle as usize)
+}
+
+/// Borrow a NUL-terminated string from the startup block
+unsafe fn cstr(p: *const u8) -> &'static str {
+    let mut len = 0;
+    while *p.add(len) != 0 {
+        len += 1;
+    }
+    // The kernel only passes on what the parent gave it; don't trust it
+    core::str::from_utf8(core::slice::from_raw_parts(p, len)).unwrap_or("")
+}
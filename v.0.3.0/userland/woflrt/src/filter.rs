@@ -0,0 +1,121 @@
+//! Syscall filters
+//!
+//! A filter lists the syscalls a process may make, optionally with
+//! conditions on their arguments. Filters only ever tighten: installing
+//! one keeps the old one in force too, and children inherit it.
+//!
+//! ```ignore
+//! use woflrt::filter::{Action, Filter, Op};
+//! use woflrt::syscall::{SYS_EXIT, SYS_WRITE};
+//!
+//! // Write at most 64 bytes at a time, and exit; anything else kills us
+//! Filter::new(Action::Kill)
+//!     .allow(SYS_EXIT)
+//!     .allow_if(SYS_WRITE, 1, Op::Le, 64)
+//!     .install()?;
+//! ```
+
+use crate::{Error, Result};
+use crate::syscall::{SYS_FILTER, check, syscall1};
+
+/// Most rules the kernel accepts in one filter
+pub const MAX_RULES: usize = 32;
+
+/// What happens to a syscall the filter doesn't allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Action {
+    /// It fails with [`Error::PermissionDenied`]
+    Deny = 0,
+    /// The process is killed, as by a fault (exception 8, the syscall
+    /// number as the address)
+    Kill = 1,
+}
+
+/// How an argument is compared with a rule's value (unsigned)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Op {
+    Eq = 1,
+    Ne = 2,
+    Lt = 3,
+    Le = 4,
+    Gt = 5,
+    Ge = 6,
+    /// None of the value's bits set in the argument
+    MaskClear = 7,
+}
+
+/// One rule as the kernel reads it
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Rule {
+    num: usize,
+    /// 0 = no condition, else an `Op`
+    op: usize,
+    arg: usize,
+    value: usize,
+}
+
+/// An allowlist of syscalls
+///
+/// Every condition on one syscall must hold.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    action: Action,
+    count: usize,
+    rules: *const Rule,
+    table: [Rule; MAX_RULES],
+    /// More rules were added than fit
+    overflow: bool,
+}
+
+impl Filter {
+    /// A filter that allows nothing yet
+    pub const fn new(action: Action) -> Self {
+        Filter {
+            action,
+            count: 0,
+            rules: core::ptr::null(),
+            table: [Rule { num: 0, op: 0, arg: 0, value: 0 }; MAX_RULES],
+            overflow: false,
+        }
+    }
+
+    fn push(mut self, rule: Rule) -> Self {
+        match self.table.get_mut(self.count) {
+            Some(slot) => {
+                *slot = rule;
+                self.count += 1;
+            }
+            None => self.overflow = true,
+        }
+        self
+    }
+
+    /// Allow syscall `num`
+    pub fn allow(self, num: usize) -> Self {
+        self.push(Rule { num, op: 0, arg: 0, value: 0 })
+    }
+
+    /// Allow syscall `num` when argument `arg` (0-5) compares with `value`
+    pub fn allow_if(self, num: usize, arg: usize, op: Op, value: usize) -> Self {
+        self.push(Rule { num, op: op as usize, arg, value })
+    }
+
+    /// The block the kernel reads, pointing at our own rules
+    pub(crate) fn as_raw(&mut self) -> Result<*const Filter> {
+        if self.overflow {
+            return Err(Error::TooBig);
+        }
+        self.rules = self.table.as_ptr();
+        Ok(self as *const Filter)
+    }
+
+    /// Restrict the calling process (every thread) by this filter
+    pub fn install(mut self) -> Result<()> {
+        let raw = self.as_raw()?;
+        check(unsafe { syscall1(SYS_FILTER, raw as usize) }).map(drop)
+    }
+}
@@ -0,0 +1,120 @@
+//! Console input and output
+
+use core::fmt;
+
+use crate::Result;
+use crate::syscall::{SYS_CONSOLE_MODE, SYS_PUTC, SYS_READ, SYS_WRITE, check, syscall1, syscall2};
+
+/// Console mode bits (see [`set_mode`])
+///
+/// Cooked input arrives a line at a time, after the kernel has handled
+/// backspace; raw input arrives byte by byte. Echo writes typed
+/// characters back to the console.
+pub const COOKED: usize = 1 << 0;
+pub const ECHO: usize = 1 << 1;
+
+/// Write one byte to the serial console
+pub fn putc(byte: u8) -> Result<()> {
+    check(unsafe { syscall1(SYS_PUTC, byte as usize) }).map(|_| ())
+}
+
+/// Write bytes to the serial console in one syscall; returns how many
+///
+/// The kernel takes at most 128 bytes at a time, so this may write fewer
+/// than `buf.len()`.
+pub fn write(buf: &[u8]) -> Result<usize> {
+    check(unsafe { syscall2(SYS_WRITE, buf.as_ptr() as usize, buf.len()) })
+}
+
+/// Write a string to the serial console
+pub fn puts(s: &str) -> Result<()> {
+    let mut rest = s.as_bytes();
+    while !rest.is_empty() {
+        rest = &rest[write(rest)?..];
+    }
+    Ok(())
+}
+
+/// Read console input into `buf`, blocking until there is some; returns
+/// how many bytes
+///
+/// In cooked mode a read returns at most one line, newline included.
+pub fn read(buf: &mut [u8]) -> Result<usize> {
+    check(unsafe { syscall2(SYS_READ, buf.as_mut_ptr() as usize, buf.len()) })
+}
+
+/// The console mode bits
+pub fn mode() -> Result<usize> {
+    check(unsafe { syscall2(SYS_CONSOLE_MODE, 0, 0) })
+}
+
+/// Set the mode bits in `mask` to their values in `mode`; returns the
+/// previous mode
+///
+/// `set_mode(COOKED | ECHO, 0)` gives raw input without echo, as a line
+/// editor would want.
+pub fn set_mode(mask: usize, mode: usize) -> Result<usize> {
+    check(unsafe { syscall2(SYS_CONSOLE_MODE, mask, mode) })
+}
+
+/// `fmt::Write` adapter over the console
+pub struct Console;
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        puts(s).map_err(|_| fmt::Error)
+    }
+}
+
+/// Collects formatted output so a `print!` usually costs one syscall
+struct Buffered {
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl Buffered {
+    fn flush(&mut self) -> fmt::Result {
+        if self.len > 0 {
+            write(&self.buf[..self.len]).map_err(|_| fmt::Error)?;
+            self.len = 0;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for Buffered {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            if self.len == self.buf.len() {
+                self.flush()?;
+            }
+            let n = bytes.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use fmt::Write;
+    let mut out = Buffered { buf: [0; 256], len: 0 };
+    let _ = out.write_fmt(args).and_then(|()| out.flush());
+}
+
+/// Print to the console (no newline)
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print(core::format_args!($($arg)*)));
+}
+
+/// Print to the console (with newline)
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($fmt:expr) => ($crate::print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => ($crate::print!(concat!($fmt, "\n"), $($arg)*));
+}
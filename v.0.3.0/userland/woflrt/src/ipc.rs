@@ -0,0 +1,137 @@
+//! Message passing
+//!
+//! An [`Endpoint`] is a rendezvous point: [`Endpoint::send`] blocks until
+//! somebody receives the message, [`Endpoint::recv`] until somebody sends
+//! one. A message is [`MSG_WORDS`] words plus an optional buffer; the
+//! receiver also learns which process and thread sent it.
+//!
+//! ```ignore
+//! let endpoint = Endpoint::new()?;
+//! // Grant it to a child; it finds it with env::cap(env::CAP_ENDPOINT)
+//! process::spawn("server", &["server"], &[], &[endpoint.handle()])?;
+//! endpoint.send([1, 0, 0], b"hello")?;
+//! ```
+//!
+//! For request/response, a client uses [`Endpoint::call`], which sends
+//! and waits for the answer in one trap, and a server loops on
+//! [`Endpoint::reply_recv`], which answers the last call and waits for
+//! the next in one trap:
+//!
+//! ```ignore
+//! let (mut words, mut len) = ([0; MSG_WORDS], 0);
+//! loop {
+//!     let msg = endpoint.reply_recv(words, &mut buf, len)?;
+//!     (words, len) = handle(msg, &mut buf);
+//! }
+//! ```
+//!
+//! An endpoint belongs to the process that created it; when that process
+//! exits, everyone using it fails with [`Error::Closed`](crate::Error), as
+//! does a caller whose server exits without answering.
+//!
+//! The remote calls are reserved and return `Err(Error::Unsupported)`.
+
+use crate::{Error, Result};
+use crate::process::Pid;
+use crate::syscall::{
+    SYS_CALL, SYS_ENDPOINT, SYS_NODE_DISCOVER, SYS_RECV, SYS_RECV_REMOTE, SYS_REPLY_RECV, SYS_SEND, SYS_SEND_REMOTE,
+    check, syscall, syscall0, syscall3, syscall_regs,
+};
+
+/// Words a message carries besides its buffer
+pub const MSG_WORDS: usize = 3;
+
+/// Longest message buffer the kernel accepts
+pub const MAX_MSG_LEN: usize = 1024;
+
+/// A capability to an IPC endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Endpoint(usize);
+
+/// A received message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    /// Length the sender gave; more than the buffer passed to
+    /// [`Endpoint::recv`] means the rest was dropped
+    pub len: usize,
+    /// Sending process and thread, as the kernel knows them
+    pub sender: Pid,
+    pub thread: Pid,
+    pub words: [usize; MSG_WORDS],
+}
+
+impl Endpoint {
+    /// Create an endpoint belonging to this process
+    pub fn new() -> Result<Endpoint> {
+        check(unsafe { syscall0(SYS_ENDPOINT) }).map(Endpoint)
+    }
+
+    /// The endpoint behind capability `handle` (see [`env::cap`](crate::env::cap))
+    pub const fn from_handle(handle: usize) -> Endpoint {
+        Endpoint(handle)
+    }
+
+    /// Capability handle, for granting it to a child
+    pub fn handle(&self) -> usize {
+        self.0
+    }
+
+    /// Send `words` and `msg`, blocking until they are received
+    pub fn send(&self, words: [usize; MSG_WORDS], msg: &[u8]) -> Result<()> {
+        let [w0, w1, w2] = words;
+        let (ret, _) = unsafe { syscall(SYS_SEND, [self.0, msg.as_ptr() as usize, msg.len(), w0, w1, w2]) };
+        check(ret).map(drop)
+    }
+
+    /// Receive a message into `buf`, blocking until one comes
+    ///
+    /// If it was sent with [`call`](Endpoint::call), this thread answers
+    /// it with its next [`reply_recv`](Endpoint::reply_recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<Message> {
+        exchange(SYS_RECV, [self.0, buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0])
+    }
+
+    /// Send `words` and `buf[..len]`, then block for the answer, which
+    /// is received into `buf`
+    pub fn call(&self, words: [usize; MSG_WORDS], buf: &mut [u8], len: usize) -> Result<Message> {
+        exchange(SYS_CALL, exchange_args(self.0, words, buf, len)?)
+    }
+
+    /// Answer the last call this thread received with `words` and
+    /// `buf[..len]` (if there is one to answer), then receive the next
+    /// message into `buf`
+    pub fn reply_recv(&self, words: [usize; MSG_WORDS], buf: &mut [u8], len: usize) -> Result<Message> {
+        exchange(SYS_REPLY_RECV, exchange_args(self.0, words, buf, len)?)
+    }
+}
+
+/// Arguments for a call that sends `buf[..len]` and receives into `buf`
+fn exchange_args(handle: usize, words: [usize; MSG_WORDS], buf: &mut [u8], len: usize) -> Result<[usize; 6]> {
+    if len > buf.len() {
+        return Err(Error::InvalidArgument);
+    }
+    let [w0, w1, w2] = words;
+    // Length going out in the low half, room coming back in the high
+    Ok([handle, buf.as_mut_ptr() as usize, len | buf.len() << 32, w0, w1, w2])
+}
+
+/// Issue a syscall that returns a message in a0-a5
+fn exchange(num: usize, args: [usize; 6]) -> Result<Message> {
+    let [len, sender, thread, w0, w1, w2] = unsafe { syscall_regs(num, args) };
+    Ok(Message { len: check(len)?, sender, thread, words: [w0, w1, w2] })
+}
+
+/// Send `msg` to an endpoint on another node
+pub fn send_remote(endpoint: usize, msg: &[u8]) -> Result<()> {
+    check(unsafe { syscall3(SYS_SEND_REMOTE, endpoint, msg.as_ptr() as usize, msg.len()) }).map(|_| ())
+}
+
+/// Receive a message from another node into `buf`; returns its length
+pub fn recv_remote(endpoint: usize, buf: &mut [u8]) -> Result<usize> {
+    check(unsafe { syscall3(SYS_RECV_REMOTE, endpoint, buf.as_mut_ptr() as usize, buf.len()) })
+}
+
+/// Discover other nodes; returns how many were found
+pub fn node_discover() -> Result<usize> {
+    check(unsafe { syscall0(SYS_NODE_DISCOVER) })
+}
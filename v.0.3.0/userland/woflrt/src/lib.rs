@@ -0,0 +1,142 @@
+//! woflOS user runtime
+//!
+//! Everything a `no_std` user program needs to run on woflOS:
+//!
+//! - `_start`, which records the startup block (see [`env`]), calls your
+//!   `main` and exits with its return value
+//! - a panic handler that reports the panic and exits with code 101
+//! - `print!` / `println!` on the serial console
+//! - typed wrappers for every syscall, returning [`Result`]
+//! - threads with `tp`-based thread-local slots
+//! - futexes and a [`sync::Mutex`] built on them
+//! - monotonic and wall-clock time, read without trapping through the
+//!   kernel data page
+//! - syscall tracing of this process or its children
+//! - syscall filters that only ever tighten
+//! - submission/completion rings for batching syscalls
+//! - synchronous message passing over IPC endpoints
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use woflrt::println;
+//!
+//! woflrt::entry!(main);
+//!
+//! fn main() -> i32 {
+//!     println!("hello from userspace");
+//!     0
+//! }
+//! ```
+
+#![no_std]
+
+pub mod datapage;
+pub mod env;
+pub mod filter;
+pub mod io;
+pub mod ipc;
+pub mod process;
+pub mod ring;
+pub mod sync;
+pub mod syscall;
+pub mod thread;
+pub mod time;
+pub mod trace;
+
+use core::panic::PanicInfo;
+
+/// Why a syscall failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Missing capability, or the target isn't ours (`EPERM`)
+    PermissionDenied,
+    /// No such program (`ENOENT`)
+    NotFound,
+    /// No such process or thread (`ESRCH`)
+    NoSuchProcess,
+    /// Too many arguments, environment strings or capabilities (`E2BIG`)
+    TooBig,
+    /// The program isn't a loadable executable (`ENOEXEC`)
+    BadExecutable,
+    /// No such child to wait for (`ECHILD`)
+    NoChild,
+    /// Out of a shared resource for now (`EAGAIN`)
+    TryAgain,
+    /// The kernel ran out of memory (`ENOMEM`)
+    OutOfMemory,
+    /// A pointer we passed is unmapped or misaligned (`EFAULT`)
+    BadAddress,
+    /// An argument is out of range (`EINVAL`)
+    InvalidArgument,
+    /// The IPC endpoint was closed by its owner (`EPIPE`)
+    Closed,
+    /// The kernel doesn't implement this syscall (`ENOSYS`)
+    Unsupported,
+    /// The process' memory (frame) limit is used up
+    FrameLimit,
+    /// The process has as many threads as its limit allows
+    ThreadLimit,
+    /// The process has as many children as its limit allows
+    ChildLimit,
+    /// The child can't hold that many capabilities
+    CapLimit,
+    /// An IPC queue is as deep as its limit allows
+    IpcDepthLimit,
+    /// An error number this runtime doesn't know
+    Other(usize),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Declare the program's `main` (`fn() -> i32`)
+///
+/// The return value becomes the process exit code.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[no_mangle]
+        fn __woflrt_main() -> i32 {
+            let main: fn() -> i32 = $main;
+            main()
+        }
+    };
+}
+
+extern "Rust" {
+    fn __woflrt_main() -> i32;
+}
+
+// Process entry point. The kernel starts us with sp pointing at argc
+// and every other register zeroed.
+core::arch::global_asm!(
+    r#"
+.section .text.entry
+.global _start
+_start:
+    li fp, 0
+    li ra, 0
+    mv a0, sp
+    call {start}
+"#,
+    start = sym start,
+);
+
+extern "C" fn start(sp: *const usize) -> ! {
+    unsafe {
+        env::init(sp);
+        thread::init_main();
+    }
+    let code = unsafe { __woflrt_main() };
+    process::exit(code as usize)
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    println!("[PANIC] {}", info.message());
+    if let Some(loc) = info.location() {
+        println!("[PANIC] at {}:{}", loc.file(), loc.line());
+    }
+    process::exit(101)
+}
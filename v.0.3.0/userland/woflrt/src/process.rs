@@ -0,0 +1,228 @@
+//! Process lifecycle and accounting
+
+use core::time::Duration;
+
+use crate::{Error, Result};
+use crate::filter::Filter;
+use crate::syscall::{
+    SYS_EXIT, SYS_GETPID, SYS_PROC_STATS, SYS_SPAWN, SYS_SUPERVISE, SYS_WAIT, SYS_YIELD, check, syscall, syscall0,
+    syscall1, syscall2,
+};
+
+pub type Pid = usize;
+
+/// Syscalls numbered below this get their own counter in [`ProcStats`]
+pub const SYSCALL_SLOTS: usize = 64;
+
+/// CPU and syscall accounting for one process
+///
+/// Layout matches the kernel's `process::stats::ProcStats`. Times are in
+/// timebase ticks.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ProcStats {
+    pub user_ticks: u64,
+    pub kernel_ticks: u64,
+    pub context_switches: u64,
+    pub page_faults: u64,
+    pub syscalls_total: u64,
+    /// Syscalls numbered >= SYSCALL_SLOTS
+    pub syscalls_other: u64,
+    /// Per-syscall counts, indexed by syscall number
+    pub syscalls: [u64; SYSCALL_SLOTS],
+}
+
+/// Most arguments / environment strings a child can be given
+pub const MAX_ARGS: usize = 32;
+pub const MAX_ENV: usize = 32;
+
+/// A string as the kernel reads it: pointer and length
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Str {
+    ptr: *const u8,
+    len: usize,
+}
+
+/// SYS_SPAWN request block
+#[repr(C)]
+struct SpawnRequest {
+    path: Str,
+    argv: *const Str,
+    argc: usize,
+    envp: *const Str,
+    envc: usize,
+    caps: *const usize,
+    ncaps: usize,
+    limits: *const Limits,
+    filter: *const Filter,
+}
+
+/// Resource limits for a child
+///
+/// A zero field keeps the parent's value, and the kernel never lets a
+/// child exceed its parent's limits.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Memory frames, page tables included
+    pub frames: usize,
+    /// Threads, the main thread included
+    pub threads: usize,
+    /// Child processes, exited but unreaped ones included
+    pub children: usize,
+    /// Capabilities
+    pub caps: usize,
+    /// Senders that may wait on one of our IPC endpoints
+    pub ipc_depth: usize,
+    /// Percentage of CPU time (1-100)
+    pub cpu_percent: usize,
+}
+
+/// Start the initrd program `path` as a child of this process
+///
+/// `args` should start with the program name. `env` holds `KEY=value`
+/// strings. `caps` lists our own capability handles to grant the child;
+/// it receives them as handles 0, 1, ... in that order. Needs the spawn
+/// capability. The child inherits our resource limits.
+pub fn spawn(path: &str, args: &[&str], env: &[&str], caps: &[usize]) -> Result<Pid> {
+    spawn_inner(path, args, env, caps, None, None)
+}
+
+/// [`spawn`], with tighter resource limits for the child
+pub fn spawn_with_limits(path: &str, args: &[&str], env: &[&str], caps: &[usize], limits: &Limits) -> Result<Pid> {
+    spawn_inner(path, args, env, caps, Some(limits), None)
+}
+
+/// [`spawn`], with the child's syscalls restricted by `filter` (on top of
+/// any filter we are under ourselves)
+pub fn spawn_with_filter(path: &str, args: &[&str], env: &[&str], caps: &[usize], filter: &Filter) -> Result<Pid> {
+    spawn_inner(path, args, env, caps, None, Some(filter))
+}
+
+fn spawn_inner(
+    path: &str,
+    args: &[&str],
+    env: &[&str],
+    caps: &[usize],
+    limits: Option<&Limits>,
+    filter: Option<&Filter>,
+) -> Result<Pid> {
+    if args.len() > MAX_ARGS || env.len() > MAX_ENV {
+        return Err(Error::TooBig);
+    }
+    let mut filter = filter.copied();
+    let filter = match filter.as_mut() {
+        Some(filter) => filter.as_raw()?,
+        None => core::ptr::null(),
+    };
+
+    let str_of = |s: &&str| Str { ptr: s.as_ptr(), len: s.len() };
+    let mut argv = [Str { ptr: core::ptr::null(), len: 0 }; MAX_ARGS];
+    let mut envp = [Str { ptr: core::ptr::null(), len: 0 }; MAX_ENV];
+    argv.iter_mut().zip(args).for_each(|(slot, s)| *slot = str_of(s));
+    envp.iter_mut().zip(env).for_each(|(slot, s)| *slot = str_of(s));
+
+    let request = SpawnRequest {
+        path: str_of(&path),
+        argv: argv.as_ptr(),
+        argc: args.len(),
+        envp: envp.as_ptr(),
+        envc: env.len(),
+        caps: caps.as_ptr(),
+        ncaps: caps.len(),
+        limits: limits.map_or(core::ptr::null(), |l| l as *const Limits),
+        filter,
+    };
+    check(unsafe { syscall1(SYS_SPAWN, &request as *const SpawnRequest as usize) })
+}
+
+/// Terminate the calling process
+pub fn exit(code: usize) -> ! {
+    unsafe { syscall1(SYS_EXIT, code) };
+    unreachable!("SYS_EXIT returned")
+}
+
+/// The calling process' PID, as the kernel sees it (the same in every
+/// thread)
+pub fn id() -> Pid {
+    crate::datapage::pid().unwrap_or_else(|| unsafe { syscall0(SYS_GETPID) })
+}
+
+/// Give up the CPU to the next runnable process
+pub fn yield_now() {
+    unsafe { syscall0(SYS_YIELD) };
+}
+
+/// Block until a child exits; returns its PID and exit code
+///
+/// `pid` selects one child, `None` waits for any.
+pub fn wait(pid: Option<Pid>) -> Result<(Pid, usize)> {
+    let (child, code) = unsafe { syscall(SYS_WAIT, [pid.unwrap_or(0), 0, 0, 0, 0, 0]) };
+    check(child).map(|child| (child, code))
+}
+
+/// Why a child ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// It exited with this code
+    Exited(usize),
+    /// It raised exception `cause` at `pc` (`addr` is the faulting
+    /// address where there is one); its exit code reads as 128 + cause
+    Faulted { cause: usize, pc: usize, addr: usize },
+}
+
+/// A reaped child
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitStatus {
+    pub pid: Pid,
+    pub reason: ExitReason,
+    /// The replacement its supervisor started, if any
+    pub restarted_as: Option<Pid>,
+}
+
+/// [`wait`], reporting why the child ended and whether it was restarted
+pub fn wait_status(pid: Option<Pid>) -> Result<ExitStatus> {
+    // reason, code, cause, pc, addr, restarted_as
+    let mut info = [0usize; 6];
+    let (child, _) = unsafe { syscall(SYS_WAIT, [pid.unwrap_or(0), info.as_mut_ptr() as usize, 0, 0, 0, 0]) };
+    let pid = check(child)?;
+    let [kind, code, cause, pc, addr, restarted_as] = info;
+    let reason = match kind {
+        0 => ExitReason::Exited(code),
+        _ => ExitReason::Faulted { cause, pc, addr },
+    };
+    Ok(ExitStatus { pid, reason, restarted_as: (restarted_as != 0).then_some(restarted_as) })
+}
+
+/// When a supervised child gets restarted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum RestartPolicy {
+    Never = 0,
+    Always = 1,
+    /// After a fault or a non-zero exit code
+    OnFailure = 2,
+}
+
+/// Have the kernel restart our child `pid` according to `policy`
+///
+/// At most `max_restarts` restarts (0 = no limit) happen within any
+/// `window` (`None` = the child's whole lifetime); after that it stays
+/// dead. Each death is still reported to [`wait`] / [`wait_status`].
+pub fn supervise(pid: Pid, policy: RestartPolicy, max_restarts: usize, window: Option<Duration>) -> Result<()> {
+    let window_ms = window.map_or(0, |w| w.as_millis().clamp(1, usize::MAX as u128) as usize);
+    let args = [pid, policy as usize, max_restarts, window_ms, 0, 0];
+    check(unsafe { syscall(SYS_SUPERVISE, args).0 }).map(drop)
+}
+
+/// Accounting data for `pid` (`None` = the caller)
+///
+/// `pid` must be one of our threads or a descendant; others fail with
+/// [`Error::PermissionDenied`](crate::Error).
+pub fn stats(pid: Option<Pid>) -> Result<ProcStats> {
+    let mut stats = core::mem::MaybeUninit::<ProcStats>::uninit();
+    let ret = unsafe { syscall2(SYS_PROC_STATS, pid.unwrap_or(0), stats.as_mut_ptr() as usize) };
+    check(ret)?;
+    Ok(unsafe { stats.assume_init() })
+}
@@ -0,0 +1,139 @@
+//! Submission/completion rings
+//!
+//! Batch syscalls through a page shared with the kernel instead of
+//! trapping for each one: [`Ring::push`] queues requests, one
+//! [`Ring::enter`] runs them all, and [`Ring::pop`] collects the
+//! results. A `SYS_SLEEP` request is a timeout: it completes once its
+//! time is up, while the program does other work. IPC requests complete
+//! when the other side of the endpoint turns up, a `SYS_CALL` once its
+//! answer comes; [`Completion::message`] has what one received.
+//!
+//! ```ignore
+//! let mut ring = Ring::new()?;
+//! ring.push(SYS_WRITE, [msg.as_ptr() as usize, msg.len(), 0, 0, 0, 0], 1);
+//! ring.push(SYS_SLEEP, [1_000_000, 0, 0, 0, 0, 0], 2);
+//! ring.enter(2)?;
+//! while let Some(done) = ring.pop() {
+//!     println!("{} -> {:?}", done.user_data, done.result);
+//! }
+//! ```
+//!
+//! Other requests that would block the caller itself (waits, joins,
+//! reads, futex waits) or end it fail with
+//! [`Error::InvalidArgument`](crate::Error).
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::Result;
+use crate::ipc::Message;
+use crate::syscall::{SYS_RING_ENTER, SYS_RING_SETUP, check, syscall0, syscall2};
+
+/// Queue sizes; layout matches the kernel's `ring`
+pub const SQ_ENTRIES: usize = 32;
+pub const CQ_ENTRIES: usize = 32;
+
+const SQ_OFFSET: usize = 64;
+const CQ_OFFSET: usize = SQ_OFFSET + SQ_ENTRIES * core::mem::size_of::<Sqe>();
+
+#[repr(C)]
+struct Header {
+    sq_head: AtomicU32,
+    sq_tail: AtomicU32,
+    cq_head: AtomicU32,
+    cq_tail: AtomicU32,
+    sq_entries: AtomicU32,
+    cq_entries: AtomicU32,
+    cq_overflow: AtomicU32,
+}
+
+#[repr(C)]
+struct Sqe {
+    num: u64,
+    user_data: u64,
+    args: [u64; 6],
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    result: u64,
+    regs: [u64; 5],
+}
+
+/// A finished request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Completion {
+    /// What the request was pushed with
+    pub user_data: u64,
+    pub result: Result<usize>,
+    /// a1-a5 as the syscall left them
+    pub registers: [usize; 5],
+}
+
+impl Completion {
+    /// The message a `SYS_RECV`, `SYS_CALL` or `SYS_REPLY_RECV` request
+    /// received
+    pub fn message(&self) -> Result<Message> {
+        let [sender, thread, w0, w1, w2] = self.registers;
+        Ok(Message { len: self.result?, sender, thread, words: [w0, w1, w2] })
+    }
+}
+
+/// The process' ring
+pub struct Ring {
+    base: usize,
+}
+
+impl Ring {
+    /// Set up the ring (the same one on every call)
+    pub fn new() -> Result<Ring> {
+        let base = check(unsafe { syscall0(SYS_RING_SETUP) })?;
+        Ok(Ring { base })
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.base as *const Header) }
+    }
+
+    /// Queue syscall `num` with `args`; false if the queue is full
+    pub fn push(&mut self, num: usize, args: [usize; 6], user_data: u64) -> bool {
+        let header = self.header();
+        let tail = header.sq_tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(header.sq_head.load(Ordering::Acquire)) as usize >= SQ_ENTRIES {
+            return false;
+        }
+        let sqe = Sqe { num: num as u64, user_data, args: args.map(|arg| arg as u64) };
+        let slot = tail as usize % SQ_ENTRIES;
+        unsafe { ((self.base + SQ_OFFSET) as *mut Sqe).add(slot).write_volatile(sqe) };
+        header.sq_tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Run everything queued, then block until at least `min_complete`
+    /// results are waiting; returns how many are
+    pub fn enter(&mut self, min_complete: usize) -> Result<usize> {
+        check(unsafe { syscall2(SYS_RING_ENTER, SQ_ENTRIES, min_complete) })
+    }
+
+    /// Take the oldest result, if any
+    pub fn pop(&mut self) -> Option<Completion> {
+        let header = self.header();
+        let head = header.cq_head.load(Ordering::Relaxed);
+        if header.cq_tail.load(Ordering::Acquire) == head {
+            return None;
+        }
+        let slot = head as usize % CQ_ENTRIES;
+        let cqe = unsafe { ((self.base + CQ_OFFSET) as *const Cqe).add(slot).read_volatile() };
+        header.cq_head.store(head.wrapping_add(1), Ordering::Release);
+        Some(Completion {
+            user_data: cqe.user_data,
+            result: check(cqe.result as usize),
+            registers: cqe.regs.map(|reg| reg as usize),
+        })
+    }
+
+    /// Results the kernel had to drop because the queue was full
+    pub fn overflow(&self) -> usize {
+        self.header().cq_overflow.load(Ordering::Relaxed) as usize
+    }
+}
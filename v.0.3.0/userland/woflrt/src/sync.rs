@@ -0,0 +1,115 @@
+//! Futexes and a mutex built on them
+//!
+//! A futex is a 32-bit atomic in user memory. Threads (or processes
+//! sharing the page) sleep on it with [`futex_wait`] while it holds an
+//! expected value and are woken with [`futex_wake`]; the uncontended
+//! paths never enter the kernel.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use crate::Result;
+use crate::syscall::{SYS_FUTEX_WAIT, SYS_FUTEX_WAKE, check, syscall2, syscall3};
+
+/// How a [`futex_wait`] ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexWait {
+    /// A [`futex_wake`] on the same word woke us
+    Woken,
+    /// The word no longer held the expected value; we didn't sleep
+    ValueChanged,
+    /// The timeout passed first
+    TimedOut,
+}
+
+/// Sleep while `word` holds `expected`, for at most `timeout` if given
+///
+/// Wakeups can be spurious as far as the caller's condition goes:
+/// always re-check it.
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> Result<FutexWait> {
+    // 0 means "no timeout" to the kernel; the shortest real one is 1ns
+    let timeout = timeout.map_or(0, |d| d.as_nanos().clamp(1, u64::MAX as u128) as usize);
+    let ret = unsafe { syscall3(SYS_FUTEX_WAIT, word.as_ptr() as usize, expected as usize, timeout) };
+    Ok(match check(ret)? {
+        0 => FutexWait::Woken,
+        1 => FutexWait::ValueChanged,
+        _ => FutexWait::TimedOut,
+    })
+}
+
+/// Wake up to `count` threads sleeping on `word`; returns how many woke
+pub fn futex_wake(word: &AtomicU32, count: usize) -> Result<usize> {
+    check(unsafe { syscall2(SYS_FUTEX_WAKE, word.as_ptr() as usize, count) })
+}
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// Locked, and somebody may be asleep waiting for it
+const CONTENDED: u32 = 2;
+
+/// Mutual exclusion between threads, sleeping in the kernel when contended
+pub struct Mutex<T> {
+    state: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex { state: AtomicU32::new(UNLOCKED), value: UnsafeCell::new(value) }
+    }
+
+    /// Take the lock, sleeping until it is free
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            // Mark it contended so the holder knows to wake us
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                let _ = futex_wait(&self.state, CONTENDED, None);
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// Take the lock if it is free
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            let _ = futex_wake(&self.state, 1);
+        }
+    }
+}
+
+/// Holds a [`Mutex`] locked until dropped
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
@@ -0,0 +1,194 @@
+//! Raw syscall interface
+//!
+//! Numbers mirror the kernel's `src/syscall/mod.rs`; keep them in sync.
+//! Convention: number in a7, arguments in a0-a5, results in a0 (and a1;
+//! the IPC receives use a0-a5).
+//! A failing call returns `-errno` in a0; [`check`] turns that into an
+//! [`Error`].
+
+use core::arch::asm;
+
+use crate::{Error, Result};
+
+/// Layer 1 core syscalls
+pub const SYS_TEST: usize = 0;
+pub const SYS_PUTC: usize = 1;
+pub const SYS_EXIT: usize = 2;
+pub const SYS_GETPID: usize = 3;
+pub const SYS_YIELD: usize = 4;
+
+/// IPC
+pub const SYS_SEND: usize = 10;
+pub const SYS_RECV: usize = 11;
+pub const SYS_ENDPOINT: usize = 12;
+pub const SYS_CALL: usize = 13;
+pub const SYS_REPLY_RECV: usize = 14;
+
+/// Process management
+pub const SYS_WAIT: usize = 20;
+pub const SYS_PROC_STATS: usize = 21;
+pub const SYS_SPAWN: usize = 22;
+pub const SYS_THREAD_CREATE: usize = 23;
+pub const SYS_THREAD_EXIT: usize = 24;
+pub const SYS_THREAD_JOIN: usize = 25;
+pub const SYS_SUPERVISE: usize = 26;
+pub const SYS_AFFINITY: usize = 27;
+pub const SYS_FILTER: usize = 28;
+
+/// Time
+pub const SYS_SLEEP: usize = 30;
+pub const SYS_CLOCK_GET: usize = 31;
+
+/// Synchronization
+pub const SYS_FUTEX_WAIT: usize = 40;
+pub const SYS_FUTEX_WAKE: usize = 41;
+pub const SYS_RING_SETUP: usize = 42;
+pub const SYS_RING_ENTER: usize = 43;
+
+/// Console
+pub const SYS_WRITE: usize = 50;
+pub const SYS_READ: usize = 51;
+pub const SYS_CONSOLE_MODE: usize = 52;
+
+/// Debugging
+pub const SYS_TRACE: usize = 60;
+pub const SYS_TRACE_READ: usize = 61;
+
+/// Layer 6+ distributed operations
+pub const SYS_SEND_REMOTE: usize = 1000;
+pub const SYS_RECV_REMOTE: usize = 1001;
+pub const SYS_NODE_DISCOVER: usize = 1010;
+
+/// Error numbers; the kernel returns them negated in a0
+pub const EPERM: usize = 1;
+pub const ENOENT: usize = 2;
+pub const ESRCH: usize = 3;
+pub const E2BIG: usize = 7;
+pub const ENOEXEC: usize = 8;
+pub const ECHILD: usize = 10;
+pub const EAGAIN: usize = 11;
+pub const ENOMEM: usize = 12;
+pub const EFAULT: usize = 14;
+pub const EINVAL: usize = 22;
+pub const EPIPE: usize = 32;
+pub const ENOSYS: usize = 38;
+pub const ELIMIT_FRAMES: usize = 200;
+pub const ELIMIT_THREADS: usize = 201;
+pub const ELIMIT_CHILDREN: usize = 202;
+pub const ELIMIT_CAPS: usize = 203;
+pub const ELIMIT_IPC_DEPTH: usize = 204;
+
+/// Largest error number: a0 values in `-MAX_ERRNO..=-1` are errors
+pub const MAX_ERRNO: usize = 4095;
+
+/// Issue syscall `num` with up to six arguments, returning (a0, a1)
+///
+/// # Safety
+/// Arguments that the kernel treats as pointers must be valid for the
+/// access that syscall performs.
+#[inline(always)]
+pub unsafe fn syscall(num: usize, args: [usize; 6]) -> (usize, usize) {
+    let a0: usize;
+    let a1: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") args[0] => a0,
+        inlateout("a1") args[1] => a1,
+        in("a2") args[2],
+        in("a3") args[3],
+        in("a4") args[4],
+        in("a5") args[5],
+        in("a7") num,
+        options(nostack),
+    );
+    (a0, a1)
+}
+
+/// Issue syscall `num` with up to six arguments, returning a0-a5
+///
+/// # Safety
+/// See [`syscall`].
+#[inline(always)]
+pub unsafe fn syscall_regs(num: usize, args: [usize; 6]) -> [usize; 6] {
+    let mut regs = args;
+    asm!(
+        "ecall",
+        inlateout("a0") regs[0],
+        inlateout("a1") regs[1],
+        inlateout("a2") regs[2],
+        inlateout("a3") regs[3],
+        inlateout("a4") regs[4],
+        inlateout("a5") regs[5],
+        in("a7") num,
+        options(nostack),
+    );
+    regs
+}
+
+/// Syscall with no arguments
+///
+/// # Safety
+/// See [`syscall`].
+#[inline(always)]
+pub unsafe fn syscall0(num: usize) -> usize {
+    syscall(num, [0; 6]).0
+}
+
+/// Syscall with one argument
+///
+/// # Safety
+/// See [`syscall`].
+#[inline(always)]
+pub unsafe fn syscall1(num: usize, a0: usize) -> usize {
+    syscall(num, [a0, 0, 0, 0, 0, 0]).0
+}
+
+/// Syscall with two arguments
+///
+/// # Safety
+/// See [`syscall`].
+#[inline(always)]
+pub unsafe fn syscall2(num: usize, a0: usize, a1: usize) -> usize {
+    syscall(num, [a0, a1, 0, 0, 0, 0]).0
+}
+
+/// Syscall with three arguments
+///
+/// # Safety
+/// See [`syscall`].
+#[inline(always)]
+pub unsafe fn syscall3(num: usize, a0: usize, a1: usize, a2: usize) -> usize {
+    syscall(num, [a0, a1, a2, 0, 0, 0]).0
+}
+
+/// Turn a raw a0 return value into a `Result`
+pub fn check(ret: usize) -> Result<usize> {
+    if ret < MAX_ERRNO.wrapping_neg() {
+        return Ok(ret);
+    }
+    Err(match ret.wrapping_neg() {
+        EPERM => Error::PermissionDenied,
+        ENOENT => Error::NotFound,
+        ESRCH => Error::NoSuchProcess,
+        E2BIG => Error::TooBig,
+        ENOEXEC => Error::BadExecutable,
+        ECHILD => Error::NoChild,
+        EAGAIN => Error::TryAgain,
+        ENOMEM => Error::OutOfMemory,
+        EFAULT => Error::BadAddress,
+        EINVAL => Error::InvalidArgument,
+        EPIPE => Error::Closed,
+        ENOSYS => Error::Unsupported,
+        ELIMIT_FRAMES => Error::FrameLimit,
+        ELIMIT_THREADS => Error::ThreadLimit,
+        ELIMIT_CHILDREN => Error::ChildLimit,
+        ELIMIT_CAPS => Error::CapLimit,
+        ELIMIT_IPC_DEPTH => Error::IpcDepthLimit,
+        errno => Error::Other(errno),
+    })
+}
+
+/// SYS_TEST: round-trip into the kernel, which answers 42
+pub fn test() -> usize {
+    unsafe { syscall0(SYS_TEST) }
+}
@@ -0,0 +1,155 @@
+//! Threads and thread-local storage
+//!
+//! Threads share the process' memory and capabilities. The kernel gives
+//! each one its own stack; the runtime gives each one a control block
+//! from a fixed table and points `tp` at it, which is how
+//! [`local_get`] / [`local_set`] find the calling thread's slots.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use crate::syscall::{
+    SYS_AFFINITY, SYS_SLEEP, SYS_THREAD_CREATE, SYS_THREAD_EXIT, SYS_THREAD_JOIN, check, syscall1, syscall2, syscall3,
+};
+use crate::{Error, Result};
+
+/// Most threads the runtime tracks, including the main thread
+pub const MAX_THREADS: usize = 16;
+
+/// Thread-local word slots per thread
+pub const LOCAL_SLOTS: usize = 8;
+
+pub type Tid = usize;
+
+/// Per-thread control block; `tp` points at the running thread's block
+#[repr(C)]
+struct ThreadBlock {
+    in_use: AtomicBool,
+    func: Option<fn(usize) -> usize>,
+    arg: usize,
+    locals: [usize; LOCAL_SLOTS],
+}
+
+impl ThreadBlock {
+    const fn new() -> Self {
+        ThreadBlock { in_use: AtomicBool::new(false), func: None, arg: 0, locals: [0; LOCAL_SLOTS] }
+    }
+}
+
+static mut BLOCKS: [ThreadBlock; MAX_THREADS] = [const { ThreadBlock::new() }; MAX_THREADS];
+
+/// A running thread that can be joined
+#[must_use = "dropping a JoinHandle leaks the thread's slot until exit"]
+pub struct JoinHandle {
+    tid: Tid,
+    block: usize,
+}
+
+impl JoinHandle {
+    /// Kernel thread ID
+    pub fn tid(&self) -> Tid {
+        self.tid
+    }
+
+    /// Wait for the thread to finish; returns what its function returned
+    pub fn join(self) -> Result<usize> {
+        let value = check(unsafe { syscall1(SYS_THREAD_JOIN, self.tid) })?;
+        block(self.block).in_use.store(false, Ordering::Release);
+        Ok(value)
+    }
+}
+
+/// Run `func(arg)` on a new thread
+pub fn spawn(func: fn(usize) -> usize, arg: usize) -> Result<JoinHandle> {
+    let index = (1..MAX_THREADS)
+        .find(|&i| block(i).in_use.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok())
+        .ok_or(Error::ThreadLimit)?;
+
+    let tb = block(index);
+    tb.func = Some(func);
+    tb.arg = arg;
+    tb.locals = [0; LOCAL_SLOTS];
+
+    let tp = tb as *mut ThreadBlock as usize;
+    let ret = unsafe { syscall3(SYS_THREAD_CREATE, thread_start as *const () as usize, tp, tp) };
+    match check(ret) {
+        Ok(tid) => Ok(JoinHandle { tid, block: index }),
+        Err(e) => {
+            tb.in_use.store(false, Ordering::Release);
+            Err(e)
+        }
+    }
+}
+
+/// End the calling thread, handing `value` to its joiner
+///
+/// On the main thread this exits the whole process.
+pub fn exit(value: usize) -> ! {
+    unsafe { syscall1(SYS_THREAD_EXIT, value) };
+    unreachable!("SYS_THREAD_EXIT returned")
+}
+
+/// Block the calling thread for at least `duration`
+///
+/// A zero duration just yields.
+pub fn sleep(duration: Duration) {
+    let ns = duration.as_nanos().min(u64::MAX as u128) as usize;
+    unsafe { syscall1(SYS_SLEEP, ns) };
+}
+
+/// Which harts thread `tid` (0 = the calling thread) may run on, one
+/// bit per hart ID
+pub fn affinity(tid: Tid) -> Result<usize> {
+    check(unsafe { syscall2(SYS_AFFINITY, tid, 0) })
+}
+
+/// Let thread `tid` (0 = the calling thread) run only on the harts in
+/// `mask`; returns its previous mask
+///
+/// Also works on the main thread of a child process. Fails if `mask`
+/// names no running hart.
+pub fn set_affinity(tid: Tid, mask: usize) -> Result<usize> {
+    if mask == 0 {
+        return Err(Error::InvalidArgument);
+    }
+    check(unsafe { syscall2(SYS_AFFINITY, tid, mask) })
+}
+
+/// Read thread-local slot `slot` of the calling thread
+pub fn local_get(slot: usize) -> usize {
+    unsafe { (*current()).locals[slot] }
+}
+
+/// Write thread-local slot `slot` of the calling thread
+pub fn local_set(slot: usize, value: usize) {
+    unsafe { (*current()).locals[slot] = value }
+}
+
+/// Give the main thread block 0
+///
+/// # Safety
+/// Call once, from `_start`, before any other thread exists.
+pub(crate) unsafe fn init_main() {
+    let main = block(0);
+    main.in_use.store(true, Ordering::Relaxed);
+    asm!("mv tp, {}", in(reg) main as *mut ThreadBlock);
+}
+
+/// Where new threads start: a0 = tp = their control block
+extern "C" fn thread_start(tb: *mut ThreadBlock) -> ! {
+    let (func, arg) = unsafe { ((*tb).func, (*tb).arg) };
+    let value = func.map_or(0, |f| f(arg));
+    exit(value)
+}
+
+fn current() -> *mut ThreadBlock {
+    let tp: usize;
+    unsafe { asm!("mv {}, tp", out(reg) tp) };
+    tp as *mut ThreadBlock
+}
+
+fn block(index: usize) -> &'static mut ThreadBlock {
+    let blocks = &raw mut BLOCKS;
+    unsafe { &mut (*blocks)[index] }
+}
@@ -0,0 +1,63 @@
+//! Clocks
+//!
+//! Every clock reads as a [`Duration`] since its own starting point.
+//! Wall-clock times count from 1970-01-01 00:00:00 UTC and need an RTC;
+//! without one they fail.
+
+use core::time::Duration;
+
+use crate::Result;
+use crate::syscall::{SYS_CLOCK_GET, check, syscall1};
+
+/// Clocks the kernel can read (numbering matches its `time::CLOCK_*`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Clock {
+    /// Never goes backwards; starts at an arbitrary point before boot
+    Monotonic = 0,
+    /// Wall-clock time since the Unix epoch
+    Realtime = 1,
+    /// Time since the kernel booted
+    Uptime = 2,
+    /// Wall-clock time at which the kernel booted
+    BootTime = 3,
+}
+
+/// Read `clock`
+///
+/// Served from the kernel data page when there is one, else by syscall.
+pub fn now(clock: Clock) -> Result<Duration> {
+    if let Some(ns) = crate::datapage::clock_ns(clock) {
+        return Ok(Duration::from_nanos(ns));
+    }
+    let ns = check(unsafe { syscall1(SYS_CLOCK_GET, clock as usize) })?;
+    Ok(Duration::from_nanos(ns as u64))
+}
+
+/// Monotonic time, for measuring intervals
+pub fn monotonic() -> Duration {
+    now(Clock::Monotonic).unwrap_or_default()
+}
+
+/// Time since the kernel booted
+pub fn uptime() -> Duration {
+    now(Clock::Uptime).unwrap_or_default()
+}
+
+/// Wall-clock time since the Unix epoch
+pub fn realtime() -> Result<Duration> {
+    now(Clock::Realtime)
+}
+
+/// Wall-clock time at which the kernel booted
+pub fn boot_time() -> Result<Duration> {
+    now(Clock::BootTime)
+}
+
+/// The `cycle` CSR: CPU cycles on this hart, for timing short stretches
+/// of code (the count differs between harts)
+pub fn cycles() -> u64 {
+    let cycles: u64;
+    unsafe { core::arch::asm!("rdcycle {}", out(reg) cycles) };
+    cycles
+}
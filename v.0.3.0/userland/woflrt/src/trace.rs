@@ -0,0 +1,142 @@
+//! Syscall tracing
+//!
+//! The kernel records every syscall a traced process makes into a trace
+//! buffer; the tracer reads its records back with [`read`]. A process may
+//! trace itself or its children:
+//!
+//! ```ignore
+//! trace::set(None, trace::CHILDREN)?;
+//! let pid = process::spawn("echo", &["echo", "hi"], &[], &[])?;
+//! process::wait(Some(pid))?;
+//! let mut records = [trace::Record::default(); 32];
+//! for record in &records[..trace::read(0, &mut records)?] {
+//!     println!("{}", record);
+//! }
+//! ```
+
+use core::fmt;
+
+use crate::Result;
+use crate::process::Pid;
+use crate::syscall::*;
+
+/// Tracing flags (see [`set`])
+///
+/// `ON` records the target's own syscalls, `CHILDREN` has children it
+/// spawns from then on traced too, and `PRINT` also has the kernel print
+/// each call on the console.
+pub const ON: usize = 1 << 0;
+pub const CHILDREN: usize = 1 << 1;
+pub const PRINT: usize = 1 << 2;
+
+/// One traced syscall
+///
+/// Layout matches the kernel's `syscall::trace::TraceRecord`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Record {
+    /// Position in the trace; pass the last one seen + 1 to [`read`]
+    pub seq: u64,
+    /// Thread that made the call
+    pub pid: u64,
+    pub num: u64,
+    pub args: [u64; 6],
+    /// Raw a0 on return; see [`Record::result`]
+    pub ret: u64,
+    /// Nanoseconds since boot when the call was made
+    pub start_ns: u64,
+    pub duration_ns: u64,
+}
+
+impl Record {
+    /// The call's result, errors decoded
+    pub fn result(&self) -> Result<usize> {
+        check(self.ret as usize)
+    }
+}
+
+/// Name and argument count of each syscall, for printing records
+const SYSCALLS: &[(usize, &str, usize)] = &[
+    (SYS_TEST, "test", 0),
+    (SYS_PUTC, "putc", 1),
+    (SYS_EXIT, "exit", 1),
+    (SYS_GETPID, "getpid", 0),
+    (SYS_YIELD, "yield", 0),
+    (SYS_SEND, "send", 6),
+    (SYS_RECV, "recv", 3),
+    (SYS_ENDPOINT, "endpoint", 0),
+    (SYS_CALL, "call", 6),
+    (SYS_REPLY_RECV, "reply_recv", 6),
+    (SYS_WAIT, "wait", 2),
+    (SYS_PROC_STATS, "proc_stats", 2),
+    (SYS_SPAWN, "spawn", 1),
+    (SYS_THREAD_CREATE, "thread_create", 3),
+    (SYS_THREAD_EXIT, "thread_exit", 1),
+    (SYS_THREAD_JOIN, "thread_join", 1),
+    (SYS_SUPERVISE, "supervise", 4),
+    (SYS_AFFINITY, "affinity", 2),
+    (SYS_FILTER, "filter", 1),
+    (SYS_SLEEP, "sleep", 1),
+    (SYS_CLOCK_GET, "clock_get", 1),
+    (SYS_FUTEX_WAIT, "futex_wait", 3),
+    (SYS_FUTEX_WAKE, "futex_wake", 2),
+    (SYS_RING_SETUP, "ring_setup", 0),
+    (SYS_RING_ENTER, "ring_enter", 2),
+    (SYS_WRITE, "write", 2),
+    (SYS_READ, "read", 2),
+    (SYS_CONSOLE_MODE, "console_mode", 2),
+    (SYS_TRACE, "trace", 2),
+    (SYS_TRACE_READ, "trace_read", 3),
+    (SYS_SEND_REMOTE, "send_remote", 3),
+    (SYS_RECV_REMOTE, "recv_remote", 3),
+    (SYS_NODE_DISCOVER, "node_discover", 0),
+];
+
+/// `[5] write(0x10a2c, 12) = 12 <31 us>`
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let known = SYSCALLS.iter().find(|&&(num, _, _)| num as u64 == self.num);
+        match known {
+            Some((_, name, _)) => write!(f, "[{}] {}(", self.pid, name)?,
+            None => write!(f, "[{}] syscall_{}(", self.pid, self.num)?,
+        }
+        let nargs = known.map_or(6, |&(_, _, nargs)| nargs);
+        for (i, arg) in self.args[..nargs].iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            // Small values are counts and flags; big ones are addresses
+            if *arg < 0x1000 {
+                write!(f, "{}{}", sep, arg)?;
+            } else {
+                write!(f, "{}{:#x}", sep, arg)?;
+            }
+        }
+        match self.result() {
+            Ok(value) => write!(f, ") = {}", value)?,
+            Err(e) => write!(f, ") = {:?}", e)?,
+        }
+        write!(f, " <{} us>", self.duration_ns / 1000)
+    }
+}
+
+/// Set how `pid` (`None` = the caller) is traced; returns the previous
+/// flags
+///
+/// `pid` must be the caller or one of its children, and the caller
+/// becomes its tracer. `0` stops tracing. A process someone else traces
+/// is theirs to change: anybody else, the process itself included, gets
+/// [`Error::PermissionDenied`](crate::Error).
+pub fn set(pid: Option<Pid>, flags: usize) -> Result<usize> {
+    check(unsafe { syscall2(SYS_TRACE, pid.unwrap_or(0), flags) })
+}
+
+/// Copy our trace records, from sequence number `from` on, into `buf`;
+/// returns how many, and how many were lost since the last read
+///
+/// Records the kernel has already overwritten are skipped. The ones we
+/// hadn't read by then are the lost ones: the trace buffer holds 256
+/// records for all tracers together.
+pub fn read(from: u64, buf: &mut [Record]) -> Result<(usize, u64)> {
+    let args = [from as usize, buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0];
+    let (n, lost) = unsafe { syscall(SYS_TRACE_READ, args) };
+    Ok((check(n)?, lost as u64))
+}